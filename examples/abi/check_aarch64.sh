@@ -0,0 +1,50 @@
+# Builds aarch64_structs.chl as an object for linux-arm64,
+# and checks that the extern functions are declared with their AAPCS64 signatures in the emitted LLVM IR.
+# Usage: examples/abi/check_aarch64.sh [path/to/chili]
+
+chili=${1:-target/debug/chili}
+dir=$(dirname "$0")
+out=$(mktemp -d)
+status=0
+
+check() {
+    if ! grep -Eq "$2" "$1"; then
+        echo "$1: expected a declaration matching: $2"
+        status=1
+    fi
+}
+
+target=linux-arm64
+ll="$out/$target.ll"
+
+if ! "$chili" "$dir/aarch64_structs.chl" --output-kind object --target $target --emit-llvm-ir --output "$out/$target.o"; then
+    echo "$target: failed to build"
+    rm -rf "$out"
+    exit 1
+fi
+
+if test ! -f "$out/$target.o"; then
+    echo "$target: no object file was emitted"
+    status=1
+fi
+
+# HFAs are passed and returned as an array of their float members
+check $ll 'declare \[2 x float\] @take_vec2\(\[2 x float\]\)'
+check $ll 'declare \[4 x double\] @take_vec4\(\[4 x double\]\)'
+
+# composites of up to 16 bytes are passed as `i64` or `[2 x i64]`,
+# and returned as an integer of their exact size when they fit in 8 bytes
+check $ll 'declare i24 @take_odd\(i64\)'
+check $ll 'declare i64 @take_small\(i64\)'
+check $ll 'declare \[2 x i64\] @take_pair\(\[2 x i64\]\)'
+
+# larger composites are passed by reference, and returned through an `sret` pointer
+check $ll 'declare void @take_big\([^,]*\* [^,]*sret[^,]*, [^,]*\*[^,]*\)'
+
+rm -rf "$out"
+
+if test $status = 0; then
+    echo "aarch64 ABI check passed"
+fi
+
+exit $status
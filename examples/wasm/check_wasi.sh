@@ -0,0 +1,41 @@
+# Builds hello_wasi.chl for wasi-wasm32, and checks the structure of the output module:
+# it must validate, export the `_start` entry from wasi-libc, and define the `__main_argc_argv`
+# function which `_start` calls. The module is also run if `wasmtime` is installed.
+# Requires wasm-ld, wasi-libc and the WebAssembly Binary Toolkit (wabt).
+# Usage: examples/wasm/check_wasi.sh [path/to/chili]
+
+chili=${1:-target/debug/chili}
+dir=$(dirname "$0")
+out=$(mktemp -d)
+wasm="$out/hello_wasi.wasm"
+status=0
+
+fail() {
+    echo "$1"
+    status=1
+}
+
+if ! "$chili" "$dir/hello_wasi.chl" --output-kind executable --target wasi-wasm32 --emit-llvm-ir --output "$out/hello_wasi"; then
+    echo "failed to build"
+    rm -rf "$out"
+    exit 1
+fi
+
+wasm-validate "$wasm" || fail "the module is invalid"
+
+wasm-objdump -x -j Export "$wasm" | grep -q '"_start"' || fail "the module doesn't export \`_start\`"
+
+grep -Eq '^define .*@__main_argc_argv\(' "$out/hello_wasi.ll" || fail "\`__main_argc_argv\` isn't defined"
+
+if command -v wasmtime > /dev/null; then
+    expected=$(printf 'hello from wasi 1\nhello from wasi 2\nhello from wasi 3')
+    test "$(wasmtime "$wasm")" = "$expected" || fail "the module printed unexpected output"
+fi
+
+rm -rf "$out"
+
+if test $status = 0; then
+    echo "wasi-wasm32 check passed"
+fi
+
+exit $status
@@ -43,25 +43,7 @@ impl Module {
         self.bindings
             .iter()
             .enumerate()
-            .find(|(_, binding)| match &binding.kind {
-                BindingKind::Let { pattern, .. } => pattern.iter().any(|pattern| pattern.name == name),
-                BindingKind::Function {
-                    name: NameAndSpan { name: binding_name, .. },
-                    ..
-                }
-                | BindingKind::ExternFunction {
-                    name: NameAndSpan { name: binding_name, .. },
-                    ..
-                }
-                | BindingKind::ExternVariable {
-                    name: NameAndSpan { name: binding_name, .. },
-                    ..
-                }
-                | BindingKind::Type {
-                    name: NameAndSpan { name: binding_name, .. },
-                    ..
-                } => *binding_name == name,
-            })
+            .find(|(_, binding)| binding.binds_name(name))
     }
 }
 
@@ -546,6 +528,28 @@ impl Binding {
         }
     }
 
+    pub fn binds_name(&self, name: Ustr) -> bool {
+        match &self.kind {
+            BindingKind::Let { pattern, .. } => pattern.iter().any(|pattern| pattern.name == name),
+            BindingKind::Function {
+                name: NameAndSpan { name: binding_name, .. },
+                ..
+            }
+            | BindingKind::ExternFunction {
+                name: NameAndSpan { name: binding_name, .. },
+                ..
+            }
+            | BindingKind::ExternVariable {
+                name: NameAndSpan { name: binding_name, .. },
+                ..
+            }
+            | BindingKind::Type {
+                name: NameAndSpan { name: binding_name, .. },
+                ..
+            } => *binding_name == name,
+        }
+    }
+
     pub fn pattern_span(&self) -> Span {
         match &self.kind {
             BindingKind::Let { pattern, .. } => pattern.span(),
@@ -1,12 +1,18 @@
 use crate::{
     ast,
-    parse::{spawn_parser, ParserCache, ParserResult},
-    workspace::Workspace,
+    error::{DiagnosticResult, Diagnostics},
+    parse::{parse_injected_bindings, parse_repl_input, spawn_parser, ParserCache, ParserResult, ReplItem},
+    span::FileId,
+    workspace::{ModulePath, Workspace},
 };
 use parking_lot::Mutex;
 use std::{
     collections::HashSet,
-    sync::{mpsc::channel, Arc},
+    mem,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
 };
 use threadpool::ThreadPool;
 
@@ -62,16 +68,7 @@ fn generate_ast_inner(workspace: &mut Workspace) -> (Vec<ast::Module>, AstGenera
         workspace.std_library().as_module_path(),
     );
 
-    for result in rx.iter() {
-        match *result {
-            ParserResult::NewModule(module) => modules.push(module),
-            ParserResult::AlreadyParsed | ParserResult::ParserFailed => (),
-            ParserResult::LexerFailed(module, diag) => {
-                modules.push(module);
-                cache.lock().diagnostics.push(diag);
-            }
-        }
-    }
+    collect_parsed_modules(&rx, &cache, &mut modules);
 
     thread_pool.join();
 
@@ -86,3 +83,100 @@ fn generate_ast_inner(workspace: &mut Workspace) -> (Vec<ast::Module>, AstGenera
         },
     )
 }
+
+/// Parses a single repl input, along with the modules it imports that weren't parsed yet.
+/// Newly parsed modules are added to the workspace.
+pub fn generate_repl_ast(
+    workspace: &mut Workspace,
+    module_path: ModulePath,
+    name: String,
+    source: &str,
+) -> Option<(Vec<ReplItem>, Vec<ast::Module>)> {
+    let (result, modules) = generate_source_ast(workspace, name, source, |thread_pool, tx, cache, file_id| {
+        parse_repl_input(thread_pool, tx, cache, module_path, file_id, source)
+    });
+
+    match result {
+        Ok(items) => Some((items, modules)),
+        Err(diag) => {
+            workspace.diagnostics.push(diag);
+            None
+        }
+    }
+}
+
+/// Parses source code injected by compile-time code into the module at `module_path`,
+/// along with the modules it imports that weren't parsed yet.
+/// Newly parsed modules are added to the workspace.
+pub fn generate_injected_ast(
+    workspace: &mut Workspace,
+    module_path: ModulePath,
+    name: String,
+    source: &str,
+) -> DiagnosticResult<(ast::Module, Vec<ast::Module>)> {
+    let (result, modules) = generate_source_ast(workspace, name, source, |thread_pool, tx, cache, file_id| {
+        parse_injected_bindings(thread_pool, tx, cache, module_path, file_id, source)
+    });
+
+    result.map(|module| (module, modules))
+}
+
+// Parses source code that doesn't exist on disk, as a file called `name`
+fn generate_source_ast<T>(
+    workspace: &mut Workspace,
+    name: String,
+    source: &str,
+    parse: impl FnOnce(ThreadPool, Sender<Box<ParserResult>>, Arc<Mutex<ParserCache>>, FileId) -> DiagnosticResult<T>,
+) -> (DiagnosticResult<T>, Vec<ast::Module>) {
+    let mut modules: Vec<ast::Module> = vec![];
+
+    let cache = Arc::new(Mutex::new(ParserCache {
+        libraries: workspace.library_map(),
+        include_paths: workspace.build_options.include_paths.clone(),
+        diagnostics: mem::replace(&mut workspace.diagnostics, Diagnostics::new()),
+        parsed_files: workspace
+            .module_infos
+            .iter()
+            .map(|(_, module_info)| module_info.file_path)
+            .collect(),
+        total_lines: 0,
+    }));
+
+    let file_id = cache.lock().diagnostics.add_file(name, source.to_string());
+
+    let thread_pool = ThreadPool::new(num_cpus::get());
+    let (tx, rx) = channel::<Box<ParserResult>>();
+
+    let result = parse(thread_pool.clone(), tx, Arc::clone(&cache), file_id);
+
+    collect_parsed_modules(&rx, &cache, &mut modules);
+
+    thread_pool.join();
+
+    let cache = Arc::try_unwrap(cache).unwrap().into_inner();
+
+    workspace.diagnostics = cache.diagnostics;
+
+    for module in modules.iter_mut() {
+        module.id = workspace.module_infos.insert(module.info);
+    }
+
+    (result, modules)
+}
+
+fn collect_parsed_modules(
+    rx: &Receiver<Box<ParserResult>>,
+    cache: &Arc<Mutex<ParserCache>>,
+    modules: &mut Vec<ast::Module>,
+) {
+    for result in rx.iter() {
+        match *result {
+            ParserResult::NewModule(module) => modules.push(module),
+            ParserResult::AlreadyParsed | ParserResult::ParserFailed => (),
+            ParserResult::LexerFailed(module, diag) => {
+                modules.push(module);
+                cache.lock().diagnostics.push(diag);
+            }
+        }
+    }
+}
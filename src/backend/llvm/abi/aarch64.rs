@@ -0,0 +1,138 @@
+use super::super::traits::IsAggregateType;
+use super::{align_of, size_of, AbiFunction, AbiInfo, AbiTy};
+use inkwell::{
+    attributes::Attribute,
+    types::{AnyType, BasicTypeEnum, FloatType, FunctionType},
+};
+
+// Procedure Call Standard for the Arm 64-bit Architecture (AAPCS64).
+
+pub(super) fn get_fn<'ctx>(info: AbiInfo<'ctx>, fn_ty: FunctionType<'ctx>) -> AbiFunction<'ctx> {
+    AbiFunction {
+        params: get_params(info, fn_ty.get_param_types()),
+        ret: get_return(info, fn_ty.get_return_type().unwrap()),
+        variadic: fn_ty.is_var_arg(),
+    }
+}
+
+pub(super) fn get_params<'ctx>(info: AbiInfo<'ctx>, params: Vec<BasicTypeEnum<'ctx>>) -> Vec<AbiTy<'ctx>> {
+    params
+        .iter()
+        .map(|&param| {
+            if param.is_aggregate_type() {
+                let size = size_of(param, info.word_size);
+
+                if size == 0 {
+                    AbiTy::direct(param)
+                } else if let Some(hfa) = homogeneous_float_aggregate(info, param) {
+                    // passed in consecutive floating-point registers
+                    *AbiTy::direct(param).with_cast_to(hfa)
+                } else if size <= 16 {
+                    // passed in up to two general-purpose registers
+                    *AbiTy::direct(param).with_cast_to(composite_to_registers(info, param, size))
+                } else {
+                    // copied to memory by the caller, which passes its address instead
+                    AbiTy::indirect(param)
+                }
+            } else {
+                non_aggregate(info, param)
+            }
+        })
+        .collect()
+}
+
+pub(super) fn get_return<'ctx>(info: AbiInfo<'ctx>, ret: BasicTypeEnum<'ctx>) -> AbiTy<'ctx> {
+    if ret.is_aggregate_type() {
+        let size = size_of(ret, info.word_size);
+
+        if size == 0 {
+            AbiTy::direct(ret)
+        } else if let Some(hfa) = homogeneous_float_aggregate(info, ret) {
+            *AbiTy::direct(ret).with_cast_to(hfa)
+        } else if size <= 8 {
+            *AbiTy::direct(ret).with_cast_to(info.context.custom_width_int_type((8 * size) as u32).into())
+        } else if size <= 16 {
+            *AbiTy::direct(ret).with_cast_to(composite_to_registers(info, ret, size))
+        } else {
+            // the caller passes the result's address in the indirect result register (x8),
+            // which LLVM assigns to an `sret` parameter
+            *AbiTy::indirect(ret).with_attr(
+                info.context
+                    .create_type_attribute(Attribute::get_named_enum_kind_id("sret"), ret.as_any_type_enum()),
+            )
+        }
+    } else {
+        non_aggregate(info, ret)
+    }
+}
+
+fn non_aggregate<'ctx>(info: AbiInfo<'ctx>, ty: BasicTypeEnum<'ctx>) -> AbiTy<'ctx> {
+    let mut abi_ty = AbiTy::direct(ty);
+
+    if ty.is_int_type() && ty.into_int_type().get_bit_width() == 1 {
+        abi_ty.attr = Some(
+            info.context
+                .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0),
+        );
+    }
+
+    abi_ty
+}
+
+/// A composite of up to 16 bytes is passed as one or two 64-bit integers,
+/// or as a single 128-bit integer if it is 16-byte aligned.
+fn composite_to_registers<'ctx>(info: AbiInfo<'ctx>, ty: BasicTypeEnum<'ctx>, size: usize) -> BasicTypeEnum<'ctx> {
+    if align_of(ty, info.word_size) == 16 {
+        info.context.i128_type().into()
+    } else if size <= 8 {
+        info.context.i64_type().into()
+    } else {
+        info.context.i64_type().array_type(2).into()
+    }
+}
+
+/// Returns the type to pass `ty` as, if it is a Homogeneous Floating-point Aggregate (HFA):
+/// a composite of one to four members, all of the same floating-point type.
+fn homogeneous_float_aggregate<'ctx>(info: AbiInfo<'ctx>, ty: BasicTypeEnum<'ctx>) -> Option<BasicTypeEnum<'ctx>> {
+    let mut base: Option<FloatType<'ctx>> = None;
+    let mut count = 0;
+
+    if !flatten_float_members(ty, &mut base, &mut count) {
+        return None;
+    }
+
+    let base = base?;
+
+    // the members must be laid out without padding
+    let base_size = size_of(base.into(), info.word_size);
+
+    if (1..=4).contains(&count) && base_size * count == size_of(ty, info.word_size) {
+        Some(base.array_type(count as u32).into())
+    } else {
+        None
+    }
+}
+
+fn flatten_float_members<'ctx>(ty: BasicTypeEnum<'ctx>, base: &mut Option<FloatType<'ctx>>, count: &mut usize) -> bool {
+    match ty {
+        BasicTypeEnum::FloatType(ft) => {
+            match base {
+                Some(base) if *base != ft => return false,
+                Some(_) => (),
+                None => *base = Some(ft),
+            }
+
+            *count += 1;
+
+            *count <= 4
+        }
+        BasicTypeEnum::ArrayType(at) => {
+            (0..at.len()).all(|_| flatten_float_members(at.get_element_type(), base, count))
+        }
+        BasicTypeEnum::StructType(st) => st
+            .get_field_types()
+            .into_iter()
+            .all(|field_ty| flatten_float_members(field_ty, base, count)),
+        _ => false,
+    }
+}
@@ -1,6 +1,8 @@
+mod aarch64;
 mod amd64_system_v;
 mod amd64_win64;
 mod i386;
+mod wasm32;
 
 use crate::common::{
     mem::{bit_width_to_size, calculate_align_from_offset},
@@ -28,6 +30,11 @@ pub(super) fn get_abi_compliant_fn<'ctx>(
             Os::Linux => amd64_system_v::get_fn(info, fn_ty),
             os => unimplemented!("{}", os.name()),
         },
+        Arch::Arm64 => match &target_metrics.os {
+            Os::Linux => aarch64::get_fn(info, fn_ty),
+            os => unimplemented!("{}", os.name()),
+        },
+        Arch::Wasm32 => wasm32::get_fn(info, fn_ty),
         arch => unimplemented!("{}", arch.name()),
     }
 }
@@ -178,7 +185,6 @@ impl<'ctx> AbiTy<'ctx> {
         self
     }
 
-    #[allow(unused)]
     pub(super) fn with_align_attr<'a>(&'a mut self, attr: Attribute) -> &'a mut Self {
         self.align_attr = Some(attr);
         self
@@ -0,0 +1,107 @@
+use super::super::traits::IsAggregateType;
+use super::{align_of, size_of, AbiFunction, AbiInfo, AbiTy};
+use inkwell::{
+    attributes::Attribute,
+    types::{AnyType, BasicTypeEnum, FunctionType},
+};
+
+// The basic C ABI of WebAssembly, which clang and wasi-libc use.
+// Structs are passed and returned through memory, unless they only hold a single scalar.
+
+pub(super) fn get_fn<'ctx>(info: AbiInfo<'ctx>, fn_ty: FunctionType<'ctx>) -> AbiFunction<'ctx> {
+    AbiFunction {
+        params: get_params(info, fn_ty.get_param_types()),
+        ret: get_return(info, fn_ty.get_return_type().unwrap()),
+        variadic: fn_ty.is_var_arg(),
+    }
+}
+
+pub(super) fn get_params<'ctx>(info: AbiInfo<'ctx>, params: Vec<BasicTypeEnum<'ctx>>) -> Vec<AbiTy<'ctx>> {
+    params
+        .iter()
+        .map(|&param| {
+            if param.is_aggregate_type() {
+                if size_of(param, info.word_size) == 0 {
+                    AbiTy::direct(param)
+                } else if let Some(element) = single_element(info, param) {
+                    *AbiTy::direct(param).with_cast_to(element)
+                } else {
+                    let mut abi_ty = AbiTy::indirect(param);
+
+                    abi_ty
+                        .with_attr(info.context.create_type_attribute(
+                            Attribute::get_named_enum_kind_id("byval"),
+                            param.as_any_type_enum(),
+                        ))
+                        .with_align_attr(info.context.create_enum_attribute(
+                            Attribute::get_named_enum_kind_id("align"),
+                            align_of(param, info.word_size) as u64,
+                        ));
+
+                    abi_ty
+                }
+            } else {
+                non_aggregate(info, param)
+            }
+        })
+        .collect()
+}
+
+pub(super) fn get_return<'ctx>(info: AbiInfo<'ctx>, ret: BasicTypeEnum<'ctx>) -> AbiTy<'ctx> {
+    if ret.is_aggregate_type() {
+        if size_of(ret, info.word_size) == 0 {
+            AbiTy::direct(ret)
+        } else if let Some(element) = single_element(info, ret) {
+            *AbiTy::direct(ret).with_cast_to(element)
+        } else {
+            *AbiTy::indirect(ret).with_attr(
+                info.context
+                    .create_type_attribute(Attribute::get_named_enum_kind_id("sret"), ret.as_any_type_enum()),
+            )
+        }
+    } else {
+        non_aggregate(info, ret)
+    }
+}
+
+fn non_aggregate<'ctx>(info: AbiInfo<'ctx>, ty: BasicTypeEnum<'ctx>) -> AbiTy<'ctx> {
+    let mut abi_ty = AbiTy::direct(ty);
+
+    if ty.is_int_type() && ty.into_int_type().get_bit_width() == 1 {
+        abi_ty.attr = Some(
+            info.context
+                .create_enum_attribute(Attribute::get_named_enum_kind_id("zeroext"), 0),
+        );
+    }
+
+    abi_ty
+}
+
+/// Returns the scalar held by `ty`, if it is a struct or an array wrapping a single scalar,
+/// such as `struct { x: f64 }`
+fn single_element<'ctx>(info: AbiInfo<'ctx>, ty: BasicTypeEnum<'ctx>) -> Option<BasicTypeEnum<'ctx>> {
+    let element = match ty {
+        BasicTypeEnum::StructType(st) => {
+            let mut fields = st
+                .get_field_types()
+                .into_iter()
+                .filter(|field_ty| size_of(*field_ty, info.word_size) > 0);
+
+            match (fields.next(), fields.next()) {
+                (Some(field_ty), None) => field_ty,
+                _ => return None,
+            }
+        }
+        BasicTypeEnum::ArrayType(at) if at.len() == 1 => at.get_element_type(),
+        _ => return Some(ty),
+    };
+
+    let element = single_element(info, element)?;
+
+    // padding after the element means it doesn't cover the whole value
+    if size_of(element, info.word_size) == size_of(ty, info.word_size) {
+        Some(element)
+    } else {
+        None
+    }
+}
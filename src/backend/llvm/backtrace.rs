@@ -0,0 +1,242 @@
+use super::{
+    codegen::{FunctionState, Generator},
+    ty::IntoLlvmType,
+};
+use crate::{hir, span::Span, types::*};
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    module::Linkage,
+    types::{BasicType, StructType},
+    values::{BasicValue, FunctionValue, PointerValue, StructValue},
+    AddressSpace,
+};
+use std::collections::HashMap;
+use ustr::ustr;
+
+impl<'g, 'ctx> Generator<'g, 'ctx> {
+    /// Emits unwind tables for `function`, so that its frame can be walked through when capturing a backtrace
+    pub(super) fn add_unwind_table_attr(&self, function: FunctionValue<'ctx>) {
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context
+                .create_enum_attribute(Attribute::get_named_enum_kind_id("uwtable"), 0),
+        );
+    }
+
+    /// Starts a new block after a call, so the runtime can map the call's return address back to its line.
+    /// A function containing a block whose address is taken can't be inlined, so this is only done in debug builds
+    pub(super) fn record_call_site(&mut self, state: &mut FunctionState<'ctx>, span: Span) {
+        if !self.workspace.build_options.optimization_level.is_debug()
+            || span.is_unknown()
+            || self.current_block().get_terminator().is_some()
+        {
+            return;
+        }
+
+        let block = self
+            .context
+            .insert_basic_block_after(self.current_block(), "call_return");
+
+        self.builder.build_unconditional_branch(block);
+        self.start_block(state, block);
+
+        self.call_sites
+            .entry(state.function)
+            .or_default()
+            .push((block, span.start.line as u32));
+    }
+
+    /// Declares the `symbol_table` intrinsic.
+    /// Its body can only be generated after all other functions, see `finalize_symbol_table`
+    pub(super) fn declare_symbol_table(&mut self, function_type: &FunctionType) -> FunctionValue<'ctx> {
+        const NAME: &str = "intrinsic#symbol_table";
+
+        let function = self.declare_fn_sig(function_type, NAME, Some(Linkage::Private));
+
+        self.symbol_table = Some((function, function_type.clone()));
+
+        function
+    }
+
+    /// Generates the body of the `symbol_table` intrinsic, if it was used.
+    /// The table holds an entry for each function defined in the module, so the
+    /// runtime can tell which function a return address belongs to, and which of its calls it returns to.
+    pub(super) fn finalize_symbol_table(&mut self) {
+        let (function, function_type) = match self.symbol_table.take() {
+            Some(symbol_table) => symbol_table,
+            None => return,
+        };
+
+        let symbol_type = match function_type.return_type.as_ref() {
+            Type::Pointer(inner, _) => match inner.as_ref() {
+                Type::Slice(inner) => inner.llvm_type(self).into_struct_type(),
+                ty => panic!("unexpected symbol table type {}", ty),
+            },
+            ty => panic!("unexpected symbol table type {}", ty),
+        };
+
+        let prev_debug_location = self.take_debug_location();
+
+        let decl_block = self.context.append_basic_block(function, "decls");
+        let entry_block = self.context.append_basic_block(function, "entry");
+
+        let abi_fn = self.get_abi_compliant_fn(&function_type);
+
+        let return_ptr = if abi_fn.ret.kind.is_indirect() {
+            Some(function.get_first_param().unwrap().into_pointer_value())
+        } else {
+            None
+        };
+
+        let mut state = FunctionState::new(
+            self.workspace.build_options.runtime_checks,
+            *self.workspace.get_root_module_info(),
+            function,
+            function_type,
+            return_ptr,
+            decl_block,
+            entry_block,
+        );
+
+        self.start_block(&mut state, entry_block);
+
+        let chili_functions: HashMap<FunctionValue<'ctx>, hir::FunctionId> =
+            self.functions.iter().map(|(id, function)| (*function, *id)).collect();
+
+        let defined_functions: Vec<FunctionValue<'ctx>> = self
+            .module
+            .get_functions()
+            .filter(|f| f.count_basic_blocks() > 0)
+            .collect();
+
+        let call_site_type = call_site_type(symbol_type);
+
+        let mut symbols: Vec<StructValue<'ctx>> = vec![];
+
+        for function_value in defined_functions {
+            let address = function_value
+                .as_global_value()
+                .as_pointer_value()
+                .const_to_int(self.ptr_sized_int_type);
+
+            // functions which didn't come from Chili code, like the entry point, have no location.
+            // the runtime uses this to tell where the program's own frames end.
+            let (name, file, line) = match chili_functions.get(&function_value) {
+                Some(id) => {
+                    let function = self.cache.functions.get(*id).unwrap();
+                    let module_info = self.workspace.module_infos.get(function.module_id).unwrap();
+
+                    (
+                        function.qualified_name,
+                        module_info.file_path,
+                        function.span.start.line as u64,
+                    )
+                }
+                None => (ustr(&function_value.get_name().to_string_lossy()), ustr(""), 0),
+            };
+
+            let name = self.const_str_slice("", name);
+            let file = self.const_str_slice("", file);
+            let call_sites = self.const_call_sites(call_site_type, function_value);
+
+            symbols.push(symbol_type.const_named_struct(&[
+                address.into(),
+                name.into(),
+                file.into(),
+                self.context.i32_type().const_int(line, false).into(),
+                call_sites.into(),
+            ]));
+        }
+
+        let symbols_type = symbol_type.array_type(symbols.len() as u32);
+        let symbols_global = self.module.add_global(symbols_type, None, "symbol_table");
+        symbols_global.set_linkage(Linkage::Private);
+        symbols_global.set_constant(true);
+        symbols_global.set_initializer(&symbol_type.const_array(&symbols));
+
+        let symbols_ptr = symbols_global
+            .as_pointer_value()
+            .const_cast(symbol_type.ptr_type(AddressSpace::Generic));
+
+        let len = self.ptr_sized_int_type.const_int(symbols.len() as u64, false);
+
+        // a slice pointer points to the slice's data pointer and length
+        let slice = self.const_slice(symbols_ptr, len);
+        let slice_global = self.module.add_global(slice.get_type(), None, "");
+        slice_global.set_initializer(&slice);
+
+        let slice = slice_global.as_pointer_value().as_basic_value_enum();
+
+        self.gen_return(&mut state, Some(slice));
+
+        self.start_block(&mut state, decl_block);
+        self.builder.build_unconditional_branch(entry_block);
+
+        self.restore_debug_location(prev_debug_location);
+    }
+
+    // Generates the `*[]CallSite` of `function`, in the order its blocks are laid out.
+    // Since blocks are emitted in order in debug builds, this sorts them by address
+    fn const_call_sites(
+        &mut self,
+        call_site_type: StructType<'ctx>,
+        function: FunctionValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let call_sites = self.call_sites.remove(&function).unwrap_or_default();
+
+        let entries: Vec<StructValue<'ctx>> = function
+            .get_basic_blocks()
+            .into_iter()
+            .filter_map(|block| {
+                let &(_, line) = call_sites.iter().find(|(call_block, _)| *call_block == block)?;
+
+                let address = block.get_address().unwrap().const_to_int(self.ptr_sized_int_type);
+
+                Some(call_site_type.const_named_struct(&[
+                    address.into(),
+                    self.context.i32_type().const_int(line as u64, false).into(),
+                ]))
+            })
+            .collect();
+
+        let entries_ptr = if entries.is_empty() {
+            call_site_type.ptr_type(AddressSpace::Generic).const_null()
+        } else {
+            let entries_global =
+                self.module
+                    .add_global(call_site_type.array_type(entries.len() as u32), None, "call_sites");
+            entries_global.set_linkage(Linkage::Private);
+            entries_global.set_constant(true);
+            entries_global.set_initializer(&call_site_type.const_array(&entries));
+
+            entries_global
+                .as_pointer_value()
+                .const_cast(call_site_type.ptr_type(AddressSpace::Generic))
+        };
+
+        let len = self.ptr_sized_int_type.const_int(entries.len() as u64, false);
+
+        let slice = self.const_slice(entries_ptr, len);
+        let slice_global = self.module.add_global(slice.get_type(), None, "");
+        slice_global.set_initializer(&slice);
+
+        slice_global.as_pointer_value()
+    }
+}
+
+// Returns the llvm type of `CallSite`, through the type of `Symbol.call_sites`
+fn call_site_type<'ctx>(symbol_type: StructType<'ctx>) -> StructType<'ctx> {
+    let call_sites_slice = symbol_type
+        .get_field_type_at_index(4)
+        .unwrap()
+        .into_pointer_type()
+        .get_element_type()
+        .into_struct_type();
+
+    call_sites_slice
+        .get_field_type_at_index(0)
+        .unwrap()
+        .into_pointer_type()
+        .get_element_type()
+        .into_struct_type()
+}
@@ -1,10 +1,15 @@
 use super::{
     abi::{align_of, size_of},
+    debug_info::{DebugInfo, DebugScope},
     traits::IsALoadInst,
 };
 use crate::{
     ast::ExternLibrary,
-    common::{build_options, scopes::Scopes, target::TargetMetrics},
+    common::{
+        build_options::{self, RuntimeChecks},
+        scopes::Scopes,
+        target::TargetMetrics,
+    },
     hir,
     infer::type_ctx::TypeCtx,
     types::*,
@@ -82,9 +87,21 @@ pub(super) struct Generator<'g, 'ctx> {
 
     pub(super) intrinsics: HashMap<hir::Intrinsic, FunctionValue<'ctx>>,
 
+    // Functions which keep their unmangled name and external linkage, when building a library
+    pub(super) exported_functions: HashSet<hir::FunctionId>,
+
     // This is an Option since it is only initialized after
     // creating the startup function's state
     pub(super) startup_function_state: Option<FunctionState<'ctx>>,
+
+    // Only generated in debug builds
+    pub(super) debug_info: Option<DebugInfo<'ctx>>,
+
+    // The `symbol_table` intrinsic, whose body is generated once all other functions are
+    pub(super) symbol_table: Option<(FunctionValue<'ctx>, FunctionType)>,
+
+    // The block following each call in a function, and the call's line. Only recorded in debug builds
+    pub(super) call_sites: HashMap<FunctionValue<'ctx>, Vec<(BasicBlock<'ctx>, u32)>>,
 }
 
 #[derive(Clone)]
@@ -97,10 +114,13 @@ pub(super) struct FunctionState<'ctx> {
     pub(super) decl_block: BasicBlock<'ctx>,
     pub(super) current_block: BasicBlock<'ctx>,
     pub(super) scopes: Scopes<BindingId, Decl<'ctx>>,
+    pub(super) debug_scope: Option<DebugScope<'ctx>>,
+    pub(super) runtime_checks: RuntimeChecks,
 }
 
 impl<'ctx> FunctionState<'ctx> {
     pub(super) fn new(
+        runtime_checks: RuntimeChecks,
         module_info: ModuleInfo,
         function: FunctionValue<'ctx>,
         fn_type: FunctionType,
@@ -117,6 +137,8 @@ impl<'ctx> FunctionState<'ctx> {
             decl_block,
             current_block: entry_block,
             scopes: Scopes::default(),
+            debug_scope: None,
+            runtime_checks,
         }
     }
 
@@ -137,21 +159,27 @@ pub(super) struct LoopBlock<'ctx> {
 
 impl<'g, 'ctx> Generator<'g, 'ctx> {
     pub(super) fn start(&mut self) {
-        self.gen_start_function();
+        if self.workspace.build_options.output_kind.is_executable() {
+            self.gen_start_function();
+        } else {
+            self.gen_exported_functions();
+        }
     }
 
     pub(super) fn optimize(&mut self) {
-        let pass_manager_builder = PassManagerBuilder::create();
+        let build_optimization_level = self.workspace.build_options.optimization_level;
 
-        let optimization_level: OptimizationLevel = self.workspace.build_options.optimization_level.into();
+        // unoptimized builds skip the pass manager, to keep them fast
+        if build_optimization_level == build_options::OptimizationLevel::O0 {
+            return;
+        }
+
+        let pass_manager_builder = PassManagerBuilder::create();
 
-        let size_level: u32 = match self.workspace.build_options.optimization_level {
-            build_options::OptimizationLevel::Debug => 1,
-            build_options::OptimizationLevel::Release => 2,
-        };
+        let optimization_level: OptimizationLevel = build_optimization_level.into();
 
         pass_manager_builder.set_optimization_level(optimization_level);
-        pass_manager_builder.set_size_level(size_level);
+        pass_manager_builder.set_size_level(build_optimization_level.size_level());
 
         let pass_manager = PassManager::create(());
         pass_manager_builder.populate_module_pass_manager(&pass_manager);
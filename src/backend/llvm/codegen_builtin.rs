@@ -282,7 +282,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                 let lhs = lhs.into_int_value();
                 let rhs = rhs.into_int_value();
 
-                if self.workspace.build_options.optimization_level.is_release() {
+                if !state.runtime_checks.overflow {
                     self.builder.build_int_add(lhs, rhs, "add").into()
                 } else {
                     let overflow_fn = self.get_overflow_fn(ast::BinaryOp::Add, ty, lhs.get_type());
@@ -316,7 +316,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                 let lhs = lhs.into_int_value();
                 let rhs = rhs.into_int_value();
 
-                if self.workspace.build_options.optimization_level.is_release() {
+                if !state.runtime_checks.overflow {
                     self.builder.build_int_sub(lhs, rhs, "sub").into()
                 } else {
                     let overflow_fn = self.get_overflow_fn(ast::BinaryOp::Sub, ty, lhs.get_type());
@@ -350,7 +350,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                 let lhs = lhs.into_int_value();
                 let rhs = rhs.into_int_value();
 
-                if self.workspace.build_options.optimization_level.is_release() {
+                if !state.runtime_checks.overflow {
                     self.builder.build_int_mul(lhs, rhs, "imul").into()
                 } else {
                     let overflow_fn = self.get_overflow_fn(ast::BinaryOp::Mul, ty, lhs.get_type());
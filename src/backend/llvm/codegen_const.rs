@@ -2,10 +2,14 @@ use super::{
     codegen::{FunctionState, Generator},
     ty::IntoLlvmType,
 };
-use crate::{hir::const_value::ConstValue, infer::normalize::Normalize, types::*};
+use crate::{
+    hir::const_value::{ConstExternVariable, ConstValue},
+    infer::normalize::Normalize,
+    types::*,
+};
 use inkwell::{
     module::Linkage,
-    values::{BasicValue, BasicValueEnum, IntValue, PointerValue, StructValue},
+    values::{BasicValue, BasicValueEnum, GlobalValue, IntValue, PointerValue, StructValue},
 };
 use ustr::Ustr;
 
@@ -110,25 +114,28 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                 function_value.as_global_value().as_pointer_value().into()
             }
             ConstValue::ExternVariable(variable) => {
-                if let Some(lib) = variable.lib.as_ref().or(variable.dylib.as_ref()) {
-                    self.extern_libraries.insert(lib.clone());
-                }
-
-                let global_value = self.extern_variables.get(&variable.name).cloned().unwrap_or_else(|| {
-                    let llvm_type = variable.ty.llvm_type(self);
+                let ptr = self.gen_extern_variable(variable).as_pointer_value();
+                self.build_load(ptr, &variable.name)
+            }
+        }
+    }
+}
 
-                    let global_value = self.module.add_global(llvm_type, None, &variable.name);
-                    global_value.set_linkage(Linkage::External);
+impl<'g, 'ctx> Generator<'g, 'ctx> {
+    pub(super) fn gen_extern_variable(&mut self, variable: &ConstExternVariable) -> GlobalValue<'ctx> {
+        if let Some(lib) = variable.lib.as_ref().or(variable.dylib.as_ref()) {
+            self.extern_libraries.insert(lib.clone());
+        }
 
-                    self.extern_variables.insert(variable.name, global_value);
+        self.extern_variables.get(&variable.name).cloned().unwrap_or_else(|| {
+            let llvm_type = variable.ty.llvm_type(self);
 
-                    global_value
-                });
+            let global_value = self.module.add_global(llvm_type, None, &variable.name);
+            global_value.set_linkage(Linkage::External);
 
-                let ptr = global_value.as_pointer_value();
+            self.extern_variables.insert(variable.name, global_value);
 
-                self.build_load(ptr, &variable.name)
-            }
-        }
+            global_value
+        })
     }
 }
@@ -21,7 +21,9 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Node {
             return generator.const_unit();
         }
 
-        match self {
+        let prev_debug_location = generator.set_debug_location(state, self.span());
+
+        let value = match self {
             hir::Node::Const(x) => x.codegen(generator, state),
             hir::Node::Binding(x) => x.codegen(generator, state),
             hir::Node::Id(x) => x.codegen(generator, state),
@@ -33,7 +35,11 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Node {
             hir::Node::Control(x) => x.codegen(generator, state),
             hir::Node::Builtin(x) => x.codegen(generator, state),
             hir::Node::Literal(x) => x.codegen(generator, state),
-        }
+        };
+
+        generator.restore_debug_location(prev_debug_location);
+
+        value
     }
 }
 
@@ -66,6 +72,8 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Binding {
                 }
                 _ => match self.value.as_const_value() {
                     Some(ConstValue::Function(function)) => Decl::Function(generator.gen_function(function.id, None)),
+                    // extern variables are accessed through their symbol, so that writes reach them
+                    Some(ConstValue::ExternVariable(variable)) => Decl::Global(generator.gen_extern_variable(variable)),
                     Some(const_value) => {
                         let global_value = generator.add_global(self.id, llvm_type, Linkage::Private);
 
@@ -101,7 +109,8 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Binding {
             }
             _ => {
                 let value = self.value.codegen(generator, state);
-                generator.gen_local(state, self.id, value);
+                let ptr = generator.gen_local(state, self.id, value);
+                generator.gen_local_debug_info(state, self.id, ptr, None);
             }
         }
 
@@ -165,6 +174,14 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Call {
 
         for (index, arg) in self.args.iter().enumerate() {
             let value = arg.codegen(generator, state);
+
+            // arguments past the parameters are passed to a C variadic function
+            let value = if index >= callee_ty.params.len() {
+                generator.promote_variadic_arg(value, &arg.ty().normalize(generator.tcx))
+            } else {
+                value
+            };
+
             args.push((value, index));
         }
 
@@ -188,6 +205,7 @@ impl<'g, 'ctx> Codegen<'g, 'ctx> for hir::Call {
             &callee_ty,
             args,
             &self.ty.normalize(generator.tcx),
+            self.span,
         )
     }
 }
@@ -0,0 +1,610 @@
+use super::{
+    codegen::{FunctionState, Generator},
+    ty::IntoLlvmType,
+};
+use crate::{
+    common::target::Os,
+    hir,
+    infer::{display::DisplayType, normalize::Normalize},
+    span::{FileId, Span},
+    types::*,
+    workspace::{BindingId, ModuleId},
+};
+use inkwell::{
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DIScope, DIType, DWARFEmissionKind,
+        DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::FlagBehavior,
+    targets::TargetData,
+    types::BasicTypeEnum,
+    values::{FunctionValue, PointerValue},
+    AddressSpace,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+// DWARF base type encodings
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED: u32 = 0x07;
+
+const DEBUG_METADATA_VERSION: u64 = 3;
+const DWARF_VERSION: u64 = 4;
+
+/// The state of the DWARF debug information of the generated module.
+/// Only created when building in debug mode.
+pub(super) struct DebugInfo<'ctx> {
+    target_data: TargetData,
+    // Each module gets its own compile unit
+    compile_units: HashMap<ModuleId, CompileUnit<'ctx>>,
+    // Types are shared between compile units, so they are all created by the root module's builder
+    types_module_id: ModuleId,
+    struct_types: HashMap<BindingId, DIType<'ctx>>,
+    // Structs whose members are currently being created. Used to break cycles in recursive structs
+    structs_in_progress: HashSet<BindingId>,
+    // The location attached to instructions emitted by the builder
+    location: Option<DILocation<'ctx>>,
+}
+
+struct CompileUnit<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+}
+
+/// The debug information scope of a function, which its instructions and locals are attached to
+#[derive(Debug, Clone, Copy)]
+pub(super) struct DebugScope<'ctx> {
+    scope: DIScope<'ctx>,
+    file: DIFile<'ctx>,
+    file_id: FileId,
+    module_id: ModuleId,
+}
+
+impl<'g, 'ctx> Generator<'g, 'ctx> {
+    pub(super) fn init_debug_info(&mut self, target_data: TargetData) {
+        let context = self.context;
+
+        if self.target_metrics.os == Os::Windows {
+            self.module.add_basic_value_flag(
+                "CodeView",
+                FlagBehavior::Warning,
+                context.i32_type().const_int(1, false),
+            );
+        } else {
+            self.module.add_basic_value_flag(
+                "Dwarf Version",
+                FlagBehavior::Warning,
+                context.i32_type().const_int(DWARF_VERSION, false),
+            );
+        }
+
+        self.module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context.i32_type().const_int(DEBUG_METADATA_VERSION, false),
+        );
+
+        let root_module_id = self.workspace.root_module_id;
+
+        self.debug_info = Some(DebugInfo {
+            target_data,
+            compile_units: HashMap::default(),
+            types_module_id: root_module_id,
+            struct_types: HashMap::default(),
+            structs_in_progress: HashSet::default(),
+            location: None,
+        });
+
+        self.ensure_compile_unit(root_module_id);
+    }
+
+    pub(super) fn finalize_debug_info(&self) {
+        if let Some(debug_info) = &self.debug_info {
+            for compile_unit in debug_info.compile_units.values() {
+                compile_unit.builder.finalize();
+            }
+        }
+    }
+
+    /// Creates the subprogram of `function` and attaches it to `function_value`
+    pub(super) fn gen_function_debug_info(
+        &mut self,
+        function: &hir::Function,
+        function_type: &FunctionType,
+        function_value: FunctionValue<'ctx>,
+    ) -> Option<DebugScope<'ctx>> {
+        if self.debug_info.is_none() || function.span.is_unknown() {
+            return None;
+        }
+
+        let return_type = match function_type.return_type.as_ref() {
+            Type::Unit | Type::Never => None,
+            ty => Some(self.debug_type(ty)),
+        };
+
+        let param_types = function_type
+            .params
+            .iter()
+            .map(|param| self.debug_type(&param.ty))
+            .collect::<Vec<_>>();
+
+        self.ensure_compile_unit(function.module_id);
+
+        let file = self.debug_file(function.module_id, function.span.file_id);
+
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let builder = &debug_info.compile_units[&function.module_id].builder;
+
+        let subroutine_type = builder.create_subroutine_type(file, return_type, &param_types, DIFlags::ZERO);
+
+        let line = function.span.start.line;
+
+        let subprogram = builder.create_function(
+            file.as_debug_info_scope(),
+            &function.name,
+            function_value.get_name().to_str().ok(),
+            file,
+            line,
+            subroutine_type,
+            !self.exported_functions.contains(&function.id),
+            true,
+            line,
+            DIFlags::PROTOTYPED,
+            false,
+        );
+
+        function_value.set_subprogram(subprogram);
+
+        Some(DebugScope {
+            scope: subprogram.as_debug_info_scope(),
+            file,
+            file_id: function.span.file_id,
+            module_id: function.module_id,
+        })
+    }
+
+    /// Describes the local variable `id`, which is stored in `ptr`.
+    /// `arg_no` is the 1-based position of the variable in the function's parameters, if it is a parameter.
+    pub(super) fn gen_local_debug_info(
+        &mut self,
+        state: &FunctionState<'ctx>,
+        id: BindingId,
+        ptr: PointerValue<'ctx>,
+        arg_no: Option<u32>,
+    ) {
+        let debug_scope = match state.debug_scope {
+            Some(debug_scope) => debug_scope,
+            None => return,
+        };
+
+        let binding_info = self.workspace.binding_infos.get(id).unwrap();
+        let span = binding_info.span;
+        let name = binding_info.name;
+        let ty = binding_info.ty.normalize(self.tcx);
+
+        // locals from other files than the function's, such as injected code, have no line
+        let (line, column) = if span.file_id == debug_scope.file_id {
+            (span.start.line, span.start.column)
+        } else {
+            (0, 0)
+        };
+
+        let di_type = self.debug_type(&ty);
+        let align_in_bits = self.debug_align_in_bits(ty.llvm_type(self));
+
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let builder = &debug_info.compile_units[&debug_scope.module_id].builder;
+
+        let variable = match arg_no {
+            Some(arg_no) => builder.create_parameter_variable(
+                debug_scope.scope,
+                &name,
+                arg_no,
+                debug_scope.file,
+                line,
+                di_type,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => builder.create_auto_variable(
+                debug_scope.scope,
+                &name,
+                debug_scope.file,
+                line,
+                di_type,
+                true,
+                DIFlags::ZERO,
+                align_in_bits,
+            ),
+        };
+
+        let location = builder.create_debug_location(self.context, line, column, debug_scope.scope, None);
+
+        // declared in the decls block, so the variable is visible for the whole function, like its alloca
+        builder.insert_declare_at_end(
+            ptr,
+            Some(variable),
+            Some(builder.create_expression(vec![])),
+            location,
+            state.decl_block,
+        );
+    }
+
+    /// Attaches `span` to the instructions emitted from now on, returning the previous location.
+    /// Spans from other files than the function's, such as injected code, keep the previous location.
+    pub(super) fn set_debug_location(&mut self, state: &FunctionState<'ctx>, span: Span) -> Option<DILocation<'ctx>> {
+        let debug_info = match &mut self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return None,
+        };
+
+        let prev_location = debug_info.location;
+
+        match state.debug_scope {
+            Some(debug_scope) if !span.is_unknown() && span.file_id == debug_scope.file_id => {
+                let location = debug_info.compile_units[&debug_scope.module_id]
+                    .builder
+                    .create_debug_location(
+                        self.context,
+                        span.start.line,
+                        span.start.column,
+                        debug_scope.scope,
+                        None,
+                    );
+
+                self.builder.set_current_debug_location(self.context, location);
+                debug_info.location = Some(location);
+            }
+            _ => (),
+        }
+
+        prev_location
+    }
+
+    /// Detaches the current location, so code can be emitted in a function without debug information,
+    /// or in a function other than the current one. Returns the detached location.
+    pub(super) fn take_debug_location(&mut self) -> Option<DILocation<'ctx>> {
+        let debug_info = self.debug_info.as_mut()?;
+
+        self.builder.unset_current_debug_location();
+        debug_info.location.take()
+    }
+
+    pub(super) fn restore_debug_location(&mut self, location: Option<DILocation<'ctx>>) {
+        if let Some(debug_info) = &mut self.debug_info {
+            match location {
+                Some(location) => self.builder.set_current_debug_location(self.context, location),
+                None => self.builder.unset_current_debug_location(),
+            }
+
+            debug_info.location = location;
+        }
+    }
+
+    fn ensure_compile_unit(&mut self, module_id: ModuleId) {
+        let debug_info = self.debug_info.as_mut().unwrap();
+
+        if debug_info.compile_units.contains_key(&module_id) {
+            return;
+        }
+
+        let module_info = self.workspace.module_infos.get(module_id).unwrap();
+        let (file_name, directory) = split_file_path(&module_info.file_path);
+
+        let (builder, unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            concat!("chili ", env!("CARGO_PKG_VERSION")),
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        debug_info
+            .compile_units
+            .insert(module_id, CompileUnit { builder, unit });
+    }
+
+    fn debug_file(&self, module_id: ModuleId, file_id: FileId) -> DIFile<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let compile_unit = &debug_info.compile_units[&module_id];
+
+        match self.workspace.diagnostics.get_file(file_id) {
+            Some(file) => {
+                let (file_name, directory) = split_file_path(file.name());
+                compile_unit.builder.create_file(&file_name, &directory)
+            }
+            None => compile_unit.unit.get_file(),
+        }
+    }
+
+    fn debug_type(&mut self, ty: &Type) -> DIType<'ctx> {
+        let llvm_type = ty.llvm_type(self);
+        let size_in_bits = self.debug_size_in_bits(llvm_type);
+        let align_in_bits = self.debug_align_in_bits(llvm_type);
+        let name = ty.display(self.tcx);
+
+        match ty {
+            Type::Bool => self.debug_basic_type(&name, size_in_bits, DW_ATE_BOOLEAN),
+            Type::Int(_) => self.debug_basic_type(&name, size_in_bits, DW_ATE_SIGNED),
+            Type::Uint(_) => self.debug_basic_type(&name, size_in_bits, DW_ATE_UNSIGNED),
+            Type::Float(_) => self.debug_basic_type(&name, size_in_bits, DW_ATE_FLOAT),
+            Type::Pointer(inner, _) => {
+                let pointee = self.debug_type(inner);
+                self.debug_pointer_type(&name, pointee, size_in_bits, align_in_bits)
+            }
+            Type::Function(_) => {
+                // functions are described by their subprograms, so function pointers are opaque
+                let pointee = self.debug_type(&Type::Unit);
+                self.debug_pointer_type(&name, pointee, size_in_bits, align_in_bits)
+            }
+            Type::Array(inner, len) => {
+                let element_type = self.debug_type(inner);
+                let debug_info = self.debug_info.as_ref().unwrap();
+
+                debug_info
+                    .types_builder()
+                    .create_array_type(element_type, size_in_bits, align_in_bits, &[0..*len as i64])
+                    .as_type()
+            }
+            Type::Slice(inner) | Type::Str(inner) => {
+                let fields = vec![
+                    ("data".to_string(), Type::Pointer(inner.clone(), false), Span::unknown()),
+                    ("len".to_string(), Type::uint(), Span::unknown()),
+                ];
+
+                self.debug_struct_type(&name, Span::unknown(), llvm_type, &fields, false)
+            }
+            Type::Tuple(elements) => {
+                let fields = elements
+                    .iter()
+                    .enumerate()
+                    .map(|(index, ty)| (index.to_string(), ty.clone(), Span::unknown()))
+                    .collect::<Vec<_>>();
+
+                self.debug_struct_type(&name, Span::unknown(), llvm_type, &fields, false)
+            }
+            Type::Struct(struct_type) => match struct_type.binding_id {
+                Some(binding_id) => self.debug_named_struct_type(struct_type, binding_id, llvm_type),
+                None => {
+                    let fields = struct_fields(struct_type);
+                    self.debug_struct_type(&name, Span::unknown(), llvm_type, &fields, struct_type.is_union())
+                }
+            },
+            _ => {
+                // zero-sized types, such as the unit type
+                self.debug_struct_type(&name, Span::unknown(), llvm_type, &[], false)
+            }
+        }
+    }
+
+    fn debug_named_struct_type(
+        &mut self,
+        struct_type: &StructType,
+        binding_id: BindingId,
+        llvm_type: BasicTypeEnum<'ctx>,
+    ) -> DIType<'ctx> {
+        if let Some(di_type) = self.debug_info.as_ref().unwrap().struct_types.get(&binding_id) {
+            return *di_type;
+        }
+
+        let span = self.workspace.binding_infos.get(binding_id).unwrap().span;
+
+        let debug_info = self.debug_info.as_mut().unwrap();
+
+        // a struct that refers to itself, through a pointer, refers to a declaration of itself,
+        // which debuggers resolve by its name
+        if !debug_info.structs_in_progress.insert(binding_id) {
+            let file = self.debug_types_file(span);
+            let debug_info = self.debug_info.as_ref().unwrap();
+
+            return debug_info
+                .types_builder()
+                .create_struct_type(
+                    file.as_debug_info_scope(),
+                    &struct_type.name,
+                    file,
+                    span.start.line,
+                    0,
+                    0,
+                    DIFlags::FWD_DECL,
+                    None,
+                    &[],
+                    0,
+                    None,
+                    "",
+                )
+                .as_type();
+        }
+
+        let fields = struct_fields(struct_type);
+        let di_type = self.debug_struct_type(&struct_type.name, span, llvm_type, &fields, struct_type.is_union());
+
+        let debug_info = self.debug_info.as_mut().unwrap();
+        debug_info.structs_in_progress.remove(&binding_id);
+        debug_info.struct_types.insert(binding_id, di_type);
+
+        di_type
+    }
+
+    fn debug_struct_type(
+        &mut self,
+        name: &str,
+        span: Span,
+        llvm_type: BasicTypeEnum<'ctx>,
+        fields: &[(String, Type, Span)],
+        is_union: bool,
+    ) -> DIType<'ctx> {
+        let size_in_bits = self.debug_size_in_bits(llvm_type);
+        let align_in_bits = self.debug_align_in_bits(llvm_type);
+        let file = self.debug_types_file(span);
+
+        let mut members = vec![];
+
+        for (index, (field_name, field_type, field_span)) in fields.iter().enumerate() {
+            let field_llvm_type = field_type.llvm_type(self);
+            let field_di_type = self.debug_type(field_type);
+
+            // union fields all start at 0, while the llvm type of a union only holds its largest field
+            let offset_in_bits = if is_union {
+                0
+            } else {
+                let debug_info = self.debug_info.as_ref().unwrap();
+                debug_info
+                    .target_data
+                    .offset_of_element(&llvm_type.into_struct_type(), index as u32)
+                    .unwrap_or_default()
+                    * 8
+            };
+
+            let field_size_in_bits = self.debug_size_in_bits(field_llvm_type);
+            let field_align_in_bits = self.debug_align_in_bits(field_llvm_type);
+
+            let line = if field_span.is_unknown() {
+                0
+            } else {
+                field_span.start.line
+            };
+
+            let debug_info = self.debug_info.as_ref().unwrap();
+
+            let member = debug_info.types_builder().create_member_type(
+                file.as_debug_info_scope(),
+                field_name,
+                file,
+                line,
+                field_size_in_bits,
+                field_align_in_bits,
+                offset_in_bits,
+                DIFlags::PUBLIC,
+                field_di_type,
+            );
+
+            members.push(member.as_type());
+        }
+
+        let line = if span.is_unknown() { 0 } else { span.start.line };
+
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let builder = debug_info.types_builder();
+
+        if is_union {
+            builder
+                .create_union_type(
+                    file.as_debug_info_scope(),
+                    name,
+                    file,
+                    line,
+                    size_in_bits,
+                    align_in_bits,
+                    DIFlags::PUBLIC,
+                    &members,
+                    0,
+                    "",
+                )
+                .as_type()
+        } else {
+            builder
+                .create_struct_type(
+                    file.as_debug_info_scope(),
+                    name,
+                    file,
+                    line,
+                    size_in_bits,
+                    align_in_bits,
+                    DIFlags::PUBLIC,
+                    None,
+                    &members,
+                    0,
+                    None,
+                    "",
+                )
+                .as_type()
+        }
+    }
+
+    fn debug_basic_type(&self, name: &str, size_in_bits: u64, encoding: u32) -> DIType<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+
+        debug_info
+            .types_builder()
+            .create_basic_type(name, size_in_bits, encoding, DIFlags::PUBLIC)
+            .unwrap()
+            .as_type()
+    }
+
+    fn debug_pointer_type(
+        &self,
+        name: &str,
+        pointee: DIType<'ctx>,
+        size_in_bits: u64,
+        align_in_bits: u32,
+    ) -> DIType<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+
+        debug_info
+            .types_builder()
+            .create_pointer_type(name, pointee, size_in_bits, align_in_bits, AddressSpace::Generic)
+            .as_type()
+    }
+
+    fn debug_types_file(&self, span: Span) -> DIFile<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        self.debug_file(debug_info.types_module_id, span.file_id)
+    }
+
+    fn debug_size_in_bits(&self, llvm_type: BasicTypeEnum<'ctx>) -> u64 {
+        self.debug_info.as_ref().unwrap().target_data.get_abi_size(&llvm_type) * 8
+    }
+
+    fn debug_align_in_bits(&self, llvm_type: BasicTypeEnum<'ctx>) -> u32 {
+        self.debug_info
+            .as_ref()
+            .unwrap()
+            .target_data
+            .get_abi_alignment(&llvm_type)
+            * 8
+    }
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    fn types_builder(&self) -> &DebugInfoBuilder<'ctx> {
+        &self.compile_units[&self.types_module_id].builder
+    }
+}
+
+fn struct_fields(struct_type: &StructType) -> Vec<(String, Type, Span)> {
+    struct_type
+        .fields
+        .iter()
+        .map(|field| (field.name.to_string(), field.ty.clone(), field.span))
+        .collect()
+}
+
+fn split_file_path(path: &str) -> (String, String) {
+    let path = Path::new(path);
+
+    let file_name = path
+        .file_name()
+        .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+        .to_string();
+
+    let directory = path
+        .parent()
+        .map_or_else(String::new, |dir| dir.to_string_lossy().to_string());
+
+    (file_name, directory)
+}
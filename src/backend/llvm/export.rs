@@ -0,0 +1,102 @@
+use super::codegen::{FunctionState, Generator};
+use crate::{ast, hir, hir::const_value::ConstValue, types::*};
+use inkwell::{module::Linkage, values::PointerValue, AddressSpace};
+
+impl<'g, 'ctx> Generator<'g, 'ctx> {
+    /// Generates all public functions of the root module with their unmangled names,
+    /// so they can be linked against by other programs.
+    /// Since there is no start function, statics are initialized in a module constructor.
+    pub(super) fn gen_exported_functions(&mut self) {
+        let init_function = self.module.add_function(
+            "__chili_init",
+            self.context.void_type().fn_type(&[], false),
+            Some(Linkage::Internal),
+        );
+
+        let decl_block = self.context.append_basic_block(init_function, "decls");
+        let entry_block = self.context.append_basic_block(init_function, "entry");
+
+        let root_module_info = self.workspace.get_root_module_info();
+
+        let mut state = FunctionState::new(
+            self.workspace.build_options.runtime_checks,
+            *root_module_info,
+            init_function,
+            FunctionType {
+                params: vec![],
+                return_type: Box::new(Type::Unit),
+                varargs: None,
+                kind: FunctionTypeKind::Orphan,
+            },
+            None,
+            decl_block,
+            entry_block,
+        );
+
+        state.push_scope();
+
+        self.start_block(&mut state, entry_block);
+
+        self.startup_function_state = Some(state.clone());
+
+        let exported_function_ids: Vec<hir::FunctionId> = self
+            .workspace
+            .binding_infos
+            .iter()
+            .filter(|(_, binding_info)| {
+                binding_info.module_id == self.workspace.root_module_id
+                    && binding_info.visibility == ast::Visibility::Public
+                    && binding_info.scope_level.is_global()
+            })
+            .filter_map(|(_, binding_info)| match &binding_info.const_value {
+                Some(ConstValue::Function(f)) => match &self.cache.functions.get(f.id).unwrap().kind {
+                    hir::FunctionKind::Orphan { .. } => Some(f.id),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        self.exported_functions.extend(exported_function_ids.iter().copied());
+
+        for id in exported_function_ids {
+            self.gen_function(id, None);
+        }
+
+        if self.current_block().get_terminator().is_none() {
+            self.builder.build_return(None);
+        }
+
+        self.start_block(&mut state, decl_block);
+
+        state.pop_scope();
+
+        self.builder.build_unconditional_branch(entry_block);
+
+        self.gen_global_ctor(init_function.as_global_value().as_pointer_value());
+    }
+
+    fn gen_global_ctor(&mut self, function_ptr: PointerValue<'ctx>) {
+        const DEFAULT_PRIORITY: u64 = 65535;
+
+        let i32_type = self.context.i32_type();
+        let i8_ptr_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+
+        let ctor_type = self.context.struct_type(
+            &[i32_type.into(), function_ptr.get_type().into(), i8_ptr_type.into()],
+            false,
+        );
+
+        let ctor = ctor_type.const_named_struct(&[
+            i32_type.const_int(DEFAULT_PRIORITY, false).into(),
+            function_ptr.into(),
+            i8_ptr_type.const_null().into(),
+        ]);
+
+        let ctors = self
+            .module
+            .add_global(ctor_type.array_type(1), None, "llvm.global_ctors");
+        ctors.set_linkage(Linkage::Appending);
+        ctors.set_initializer(&ctor_type.const_array(&[ctor]));
+    }
+}
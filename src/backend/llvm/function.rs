@@ -4,7 +4,10 @@ use super::{
     ty::IntoLlvmType,
     CallingConv,
 };
-use crate::{backend::llvm::codegen::Codegen, hir, infer::normalize::Normalize, types::*};
+use crate::{
+    backend::llvm::codegen::Codegen, common::build_options::RuntimeChecks, hir, infer::normalize::Normalize,
+    span::Span, types::*,
+};
 use inkwell::{
     attributes::{Attribute, AttributeLoc},
     module::Linkage,
@@ -18,6 +21,8 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         id: hir::FunctionId,
         prev_state: Option<FunctionState<'ctx>>,
     ) -> FunctionValue<'ctx> {
+        let id = self.redirect_to_panic_handler(id, prev_state.as_ref());
+
         match self.functions.get(&id) {
             Some(function) => *function,
             None => {
@@ -33,11 +38,19 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                             self.builder.get_insert_block()
                         };
 
-                        let function_value =
-                            self.declare_fn_sig(&function_type, function.qualified_name, Some(Linkage::Private));
+                        let (name, linkage) = if self.exported_functions.contains(&function.id) {
+                            (function.name, Linkage::External)
+                        } else {
+                            (function.qualified_name, Linkage::Private)
+                        };
+
+                        let function_value = self.declare_fn_sig(&function_type, name, Some(linkage));
 
                         self.functions.insert(function.id, function_value);
 
+                        // this function may be generated while in the middle of another function
+                        let prev_debug_location = self.take_debug_location();
+
                         let decl_block = self.context.append_basic_block(function_value, "decls");
                         let entry_block = self.context.append_basic_block(function_value, "entry");
 
@@ -51,7 +64,15 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                             None
                         };
 
+                        // functions annotated with `no_runtime_checks` opt out of all checks
+                        let runtime_checks = if function.no_runtime_checks {
+                            RuntimeChecks::none()
+                        } else {
+                            self.workspace.build_options.runtime_checks
+                        };
+
                         let mut state = FunctionState::new(
+                            runtime_checks,
                             module_info,
                             function_value,
                             function_type,
@@ -64,8 +85,12 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                             state.scopes = prev_state.scopes;
                         }
 
+                        state.debug_scope = self.gen_function_debug_info(function, &function_type, function_value);
+
                         self.start_block(&mut state, entry_block);
 
+                        self.set_debug_location(&state, function.span);
+
                         state.push_scope();
 
                         for (index, (&value, param)) in function_value
@@ -93,7 +118,8 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
                             let transmuted_value = self.build_transmute(&state, value, llvm_param_ty);
 
-                            self.gen_local(&mut state, param.id, transmuted_value);
+                            let ptr = self.gen_local(&mut state, param.id, transmuted_value);
+                            self.gen_local_debug_info(&state, param.id, ptr, Some(index as u32 + 1));
                         }
 
                         let return_value = body.as_ref().unwrap().codegen(self, &mut state);
@@ -111,6 +137,8 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                             self.builder.position_at_end(prev_block);
                         }
 
+                        self.restore_debug_location(prev_debug_location);
+
                         function_value
                     }
                     hir::FunctionKind::Extern { lib, dylib, link_name } => {
@@ -147,7 +175,11 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
         // Add the function to the current module
         let function = match ty.kind {
-            FunctionTypeKind::Orphan => self.add_function(name, fn_type, linkage),
+            FunctionTypeKind::Orphan => {
+                let function = self.add_function(name, fn_type, linkage);
+                self.add_unwind_table_attr(function);
+                function
+            }
             FunctionTypeKind::Extern => self.get_or_add_function(name, fn_type, linkage),
         };
 
@@ -194,6 +226,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         callee_ty: &FunctionType,
         args: Vec<BasicValueEnum<'ctx>>,
         result_ty: &Type,
+        span: Span,
     ) -> BasicValueEnum<'ctx> {
         let abi_fn = self.get_abi_compliant_fn(callee_ty);
 
@@ -241,8 +274,9 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         }
 
         if abi_fn.variadic {
-            for i in param_index..args.len() {
-                processed_args.push(args[i]);
+            // variadic arguments are already promoted by the caller - see `promote_variadic_arg`
+            for &arg in args.iter().skip(param_index) {
+                processed_args.push(arg);
             }
         }
 
@@ -269,6 +303,8 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         let result_ty = result_ty.llvm_type(self);
         let value = self.build_transmute(state, value, result_ty);
 
+        self.record_call_site(state, span);
+
         if callee_ty.return_type.is_never() {
             self.build_unreachable();
         }
@@ -276,6 +312,29 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         value
     }
 
+    /// Applies C's default argument promotions to a variadic argument:
+    /// integers narrower than `int` (and bools) are passed as `i32`, and `f32` is passed as `f64`.
+    /// These match the VM's promotions when calling variadic foreign functions.
+    pub(super) fn promote_variadic_arg(&self, arg: BasicValueEnum<'ctx>, arg_ty: &Type) -> BasicValueEnum<'ctx> {
+        match arg_ty {
+            Type::Int(IntType::I8 | IntType::I16) => self
+                .builder
+                .build_int_s_extend(arg.into_int_value(), self.context.i32_type(), "vararg_sext")
+                .into(),
+            Type::Uint(UintType::U8 | UintType::U16) | Type::Bool => self
+                .builder
+                .build_int_z_extend(arg.into_int_value(), self.context.i32_type(), "vararg_zext")
+                .into(),
+            _ => match arg {
+                BasicValueEnum::FloatValue(value) if value.get_type() == self.context.f32_type() => self
+                    .builder
+                    .build_float_ext(value, self.context.f64_type(), "vararg_fpext")
+                    .into(),
+                _ => arg,
+            },
+        }
+    }
+
     fn gen_function_call_inner(
         &mut self,
         callee: impl Into<CallableValue<'ctx>>,
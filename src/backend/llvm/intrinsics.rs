@@ -26,7 +26,12 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
                 let str = generator.const_str_slice("", "");
 
                 let return_value = generator
-                    .const_struct(&[str.into(), generator.const_bool(false).into()])
+                    .const_struct(&[
+                        str.into(),
+                        generator.const_bool(false).into(),
+                        generator.ptr_sized_int_type.const_zero().into(),
+                        generator.ptr_sized_int_type.const_zero().into(),
+                    ])
                     .as_basic_value_enum();
 
                 generator.build_store(return_ptr, return_value);
@@ -35,10 +40,28 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
                 function
             }),
+            hir::Intrinsic::InjectBindings => self.get_or_create_intrinsic(intrinsic, |generator| {
+                const NAME: &str = "intrinsic#inject_bindings";
+
+                // bindings can only be injected at compile-time, so this is a noop at runtime
+                let function = generator.declare_fn_sig(function_type, NAME, Some(Linkage::Private));
+
+                let entry_block = generator.context.append_basic_block(function, "entry");
+
+                generator.builder.position_at_end(entry_block);
+                generator.builder.build_return(None);
+
+                function
+            }),
+            hir::Intrinsic::SymbolTable => {
+                self.get_or_create_intrinsic(intrinsic, |generator| generator.declare_symbol_table(function_type))
+            }
             hir::Intrinsic::Location
             | hir::Intrinsic::CallerLocation
             | hir::Intrinsic::CompilerError
-            | hir::Intrinsic::CompilerWarning => panic!(
+            | hir::Intrinsic::CompilerWarning
+            | hir::Intrinsic::IsDefined
+            | hir::Intrinsic::GetDefine => panic!(
                 "intrinsic function '{}' should have been evaluated at compile-time",
                 intrinsic
             ),
@@ -51,7 +74,11 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         F: FnOnce(&mut Generator<'g, 'ctx>) -> FunctionValue<'ctx>,
     {
         self.intrinsics.get(intrinsic).cloned().unwrap_or_else(|| {
+            // intrinsics have no debug information, so the current location must not leak into them
+            let prev_debug_location = self.take_debug_location();
             let function = create_fn(self);
+            self.restore_debug_location(prev_debug_location);
+
             self.intrinsics.insert(*intrinsic, function);
             function
         })
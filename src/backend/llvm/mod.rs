@@ -1,4 +1,5 @@
 mod abi;
+mod backtrace;
 mod codegen;
 mod codegen_builtin;
 mod codegen_const;
@@ -7,6 +8,8 @@ mod codegen_literal;
 mod codegen_node;
 mod codegen_static;
 mod conditional;
+mod debug_info;
+mod export;
 mod function;
 mod intrinsics;
 mod panic;
@@ -19,9 +22,10 @@ mod util;
 use crate::{
     ast,
     common::{
-        build_options::{self, BuildOptions},
-        target::{Arch, Os, TargetMetrics},
+        build_options::{self, BuildOptions, OutputKind},
+        target::{Arch, Os, TargetMetrics, TargetPlatform},
     },
+    error::diagnostic::Diagnostic,
     hir,
     infer::type_ctx::TypeCtx,
     time,
@@ -38,7 +42,8 @@ use inkwell::{
 use path_absolutize::Absolutize;
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    env,
+    path::{Path, PathBuf},
     process::Command,
 };
 use ustr::UstrMap;
@@ -69,16 +74,29 @@ pub fn codegen<'w>(workspace: &Workspace, tcx: &TypeCtx, cache: &hir::Cache) ->
 
     let triple = TargetTriple::create(target_metrics.target_triplet);
     let target = Target::from_triple(&triple).unwrap();
-    let host_cpu = TargetMachine::get_host_cpu_name();
-    let features = TargetMachine::get_host_cpu_features();
+
+    // The host's cpu features can only be used when compiling for the host
+    let (cpu, features) = if workspace.build_options.target_platform.is_host() {
+        (
+            TargetMachine::get_host_cpu_name().to_string(),
+            TargetMachine::get_host_cpu_features().to_string(),
+        )
+    } else {
+        ("generic".to_string(), "".to_string())
+    };
+
+    let reloc_mode = match workspace.build_options.output_kind {
+        OutputKind::SharedLibrary => RelocMode::PIC,
+        _ => RelocMode::Default,
+    };
 
     let target_machine = target
         .create_target_machine(
             &triple,
-            host_cpu.to_str().unwrap(),
-            features.to_str().unwrap(),
+            &cpu,
+            &features,
             workspace.build_options.optimization_level.into(),
-            RelocMode::Default,
+            reloc_mode,
             CodeModel::Default,
         )
         .unwrap();
@@ -103,13 +121,24 @@ pub fn codegen<'w>(workspace: &Workspace, tcx: &TypeCtx, cache: &hir::Cache) ->
         extern_variables: UstrMap::default(),
         extern_libraries: HashSet::default(),
         intrinsics: HashMap::default(),
+        exported_functions: HashSet::default(),
         startup_function_state: None,
+        debug_info: None,
+        symbol_table: None,
+        call_sites: HashMap::default(),
     };
 
+    if workspace.build_options.optimization_level.is_debug() {
+        cg.init_debug_info(target_machine.get_target_data());
+    }
+
     time! { workspace.build_options.emit_times, "llvm", {
         cg.start();
     }};
 
+    cg.finalize_symbol_table();
+    cg.finalize_debug_info();
+
     if let Err(e) = cg.module.verify() {
         cg.module.print_to_file("fail.ll").unwrap();
         panic!("{}", e);
@@ -119,7 +148,7 @@ pub fn codegen<'w>(workspace: &Workspace, tcx: &TypeCtx, cache: &hir::Cache) ->
         cg.optimize();
     }};
 
-    build_executable(
+    build_output(
         &workspace.build_options,
         &target_machine,
         &target_metrics,
@@ -131,13 +160,17 @@ pub fn codegen<'w>(workspace: &Workspace, tcx: &TypeCtx, cache: &hir::Cache) ->
 impl From<build_options::OptimizationLevel> for OptimizationLevel {
     fn from(o: build_options::OptimizationLevel) -> Self {
         match o {
-            build_options::OptimizationLevel::Debug => OptimizationLevel::Less,
-            build_options::OptimizationLevel::Release => OptimizationLevel::Default,
+            build_options::OptimizationLevel::O0 => OptimizationLevel::None,
+            build_options::OptimizationLevel::O1 => OptimizationLevel::Less,
+            build_options::OptimizationLevel::O2
+            | build_options::OptimizationLevel::Os
+            | build_options::OptimizationLevel::Oz => OptimizationLevel::Default,
+            build_options::OptimizationLevel::O3 => OptimizationLevel::Aggressive,
         }
     }
 }
 
-fn build_executable(
+fn build_output(
     build_options: &BuildOptions,
     target_machine: &TargetMachine,
     target_metrics: &TargetMetrics,
@@ -157,36 +190,101 @@ fn build_executable(
         module.print_to_file(output_path.with_extension("ll")).unwrap();
     }
 
+    match build_options.output_kind {
+        OutputKind::Assembly => {
+            let assembly_file = if target_metrics.os == Os::Windows {
+                output_path.with_extension("asm")
+            } else {
+                output_path.with_extension("s")
+            };
+
+            time! { build_options.emit_times, "write asm",
+                target_machine
+                    .write_to_file(&module, FileType::Assembly, &assembly_file)
+                    .unwrap()
+            };
+
+            return assembly_file.absolutize().unwrap().to_path_buf();
+        }
+        OutputKind::Bitcode => {
+            let bitcode_file = output_path.with_extension("bc");
+
+            time! { build_options.emit_times, "write bitcode",
+                module.write_bitcode_to_path(&bitcode_file)
+            };
+
+            return bitcode_file.absolutize().unwrap().to_path_buf();
+        }
+        _ => (),
+    }
+
     let object_file = if target_metrics.os == Os::Windows {
         output_path.with_extension("obj")
     } else {
         output_path.with_extension("o")
     };
 
-    let output_file = if target_metrics.os == Os::Windows {
-        output_path.with_extension("exe")
-    } else {
-        output_path.with_extension("")
-    };
-
     time! { build_options.emit_times, "write obj",
         target_machine
             .write_to_file(&module, FileType::Object, &object_file)
             .unwrap()
     };
 
-    time! { build_options.emit_times, "link",
-        link(target_metrics, &output_file, &object_file, &extern_libraries)
-    }
+    let output_file = match build_options.output_kind {
+        OutputKind::Executable => {
+            let executable_file = match target_metrics.os {
+                Os::Windows => output_path.with_extension("exe"),
+                Os::Wasi => output_path.with_extension("wasm"),
+                _ => output_path.with_extension(""),
+            };
+
+            time! { build_options.emit_times, "link",
+                link(build_options, target_metrics, &executable_file, &object_file, &extern_libraries)
+            }
+
+            executable_file
+        }
+        OutputKind::Object => return object_file.absolutize().unwrap().to_path_buf(),
+        OutputKind::Assembly | OutputKind::Bitcode => unreachable!(),
+        OutputKind::StaticLibrary => {
+            let library_file = if target_metrics.os == Os::Windows {
+                output_path.with_extension("lib")
+            } else {
+                output_path.with_extension("a")
+            };
+
+            time! { build_options.emit_times, "archive",
+                archive(target_metrics, &library_file, &object_file)
+            }
 
-    let _ = std::fs::remove_file(object_file);
+            library_file
+        }
+        OutputKind::SharedLibrary => {
+            let library_file = match target_metrics.os {
+                Os::Windows => output_path.with_extension("dll"),
+                Os::Darwin => output_path.with_extension("dylib"),
+                _ => output_path.with_extension("so"),
+            };
+
+            time! { build_options.emit_times, "link",
+                link(build_options, target_metrics, &library_file, &object_file, &extern_libraries)
+            }
+
+            library_file
+        }
+    };
+
+    if !build_options.keep_intermediates {
+        let _ = std::fs::remove_file(object_file);
+    }
 
     output_file.absolutize().unwrap().to_path_buf()
 }
 
 fn link(
+    build_options: &BuildOptions,
     target_metrics: &TargetMetrics,
-    executable_file: &PathBuf,
+    output_file: &PathBuf,
     object_file: &PathBuf,
     extern_libraries: &HashSet<ast::ExternLibrary>,
 ) {
@@ -208,7 +306,12 @@ fn link(
             _ => vec![],
         },
         Arch::Wasm32 | Arch::Wasm64 => {
-            let mut link_flags = vec!["--allow-undefined"];
+            let mut link_flags = vec![];
+
+            // On wasi, extern functions are resolved against wasi-libc, so a missing one is a link error
+            if !matches!(target_metrics.os, Os::Wasi) {
+                link_flags.push("--allow-undefined");
+            }
 
             if matches!(target_metrics.arch, Arch::Wasm64) {
                 link_flags.push("-mwas64");
@@ -239,52 +342,279 @@ fn link(
         }
     }
 
-    #[cfg(windows)]
-    {
-        let find_result = unsafe { microsoft_craziness::find_visual_studio_and_windows_sdk() };
+    lib_paths.extend(
+        build_options
+            .library_search_paths
+            .iter()
+            .map(|path| path.to_str().unwrap().to_string()),
+    );
 
-        if let Some(path) = &find_result.windows_sdk_ucrt_library_path {
-            lib_paths.push(path.to_string().unwrap());
-        }
+    libs.extend(
+        build_options
+            .libraries
+            .iter()
+            .filter(|lib| !is_libc(lib))
+            .map(|lib| library_file_name(target_metrics, lib)),
+    );
 
-        if let Some(path) = &find_result.windows_sdk_um_library_path {
-            lib_paths.push(path.to_string().unwrap());
-        }
+    let is_shared_library = matches!(build_options.output_kind, OutputKind::SharedLibrary);
+    let is_cross_compiling = !build_options.target_platform.is_host();
 
-        if let Some(path) = &find_result.vs_library_path {
-            lib_paths.push(path.to_string().unwrap());
-        }
+    if target_metrics.os == Os::Wasi {
+        // wasi-libc's crt1-command.o defines the module's `_start` entry, which calls the start function
+        let lib_dir = wasi_lib_dir(&find_wasi_sysroot(build_options).unwrap());
 
-        Command::new("lld-link")
-            .arg(format!("/out:{}", executable_file.to_str().unwrap()))
-            .arg("/entry:mainCRTStartup")
-            .arg("/defaultlib:libcmt")
-            .arg("/nologo")
-            .arg("/incremental:no")
-            .arg("/opt:ref")
-            .arg("/threads:8")
-            .arg("/subsystem:CONSOLE")
-            .args(lib_paths.iter().map(|path| format!("/libpath:{}", path)))
+        Command::new("wasm-ld")
+            .arg(lib_dir.join("crt1-command.o").to_str().unwrap())
             .arg(object_file.to_str().unwrap())
-            .args(libs)
+            .arg("-o")
+            .arg(output_file.to_str().unwrap())
+            .arg(format!("-L{}", lib_dir.to_str().unwrap()))
+            .args(lib_paths.iter().map(|path| format!("-L{}", path)))
+            .arg("-lc")
+            .args(libs.iter().map(|path| format!("-l:{}", path)))
             .args(link_flags)
             .execute_output()
             .unwrap();
+
+        return;
+    }
+
+    #[cfg(windows)]
+    {
+        if !is_cross_compiling {
+            let find_result = unsafe { microsoft_craziness::find_visual_studio_and_windows_sdk() };
+
+            if let Some(path) = &find_result.windows_sdk_ucrt_library_path {
+                lib_paths.push(path.to_string().unwrap());
+            }
+
+            if let Some(path) = &find_result.windows_sdk_um_library_path {
+                lib_paths.push(path.to_string().unwrap());
+            }
+
+            if let Some(path) = &find_result.vs_library_path {
+                lib_paths.push(path.to_string().unwrap());
+            }
+
+            Command::new("lld-link")
+                .arg(format!("/out:{}", output_file.to_str().unwrap()))
+                .arg(if is_shared_library {
+                    "/dll"
+                } else {
+                    "/entry:mainCRTStartup"
+                })
+                .arg("/defaultlib:libcmt")
+                .arg("/nologo")
+                .arg("/incremental:no")
+                .arg("/opt:ref")
+                .arg("/threads:8")
+                .arg("/subsystem:CONSOLE")
+                .args(lib_paths.iter().map(|path| format!("/libpath:{}", path)))
+                .arg(object_file.to_str().unwrap())
+                .args(libs)
+                .args(link_flags)
+                .execute_output()
+                .unwrap();
+
+            return;
+        }
     }
 
-    #[cfg(not(windows))]
-    Command::new("clang")
+    let mut command = Command::new("clang");
+
+    command
         .arg("-Wno-unused-command-line-argument")
         .arg(object_file.to_str().unwrap())
-        .arg(format!("-o{}", executable_file.to_str().unwrap()))
+        .arg(format!("-o{}", output_file.to_str().unwrap()))
         .args(lib_paths.iter().map(|path| format!("-L{}", path)))
         .arg("-lc")
         .arg("-lm")
         .args(libs.iter().map(|path| format!("-l:{}", path)))
-        .arg("-no-pie")
-        .args(link_flags)
-        .execute_output()
-        .unwrap();
+        .arg(if is_shared_library { "-shared" } else { "-no-pie" })
+        .args(link_flags);
+
+    if is_cross_compiling {
+        // The host's linker can only link for the host, so lld is used instead
+        command
+            .arg(format!("--target={}", target_metrics.target_triplet))
+            .arg("-fuse-ld=lld");
+
+        if let Some(sysroot) = &build_options.sysroot {
+            command.arg(format!("--sysroot={}", sysroot.to_str().unwrap()));
+        }
+    }
+
+    command.execute_output().unwrap();
+}
+
+/// Checks that the tools needed to link for a foreign target are installed,
+/// so that a missing cross toolchain is reported before the workspace is compiled
+pub fn check_cross_toolchain(build_options: &BuildOptions) -> Result<(), Diagnostic> {
+    let target_platform = &build_options.target_platform;
+
+    let needs_linking = matches!(
+        build_options.output_kind,
+        OutputKind::Executable | OutputKind::SharedLibrary
+    );
+
+    if !needs_linking || target_platform.is_host() {
+        return Ok(());
+    }
+
+    if target_platform == &TargetPlatform::WasiWasm32 {
+        return check_wasi_toolchain(build_options);
+    }
+
+    let target_name = target_platform.name();
+
+    for tool in ["clang", "ld.lld"] {
+        if !is_in_path(tool) {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "cannot link for target `{}`, since `{}` was not found",
+                    target_name, tool
+                ))
+                .with_note("cross-compiling requires `clang` and `ld.lld` to be in your PATH"));
+        }
+    }
+
+    match &build_options.sysroot {
+        Some(sysroot) if !sysroot.is_dir() => Err(Diagnostic::error()
+            .with_message(format!("sysroot `{}` doesn't exist", sysroot.display()))
+            .with_note(format!("the sysroot is used to link for target `{}`", target_name))),
+        Some(_) => Ok(()),
+        None => {
+            if let Some(gnu_triple) = gnu_cross_triple(target_platform) {
+                // Debian-based distributions install GNU cross toolchains into /usr/<triple>,
+                // where clang finds them on its own
+                if Path::new("/usr").join(gnu_triple).is_dir() {
+                    return Ok(());
+                }
+            }
+
+            Err(Diagnostic::error()
+                .with_message(format!("no cross toolchain was found for target `{}`", target_name))
+                .with_note(match gnu_cross_triple(target_platform) {
+                    Some(gnu_triple) => format!(
+                        "install one, such as `gcc-{}`, or pass the target's sysroot with `--sysroot`",
+                        gnu_triple
+                    ),
+                    None => "pass the target's sysroot with `--sysroot`".to_string(),
+                }))
+        }
+    }
+}
+
+fn check_wasi_toolchain(build_options: &BuildOptions) -> Result<(), Diagnostic> {
+    let target_name = build_options.target_platform.name();
+
+    if matches!(build_options.output_kind, OutputKind::SharedLibrary) {
+        return Err(Diagnostic::error()
+            .with_message(format!("cannot build a shared library for target `{}`", target_name))
+            .with_note("build an executable or a static library instead"));
+    }
+
+    if !is_in_path("wasm-ld") {
+        return Err(Diagnostic::error()
+            .with_message(format!(
+                "cannot link for target `{}`, since `wasm-ld` was not found",
+                target_name
+            ))
+            .with_note("`wasm-ld` is installed with lld, and with the wasi-sdk"));
+    }
+
+    if find_wasi_sysroot(build_options).is_none() {
+        let message = match &build_options.sysroot {
+            Some(sysroot) => format!("sysroot `{}` doesn't contain wasi-libc", sysroot.display()),
+            None => format!(
+                "wasi-libc was not found, which is needed to link for target `{}`",
+                target_name
+            ),
+        };
+
+        return Err(Diagnostic::error()
+            .with_message(message)
+            .with_note("install the wasi-sdk into /opt/wasi-sdk, or pass wasi-libc's sysroot with `--sysroot`"));
+    }
+
+    Ok(())
+}
+
+// Where the wasi-sdk and distribution packages install wasi-libc
+const WASI_SYSROOTS: &[&str] = &["/opt/wasi-sdk/share/wasi-sysroot", "/usr/share/wasi-sysroot", "/usr"];
+
+fn find_wasi_sysroot(build_options: &BuildOptions) -> Option<PathBuf> {
+    let is_wasi_sysroot = |sysroot: &Path| wasi_lib_dir(sysroot).join("crt1-command.o").is_file();
+
+    match &build_options.sysroot {
+        Some(sysroot) => is_wasi_sysroot(sysroot).then(|| sysroot.clone()),
+        None => WASI_SYSROOTS
+            .iter()
+            .map(PathBuf::from)
+            .find(|sysroot| is_wasi_sysroot(sysroot)),
+    }
+}
+
+fn wasi_lib_dir(sysroot: &Path) -> PathBuf {
+    sysroot.join("lib").join("wasm32-wasi")
+}
+
+fn gnu_cross_triple(target_platform: &TargetPlatform) -> Option<&'static str> {
+    match target_platform {
+        TargetPlatform::LinuxArm64 => Some("aarch64-linux-gnu"),
+        TargetPlatform::LinuxAmd64 => Some("x86_64-linux-gnu"),
+        TargetPlatform::Linux386 => Some("i686-linux-gnu"),
+        _ => None,
+    }
+}
+
+fn is_in_path(program: &str) -> bool {
+    let program = if cfg!(windows) {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+
+    env::var_os("PATH").map_or(false, |paths| {
+        env::split_paths(&paths).any(|path| path.join(&program).is_file())
+    })
+}
+
+fn archive(target_metrics: &TargetMetrics, library_file: &PathBuf, object_file: &PathBuf) {
+    // Archives are rebuilt from scratch, since `ar` appends to existing ones
+    let _ = std::fs::remove_file(library_file);
+
+    if target_metrics.os == Os::Windows {
+        Command::new("llvm-lib")
+            .arg("/nologo")
+            .arg(format!("/out:{}", library_file.to_str().unwrap()))
+            .arg(object_file.to_str().unwrap())
+            .execute_output()
+            .unwrap();
+    } else {
+        Command::new("llvm-ar")
+            .arg("rcs")
+            .arg(library_file.to_str().unwrap())
+            .arg(object_file.to_str().unwrap())
+            .execute_output()
+            .unwrap();
+    }
+}
+
+// Libraries given by name are resolved to their platform-specific file name, i.e `m` -> `libm.so`
+fn library_file_name(target_metrics: &TargetMetrics, lib: &str) -> String {
+    if Path::new(lib).extension().is_some() {
+        lib.to_string()
+    } else {
+        match target_metrics.os {
+            Os::Windows => format!("{}.lib", lib),
+            Os::Darwin => format!("lib{}.dylib", lib),
+            // WebAssembly modules can only be linked statically
+            Os::Wasi => format!("lib{}.a", lib),
+            _ => format!("lib{}.so", lib),
+        }
+    }
 }
 
 fn is_libc(lib: &str) -> bool {
@@ -1,8 +1,12 @@
 use super::codegen::{FunctionState, Generator};
 use super::ty::IntoLlvmType;
+use crate::hir::{self, const_value::ConstValue};
 use crate::infer::normalize::Normalize;
 use crate::span::Span;
-use inkwell::values::{BasicValue, BasicValueEnum, IntValue};
+use crate::types::FunctionType;
+use inkwell::values::{BasicValue, BasicValueEnum, FunctionValue, IntValue};
+
+const DEFAULT_PANIC_FUNCTION_NAME: &str = "std.panicking.panic";
 
 impl<'g, 'ctx> Generator<'g, 'ctx> {
     #[allow(unused)]
@@ -12,9 +16,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
     }
 
     pub(super) fn gen_panic(&mut self, state: &mut FunctionState<'ctx>, message: BasicValueEnum<'ctx>, span: Span) {
-        let panic_fn = self.find_decl_by_name("std.panicking", "panic").into_function_value();
-        let panic_binding_info = self.find_binding_info_by_name("std.panicking", "panic");
-        let panic_fn_type = panic_binding_info.ty.clone().normalize(self.tcx).into_function();
+        let (panic_fn, panic_fn_type) = self.gen_panic_function(state);
 
         let location_llvm_type = panic_fn_type.params[0].ty.llvm_type(self);
 
@@ -39,6 +41,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
             &panic_fn_type,
             vec![location, message],
             &panic_fn_type.return_type,
+            span,
         );
 
         self.build_unreachable();
@@ -65,4 +68,56 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
         self.start_block(state, no_panic_block);
     }
+
+    /// Returns the workspace's panic handler, or `std.panicking.panic` if it doesn't define one.
+    /// Failed runtime checks in the handler itself call `std.panicking.panic`, so they don't recurse into it
+    fn gen_panic_function(&mut self, state: &FunctionState<'ctx>) -> (FunctionValue<'ctx>, FunctionType) {
+        let cache = self.cache;
+
+        match cache.panic_handler() {
+            Some(handler) if !self.is_panic_handler(state) => {
+                let handler_type = handler.ty.normalize(self.tcx).into_function();
+                (self.gen_function(handler.id, None), handler_type)
+            }
+            _ => {
+                let panic_binding_info = self.find_binding_info_by_name("std.panicking", "panic");
+                let panic_fn_ty = panic_binding_info.ty;
+
+                let panic_fn_id = match &panic_binding_info.const_value {
+                    Some(ConstValue::Function(function)) => function.id,
+                    const_value => panic!("expected `std.panicking.panic` to be a function, got {:?}", const_value),
+                };
+
+                let panic_fn_type = panic_fn_ty.normalize(self.tcx).into_function();
+
+                (self.gen_function(panic_fn_id, Some(state.clone())), panic_fn_type)
+            }
+        }
+    }
+
+    /// Calls to `std.panicking.panic` are redirected to the workspace's panic handler, if it defines one.
+    /// Calls made by the handler itself aren't, so that it can forward to the default panic behavior.
+    /// Panics in functions that the handler calls still reach it.
+    pub(super) fn redirect_to_panic_handler(
+        &self,
+        id: hir::FunctionId,
+        state: Option<&FunctionState<'ctx>>,
+    ) -> hir::FunctionId {
+        match self.cache.panic_handler_function_id {
+            Some(handler_id)
+                if self.cache.functions.get(id).unwrap().qualified_name == DEFAULT_PANIC_FUNCTION_NAME
+                    && !state.map_or(false, |state| self.is_panic_handler(state)) =>
+            {
+                handler_id
+            }
+            _ => id,
+        }
+    }
+
+    fn is_panic_handler(&self, state: &FunctionState<'ctx>) -> bool {
+        self.cache
+            .panic_handler_function_id
+            .and_then(|id| self.functions.get(&id))
+            .map_or(false, |handler| *handler == state.function)
+    }
 }
@@ -5,9 +5,9 @@ use inkwell::{
     IntPredicate,
 };
 
-macro_rules! release_guard {
-    ($generator: expr) => {{
-        if $generator.workspace.build_options.optimization_level.is_release() {
+macro_rules! runtime_check_guard {
+    ($state: expr, $check: ident) => {{
+        if !$state.runtime_checks.$check {
             return;
         }
     }};
@@ -20,7 +20,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         divisor: IntValue<'ctx>,
         span: Span,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, division_by_zero);
 
         const NAME: &str = "__runtime_check_division_by_zero";
         let condition = self
@@ -36,7 +36,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         ptr: PointerValue<'ctx>,
         span: Span,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, null_pointer_dereference);
 
         const NAME: &str = "__runtime_check_null_pointer_dereference";
         let condition = self.builder.build_is_null(ptr, "");
@@ -53,7 +53,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         span: Span,
         op: &str,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, overflow);
 
         let name = format!("__runtime_check_overflow_{}", op);
         let message = self
@@ -69,7 +69,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         len: IntValue<'ctx>,
         span: Span,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, index_out_of_bounds);
 
         const NAME: &str = "__runtime_check_index_out_of_bounds";
 
@@ -95,7 +95,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         high: IntValue<'ctx>,
         span: Span,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, slice_range);
 
         const NAME: &str = "__runtime_check_slice_end_before_start";
 
@@ -116,7 +116,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         len: IntValue<'ctx>,
         span: Span,
     ) {
-        release_guard!(self);
+        runtime_check_guard!(state, slice_range);
 
         const NAME: &str = "__runtime_check_slice_range_out_of_bounds";
 
@@ -1,6 +1,12 @@
 use super::codegen::{FunctionState, Generator};
-use crate::{infer::normalize::Normalize, types::*};
-use inkwell::{module::Linkage, values::BasicValue, AddressSpace};
+use super::ty::IntoLlvmType;
+use crate::{infer::normalize::Normalize, span::Span, types::*};
+use inkwell::{
+    module::Linkage,
+    types::BasicType,
+    values::{BasicValue, BasicValueEnum, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
+};
 use ustr::ustr;
 
 impl<'g, 'ctx> Generator<'g, 'ctx> {
@@ -77,6 +83,8 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
             linkage,
         );
 
+        self.add_unwind_table_attr(function);
+
         function.get_nth_param(0).unwrap().set_name("argc");
         function.get_nth_param(1).unwrap().set_name("argv");
 
@@ -86,6 +94,7 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
         let root_module_info = self.workspace.get_root_module_info();
 
         let mut state = FunctionState::new(
+            self.workspace.build_options.runtime_checks,
             *root_module_info,
             function,
             startup_fn_type,
@@ -110,18 +119,34 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
         let entry_point_function_type = entry_point_function.ty.normalize(self.tcx).into_function();
 
-        self.gen_function_call(
+        // The entry point function either takes no arguments, or the program's arguments as *[]*str
+        let args = if entry_point_function_type.params.is_empty() {
+            vec![]
+        } else {
+            let argc = function.get_nth_param(0).unwrap().into_int_value();
+            let argv = function.get_nth_param(1).unwrap().into_pointer_value();
+            vec![self.gen_program_args(&mut state, argc, argv)]
+        };
+
+        let return_value = self.gen_function_call(
             &mut state,
             entry_point_function_value,
             &entry_point_function_type,
-            vec![],
+            args,
             &entry_point_function_type.return_type,
+            Span::unknown(),
         );
 
         // TODO: if this is DLL Main, return 1 instead of 0
 
         if self.current_block().get_terminator().is_none() {
-            self.builder.build_return(Some(&self.context.i32_type().const_zero()));
+            // An entry point function returning i32 decides the process' exit code
+            let exit_code = match entry_point_function_type.return_type.as_ref() {
+                Type::Int(IntType::I32) => return_value.into_int_value(),
+                _ => self.context.i32_type().const_zero(),
+            };
+
+            self.builder.build_return(Some(&exit_code));
         }
 
         self.start_block(&mut state, decl_block);
@@ -130,4 +155,77 @@ impl<'g, 'ctx> Generator<'g, 'ctx> {
 
         self.builder.build_unconditional_branch(entry_block);
     }
+
+    /// Builds a `*[]*str` from the C `argc` and `argv` arguments
+    fn gen_program_args(
+        &mut self,
+        state: &mut FunctionState<'ctx>,
+        argc: IntValue<'ctx>,
+        argv: PointerValue<'ctx>,
+    ) -> BasicValueEnum<'ctx> {
+        // A slice pointer points to the slice's data pointer and length
+        let str_type = Type::str().llvm_type(self);
+        let args_type = Type::Slice(Box::new(Type::str_pointer())).llvm_type(self);
+
+        let strlen = self.get_or_add_function(
+            "strlen",
+            self.ptr_sized_int_type
+                .fn_type(&[self.raw_pointer_type().into()], false),
+            Some(Linkage::External),
+        );
+
+        let len = self
+            .builder
+            .build_int_z_extend_or_bit_cast(argc, self.ptr_sized_int_type, "args_len");
+
+        let strs = self.builder.build_array_alloca(str_type, len, "args_strs");
+        let str_ptrs = self
+            .builder
+            .build_array_alloca(str_type.ptr_type(AddressSpace::Generic), len, "args_str_ptrs");
+
+        let index_ptr = self.build_alloca(state, self.ptr_sized_int_type.into());
+        self.build_store(index_ptr, self.ptr_sized_int_type.const_zero().into());
+
+        let loop_head = self.append_basic_block(state, "args_loop_head");
+        let loop_body = self.append_basic_block(state, "args_loop_body");
+        let loop_exit = self.append_basic_block(state, "args_loop_exit");
+
+        self.builder.build_unconditional_branch(loop_head);
+        self.start_block(state, loop_head);
+
+        let index = self.build_load(index_ptr, "index").into_int_value();
+        let condition = self.builder.build_int_compare(IntPredicate::ULT, index, len, "");
+        self.builder.build_conditional_branch(condition, loop_body, loop_exit);
+
+        self.start_block(state, loop_body);
+
+        let arg = unsafe { self.builder.build_in_bounds_gep(argv, &[index], "") };
+        let arg = self.build_load(arg, "arg");
+
+        let arg_len = self
+            .builder
+            .build_call(strlen, &[arg.into()], "arg_len")
+            .try_as_basic_value()
+            .left()
+            .unwrap();
+
+        let str_ptr = unsafe { self.builder.build_in_bounds_gep(strs, &[index], "") };
+        self.build_store(self.builder.build_struct_gep(str_ptr, 0, "").unwrap(), arg);
+        self.build_store(self.builder.build_struct_gep(str_ptr, 1, "").unwrap(), arg_len);
+
+        let str_ptr_ptr = unsafe { self.builder.build_in_bounds_gep(str_ptrs, &[index], "") };
+        self.build_store(str_ptr_ptr, str_ptr.into());
+
+        let next_index = self
+            .builder
+            .build_int_add(index, self.ptr_sized_int_type.const_int(1, false), "");
+        self.build_store(index_ptr, next_index.into());
+
+        self.builder.build_unconditional_branch(loop_head);
+
+        self.start_block(state, loop_exit);
+
+        self.build_struct(state, args_type, &[str_ptrs.into(), len.into()])
+            .into()
+    }
 }
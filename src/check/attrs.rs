@@ -78,7 +78,9 @@ impl<'s> CheckSess<'s> {
 
     fn get_attr_expected_type(&self, kind: AttrKind) -> TypeId {
         match kind {
-            AttrKind::Intrinsic | AttrKind::TrackCaller => self.tcx.common_types.unit,
+            AttrKind::Intrinsic | AttrKind::TrackCaller | AttrKind::NoRuntimeChecks | AttrKind::PanicHandler => {
+                self.tcx.common_types.unit
+            }
             AttrKind::Lib | AttrKind::Dylib | AttrKind::LinkName => self.tcx.common_types.str_pointer,
         }
     }
@@ -109,7 +111,7 @@ impl<'s> CheckSess<'s> {
                         ))
                     }
                 },
-                AttrKind::TrackCaller => match &binding.kind {
+                AttrKind::TrackCaller | AttrKind::NoRuntimeChecks | AttrKind::PanicHandler => match &binding.kind {
                     ast::BindingKind::Function { .. } => (),
                     _ => return Err(invalid_attr_use(attr, "can only be used on functions")),
                 },
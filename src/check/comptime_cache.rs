@@ -0,0 +1,30 @@
+use super::CheckSess;
+use crate::{
+    span::{FileId, Span},
+    workspace::ModuleId,
+};
+
+impl<'s> CheckSess<'s> {
+    /// Records that code in `module_id` refers to `dependency`,
+    /// so that editing `dependency` invalidates the cached evaluations of `module_id`
+    pub(super) fn add_module_dependency(&mut self, module_id: ModuleId, dependency: ModuleId) {
+        if let Some(cache) = &mut self.interp.comptime_cache {
+            cache.add_module_dependency(module_id, dependency);
+        }
+    }
+
+    pub(super) fn add_injected_file(&mut self, module_id: ModuleId, file_id: FileId) {
+        if let Some(cache) = &mut self.interp.comptime_cache {
+            cache.add_injected_file(module_id, file_id);
+        }
+    }
+
+    /// The key of the evaluation at `span` in the comptime cache, which must be enabled
+    pub(super) fn comptime_cache_key(&mut self, module_id: ModuleId, span: Span) -> u64 {
+        self.interp
+            .comptime_cache
+            .as_mut()
+            .unwrap()
+            .key(self.workspace, module_id, span)
+    }
+}
@@ -3,6 +3,7 @@ use crate::{
     error::diagnostic::{Diagnostic, Label},
     infer::{display::DisplayType, normalize::Normalize},
     span::Span,
+    types::Type,
 };
 
 impl<'s> CheckSess<'s> {
@@ -11,11 +12,18 @@ impl<'s> CheckSess<'s> {
             if let Some(function) = self.cache.entry_point_function() {
                 let ty = function.ty.normalize(&self.tcx).into_function();
 
-                // Validate its type is fn() -> ()
-                if !(ty.return_type.is_unit() || ty.return_type.is_never())
-                    || !ty.params.is_empty()
-                    || ty.has_c_varargs()
-                {
+                // Validate its type is fn() -> (), optionally taking the program's arguments as *[]*str,
+                // and optionally returning the process' exit code as i32
+                let is_valid_return_type =
+                    ty.return_type.is_unit() || ty.return_type.is_never() || *ty.return_type == Type::i32();
+
+                let is_valid_params = match ty.params.as_slice() {
+                    [] => true,
+                    [args] => args.ty == Type::slice_pointer(Type::str_pointer(), false),
+                    _ => false,
+                };
+
+                if !is_valid_return_type || !is_valid_params || ty.has_c_varargs() {
                     self.workspace.diagnostics.push(
                         Diagnostic::error()
                             .with_message(format!(
@@ -23,7 +31,10 @@ impl<'s> CheckSess<'s> {
                                 function.name,
                                 ty.display(&self.tcx)
                             ))
-                            .with_label(Label::primary(function.span, "invalid entry point function type")),
+                            .with_label(Label::primary(function.span, "invalid entry point function type"))
+                            .with_note(
+                                "the entry point function may also take `args: *[]*str`, and return an `i32` exit code",
+                            ),
                     )
                 }
             } else {
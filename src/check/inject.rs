@@ -0,0 +1,109 @@
+use super::{top_level::CheckTopLevel, CheckResult, CheckSess};
+use crate::{
+    ast,
+    astgen::generate_injected_ast,
+    error::diagnostic::{Diagnostic, Label},
+    interp::interp::InjectedBindings,
+    workspace::ModuleId,
+};
+use std::mem;
+use ustr::Ustr;
+
+impl<'s> CheckSess<'s> {
+    /// Parses and checks the bindings injected by the last compile-time evaluation, adding them to `module_id`
+    pub(super) fn check_injected_bindings(&mut self, module_id: ModuleId) -> CheckResult<()> {
+        for injected in mem::take(&mut self.interp.injected_bindings) {
+            let span = injected.span;
+
+            self.inject_bindings(injected, module_id)
+                .map_err(|diag| diag.with_label(Label::secondary(span, "injected here")))?;
+        }
+
+        Ok(())
+    }
+
+    fn inject_bindings(&mut self, injected: InjectedBindings, module_id: ModuleId) -> CheckResult<()> {
+        let module_info = *self.workspace.module_infos.get(module_id).unwrap();
+        let module_path = self.workspace.module_path(module_id);
+
+        // The injected source is treated as if it was written in a virtual file, so that spans can point into it
+        self.injected_sources_count += 1;
+        let name = format!("<generated:{}:{}>", module_info.name, self.injected_sources_count);
+
+        let error_count = self.workspace.diagnostics.error_count();
+
+        let (injected_module, imported_modules) =
+            generate_injected_ast(self.workspace, module_path, name, &injected.source)?;
+
+        if self.workspace.diagnostics.error_count() > error_count {
+            return Err(Diagnostic::error().with_message("failed to parse the modules imported by injected bindings"));
+        }
+
+        self.add_injected_file(module_id, injected_module.file_id);
+
+        // Imported modules need to live as long as the session
+        for module in imported_modules {
+            self.modules.push(Box::leak(Box::new(module)));
+        }
+
+        // The injected bindings are indexed after the module's existing bindings, so that their indices stay the same.
+        // This lets injected bindings refer to each other, regardless of their order.
+        let first_injected_index = self.injected_bindings_end(module_id);
+
+        let injected_module: &'s ast::Module = Box::leak(Box::new(injected_module));
+
+        self.injected_modules
+            .entry(module_id)
+            .or_default()
+            .push(injected_module);
+
+        for (index, binding) in injected_module.bindings.iter().enumerate() {
+            if self
+                .queued_modules
+                .get_mut(&module_id)
+                .unwrap()
+                .queued_bindings
+                .insert(first_injected_index + index)
+            {
+                binding.check_top_level(self, module_id)?;
+            }
+        }
+
+        for r#static in injected_module.comptime_blocks.iter() {
+            self.check_comptime_block(r#static, module_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finds a binding in `module`, or in the bindings injected into it
+    pub(super) fn find_module_binding(&self, module: &'s ast::Module, name: Ustr) -> Option<(usize, &'s ast::Binding)> {
+        if let Some(found) = module.find_binding(name) {
+            return Some(found);
+        }
+
+        let mut first_index = module.bindings.len();
+
+        for injected_module in self.injected_modules.get(&module.id)?.iter().copied() {
+            if let Some((index, binding)) = injected_module.find_binding(name) {
+                return Some((first_index + index, binding));
+            }
+
+            first_index += injected_module.bindings.len();
+        }
+
+        None
+    }
+
+    // The index after the last binding of the module, including injected bindings
+    fn injected_bindings_end(&self, module_id: ModuleId) -> usize {
+        let module = self.modules.iter().find(|m| m.id == module_id).unwrap();
+
+        let injected_count: usize = self
+            .injected_modules
+            .get(&module_id)
+            .map_or(0, |modules| modules.iter().map(|m| m.bindings.len()).sum());
+
+        module.bindings.len() + injected_count
+    }
+}
@@ -1,9 +1,13 @@
 mod attrs;
+mod comptime_cache;
 mod const_fold;
 mod entry;
 mod env;
+mod inject;
 mod lvalue_access;
+mod panic_handler;
 mod pattern;
+pub mod repl;
 pub mod symbols;
 mod top_level;
 
@@ -33,7 +37,7 @@ use crate::{
         type_ctx::TypeCtx,
         unify::{occurs, UnifyType, UnifyTypeErr},
     },
-    interp::interp::Interp,
+    interp::{interp::Interp, vm::value::Value, TargetWordSizeGuard},
     span::Span,
     types::{
         align_of::AlignOf, is_sized::IsSized, size_of::SizeOf, FunctionType, FunctionTypeKind, FunctionTypeParam,
@@ -55,7 +59,7 @@ use ustr::{ustr, Ustr, UstrMap, UstrSet};
 pub type CheckData = (hir::Cache, TypeCtx);
 
 pub fn check(workspace: &mut Workspace, module: Vec<ast::Module>) -> CheckData {
-    let mut sess = CheckSess::new(workspace, &module);
+    let mut sess = CheckSess::new(workspace, module.iter().collect());
 
     if let Err(diag) = sess.start() {
         sess.workspace.diagnostics.push(diag);
@@ -75,6 +79,7 @@ pub(super) struct QueuedModule {
     pub(super) module_type: TypeId,
     pub(super) all_complete: bool,
     pub(super) queued_bindings: HashSet<usize>, // Binding index -> Completion status
+    pub(super) comptime_blocks_checked: bool,
 }
 
 pub(super) struct CheckSess<'s> {
@@ -86,7 +91,7 @@ pub(super) struct CheckSess<'s> {
     pub tcx: TypeCtx,
 
     // The ast's being processed
-    pub modules: &'s [ast::Module],
+    pub modules: Vec<&'s ast::Module>,
 
     pub cache: hir::Cache,
     pub queued_modules: HashMap<ModuleId, QueuedModule>,
@@ -110,6 +115,13 @@ pub(super) struct CheckSess<'s> {
 
     // A stack of encountered items. Used to detect global bindings that refer themselves
     pub encountered_items: HashSet<(ModuleId, usize)>,
+
+    // The number of sources injected by `inject_bindings`. Used to name their virtual files
+    pub injected_sources_count: usize,
+
+    // The bindings injected into each module by `inject_bindings`, in injection order.
+    // Their indices continue after the module's own bindings
+    pub injected_modules: HashMap<ModuleId, Vec<&'s ast::Module>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -120,7 +132,7 @@ pub struct FunctionFrame {
 }
 
 impl<'s> CheckSess<'s> {
-    pub fn new(workspace: &'s mut Workspace, old_asts: &'s Vec<ast::Module>) -> Self {
+    pub fn new(workspace: &'s mut Workspace, modules: Vec<&'s ast::Module>) -> Self {
         let target_metrics = workspace.build_options.target_platform.metrics();
         let interp = Interp::new(workspace.build_options.clone());
 
@@ -129,7 +141,7 @@ impl<'s> CheckSess<'s> {
             target_metrics,
             interp,
             tcx: TypeCtx::default(),
-            modules: old_asts,
+            modules,
             cache: hir::Cache::new(),
             queued_modules: HashMap::new(),
             global_scopes: HashMap::new(),
@@ -139,6 +151,8 @@ impl<'s> CheckSess<'s> {
             unique_name_indices: UstrMap::default(),
             in_lvalue_context: false,
             encountered_items: HashSet::new(),
+            injected_sources_count: 0,
+            injected_modules: HashMap::new(),
         }
     }
 
@@ -172,9 +186,15 @@ impl<'s> CheckSess<'s> {
     }
 
     fn check_library(&mut self, library_id: LibraryId) -> CheckResult<()> {
-        self.modules
+        let modules: Vec<&ast::Module> = self
+            .modules
             .iter()
+            .copied()
             .filter(|module| module.info.library_id == library_id)
+            .collect();
+
+        modules
+            .into_iter()
             .try_for_each(|module| self.check_module(module).map(|_| ()))
     }
 
@@ -187,6 +207,12 @@ impl<'s> CheckSess<'s> {
     }
 
     fn into_data(self) -> CheckData {
+        if let Some(cache) = &self.interp.comptime_cache {
+            // entries of code that wasn't reached because of errors are kept for the next build
+            cache.save(!self.workspace.diagnostics.has_errors());
+        }
+
+        self.workspace.comptime_profile = self.interp.profiler;
         (self.cache, self.tcx)
     }
 
@@ -272,27 +298,63 @@ impl<'s> CheckSess<'s> {
         } else {
             let ty = node.ty().normalize(&self.tcx);
 
-            let eval_result = self
+            let cache_key = self
                 .interp
-                .create_session(self.workspace, &self.tcx, &self.cache)
-                .eval(node, module_id);
-
-            match eval_result {
-                Ok(value) => match value.try_into_const_value(&mut self.tcx, &ty, eval_span) {
-                    Ok(const_value) => Ok(const_value),
-                    Err(value_str) => Err(Diagnostic::error()
-                        .with_message(format!("compile-time evaluation cannot result in `{}`", value_str))
-                        .with_label(Label::primary(eval_span, "evaluated here"))),
-                },
-                Err(mut diagnostics) => {
-                    let last = diagnostics.pop().unwrap();
-                    self.workspace.diagnostics.extend(diagnostics);
-                    Err(last)
+                .comptime_cache
+                .is_some()
+                .then(|| self.comptime_cache_key(module_id, eval_span));
+
+            if let (Some(cache), Some(key)) = (&mut self.interp.comptime_cache, cache_key) {
+                if let Some(const_value) = cache.get(key, &ty, &mut self.tcx, eval_span) {
+                    return Ok(const_value);
                 }
             }
+
+            self.interp.has_side_effects = false;
+
+            let value = self.eval_value(node, module_id)?;
+
+            match value.try_into_const_value(&mut self.tcx, &ty, eval_span) {
+                Ok(const_value) => {
+                    if let (Some(cache), Some(key)) = (&mut self.interp.comptime_cache, cache_key) {
+                        if !self.interp.has_side_effects {
+                            cache.insert(key, &const_value);
+                        }
+                    }
+
+                    Ok(const_value)
+                }
+                Err(value_str) => Err(Diagnostic::error()
+                    .with_message(format!("compile-time evaluation cannot result in `{}`", value_str))
+                    .with_label(Label::primary(eval_span, "evaluated here"))),
+            }
         }
     }
 
+    pub(super) fn eval_value(&mut self, node: &hir::Node, module_id: ModuleId) -> CheckResult<Value> {
+        // The VM evaluates integers, pointers and layouts with the target's word size
+        let _word_size_guard = TargetWordSizeGuard::new(self.target_metrics.word_size);
+
+        let value = self
+            .interp
+            .create_session(self.workspace, &self.tcx, &self.cache)
+            .eval(node, module_id)
+            .map_err(|mut diagnostics| {
+                let last = diagnostics.pop().unwrap();
+                self.workspace.diagnostics.extend(diagnostics);
+                last
+            })?;
+
+        if !self.interp.injected_bindings.is_empty() {
+            self.check_injected_bindings(module_id)?;
+
+            // evaluations of the injected code reset this, but injecting bindings is a side effect by itself
+            self.interp.has_side_effects = true;
+        }
+
+        Ok(value)
+    }
+
     pub(super) fn get_type_by_name(
         &mut self,
         module_name: &str,
@@ -564,6 +626,18 @@ impl Check for ast::Binding {
                     },
                 )?;
 
+                if attrs.has(AttrKind::NoRuntimeChecks) {
+                    if let Some(ConstValue::Function(f)) = node.as_const_value() {
+                        sess.cache.functions.get_mut(f.id).unwrap().no_runtime_checks = true;
+                    }
+                }
+
+                if let Some(attr) = attrs.get(AttrKind::PanicHandler) {
+                    if let Some(ConstValue::Function(f)) = node.as_const_value() {
+                        sess.check_panic_handler(f.id, attr.span)?;
+                    }
+                }
+
                 // If this function binding matches the entry point function's requirements, Tag it as the entry function
                 // Requirements:
                 // - Is declared in the root module
@@ -629,10 +703,14 @@ impl Check for ast::Binding {
 
                             match intrinsic {
                                 hir::Intrinsic::StartWorkspace
+                                | hir::Intrinsic::InjectBindings
+                                | hir::Intrinsic::SymbolTable
                                 | hir::Intrinsic::Location
                                 | hir::Intrinsic::CallerLocation
                                 | hir::Intrinsic::CompilerError
-                                | hir::Intrinsic::CompilerWarning => (
+                                | hir::Intrinsic::CompilerWarning
+                                | hir::Intrinsic::IsDefined
+                                | hir::Intrinsic::GetDefine => (
                                     get_qualified_name(env.scope_name(), name),
                                     hir::FunctionKind::Intrinsic(intrinsic),
                                     BindingInfoKind::Intrinsic(intrinsic),
@@ -672,6 +750,7 @@ impl Check for ast::Binding {
                     kind: function_kind,
                     ty,
                     span: self.span,
+                    no_runtime_checks: false,
                 });
 
                 let function_value = hir::Node::Const(hir::Const {
@@ -731,10 +810,14 @@ impl Check for ast::Binding {
                                     sess.tcx.common_types.uint,
                                 ),
                                 hir::Intrinsic::StartWorkspace
+                                | hir::Intrinsic::InjectBindings
+                                | hir::Intrinsic::SymbolTable
                                 | hir::Intrinsic::Location
                                 | hir::Intrinsic::CallerLocation
                                 | hir::Intrinsic::CompilerError
-                                | hir::Intrinsic::CompilerWarning => {
+                                | hir::Intrinsic::CompilerWarning
+                                | hir::Intrinsic::IsDefined
+                                | hir::Intrinsic::GetDefine => {
                                     return Err(Diagnostic::error()
                                         .with_message(format!("intrinsic name `{}` is reserved for a function", name))
                                         .with_label(Label::primary(span, "intrinsic is a function")));
@@ -882,9 +965,12 @@ impl Check for ast::Ast {
                 let module = sess
                     .modules
                     .iter()
+                    .copied()
                     .find(|m| m.info.file_path == import_path)
                     .unwrap_or_else(|| panic!("couldn't find ast for module with path: {}", import_path));
 
+                sess.add_module_dependency(env.module_id(), module.id);
+
                 let module_type = sess.check_module(module)?;
 
                 Ok(hir::Node::Const(hir::Const {
@@ -2638,8 +2724,14 @@ impl Check for ast::Call {
                         hir::Intrinsic::Location
                         | hir::Intrinsic::CallerLocation
                         | hir::Intrinsic::CompilerError
-                        | hir::Intrinsic::CompilerWarning => Some(*intrinsic),
-                        hir::Intrinsic::StartWorkspace | hir::Intrinsic::Os | hir::Intrinsic::Arch => None,
+                        | hir::Intrinsic::CompilerWarning
+                        | hir::Intrinsic::IsDefined
+                        | hir::Intrinsic::GetDefine => Some(*intrinsic),
+                        hir::Intrinsic::StartWorkspace
+                        | hir::Intrinsic::InjectBindings
+                        | hir::Intrinsic::SymbolTable
+                        | hir::Intrinsic::Os
+                        | hir::Intrinsic::Arch => None,
                     },
                     _ => None,
                 }
@@ -3023,7 +3115,43 @@ impl Check for ast::Call {
                                     .with_label(Label::primary(first_arg.span(), "not a string literal")))
                             }
                         }
-                        hir::Intrinsic::StartWorkspace | hir::Intrinsic::Os | hir::Intrinsic::Arch => unreachable!(),
+                        hir::Intrinsic::IsDefined | hir::Intrinsic::GetDefine => {
+                            let first_arg = args.first().unwrap();
+
+                            let name = if let Some(ConstValue::Str(name)) = first_arg.as_const_value() {
+                                *name
+                            } else {
+                                return Err(Diagnostic::error()
+                                    .with_message("argument `name` must be a string literal")
+                                    .with_label(Label::primary(first_arg.span(), "not a string literal")));
+                            };
+
+                            let define = sess.workspace.build_options.defines.get(name.as_str());
+
+                            let value = match intrinsic {
+                                hir::Intrinsic::IsDefined => ConstValue::Bool(define.is_some()),
+                                _ => match define {
+                                    Some(value) => ConstValue::Str(ustr(value)),
+                                    None => {
+                                        return Err(Diagnostic::error()
+                                            .with_message(format!("`{}` is not defined", name))
+                                            .with_label(Label::primary(first_arg.span(), "undefined"))
+                                            .with_note("use `is_defined` to check whether a name is defined"))
+                                    }
+                                },
+                            };
+
+                            Ok(hir::Node::Const(hir::Const {
+                                value,
+                                ty,
+                                span: self.span,
+                            }))
+                        }
+                        hir::Intrinsic::StartWorkspace
+                        | hir::Intrinsic::InjectBindings
+                        | hir::Intrinsic::SymbolTable
+                        | hir::Intrinsic::Os
+                        | hir::Intrinsic::Arch => unreachable!(),
                     }
                 } else {
                     Ok(hir::Node::Call(hir::Call {
@@ -3739,6 +3867,7 @@ fn check_function<'s>(
         },
         ty: sig_type,
         span,
+        no_runtime_checks: false,
     });
 
     env.insert_function(name, function_id);
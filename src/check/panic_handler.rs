@@ -0,0 +1,54 @@
+use super::CheckSess;
+use crate::{
+    error::{
+        diagnostic::{Diagnostic, Label},
+        DiagnosticResult,
+    },
+    hir,
+    infer::{display::DisplayType, normalize::Normalize},
+    span::Span,
+    types::Type,
+};
+
+impl<'s> CheckSess<'s> {
+    /// Installs `function_id` as the workspace's panic handler, which replaces `std.panicking.panic` in generated code
+    pub(super) fn check_panic_handler(
+        &mut self,
+        function_id: hir::FunctionId,
+        attr_span: Span,
+    ) -> DiagnosticResult<()> {
+        if let Some(handler) = self.cache.panic_handler() {
+            return Err(Diagnostic::error()
+                .with_message("a panic handler is already defined in this workspace")
+                .with_label(Label::primary(attr_span, "duplicate panic handler"))
+                .with_label(Label::secondary(handler.span, "first defined here")));
+        }
+
+        let location_type = self.location_type()?.normalize(&self.tcx);
+
+        let function = self.cache.functions.get(function_id).unwrap();
+        let ty = function.ty.normalize(&self.tcx).into_function();
+
+        // Validate its type is fn(Location, *str) -> never, which is `std.panicking.panic`'s type
+        let is_valid = ty.params.len() == 2
+            && ty.params[0].ty == location_type
+            && ty.params[1].ty == Type::str_pointer()
+            && ty.return_type.is_never()
+            && ty.varargs.is_none();
+
+        if !is_valid {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "panic handler `{}` has type `{}`, expected `fn(Location, *str) -> never`",
+                    function.name,
+                    ty.display(&self.tcx)
+                ))
+                .with_label(Label::primary(function.span, "invalid panic handler type"))
+                .with_note("a function annotated with `track_caller` receives the `Location` implicitly"));
+        }
+
+        self.cache.panic_handler_function_id = Some(function_id);
+
+        Ok(())
+    }
+}
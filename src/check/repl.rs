@@ -0,0 +1,70 @@
+use super::{env::ScopeKind, top_level::CheckTopLevel, Check, CheckResult, CheckSess};
+use crate::{
+    ast,
+    hir::const_value::ConstValue,
+    infer::{normalize::Normalize, substitute::substitute_node, type_ctx::TypeCtx},
+    interp::vm::value::Value,
+    types::TypeId,
+    workspace::ModuleId,
+};
+
+// The result of an expression evaluated in the repl.
+// Values that can't be represented as constants, such as pointers, are kept as VM values.
+pub enum ReplValue {
+    Const(ConstValue),
+    Value(Value),
+}
+
+impl ReplValue {
+    pub fn display(&self, tcx: &TypeCtx) -> String {
+        match self {
+            ReplValue::Const(value) => value.display(tcx),
+            ReplValue::Value(value) => value.to_string(),
+        }
+    }
+}
+
+impl<'s> CheckSess<'s> {
+    /// Makes `module` available to imports entered in the repl
+    pub fn add_repl_module(&mut self, module: &'s ast::Module) {
+        self.modules.push(module);
+    }
+
+    pub fn check_repl_binding(&mut self, binding: &ast::Binding, module_id: ModuleId) -> CheckResult<()> {
+        // bindings entered in the repl shadow previous bindings with the same name
+        if let Some(scope) = self.global_scopes.get_mut(&module_id) {
+            scope.bindings.retain(|name, _| !binding.binds_name(*name));
+        }
+
+        binding.check_top_level(self, module_id)?;
+
+        Ok(())
+    }
+
+    pub fn eval_repl_expr(&mut self, expr: &ast::Ast, module_id: ModuleId) -> CheckResult<(ReplValue, TypeId)> {
+        let node = self.with_env(module_id, |sess, mut env| {
+            env.with_scope(ScopeKind::Block, |mut env| expr.check(sess, &mut env, None))
+        })?;
+
+        if let Err(mut diagnostics) = substitute_node(&node, &mut self.tcx) {
+            let last = diagnostics.pop().unwrap();
+            self.workspace.diagnostics.extend(diagnostics);
+            return Err(last);
+        }
+
+        let value = match node.as_const_value() {
+            Some(const_value) => ReplValue::Const(const_value.clone()),
+            None => {
+                let ty = node.ty().normalize(&self.tcx);
+                let value = self.eval_value(&node, module_id)?;
+
+                match value.clone().try_into_const_value(&mut self.tcx, &ty, node.span()) {
+                    Ok(const_value) => ReplValue::Const(const_value),
+                    Err(_) => ReplValue::Value(value),
+                }
+            }
+        };
+
+        Ok((value, node.ty()))
+    }
+}
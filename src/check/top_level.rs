@@ -11,7 +11,7 @@ use crate::{
 use std::collections::HashSet;
 use ustr::{Ustr, UstrMap};
 
-trait CheckTopLevel
+pub(super) trait CheckTopLevel
 where
     Self: Sized,
 {
@@ -71,6 +71,7 @@ impl<'s> CheckSess<'s> {
             let module = self
                 .modules
                 .iter()
+                .copied()
                 .find(|m| m.id == module_id)
                 .unwrap_or_else(|| panic!("{:?}", module_id));
 
@@ -86,6 +87,9 @@ impl<'s> CheckSess<'s> {
                         .find(|(_, library)| library.name == name);
 
                     if let Some((_, library)) = find_library_result {
+                        let root_module_id = library.root_module_id;
+                        self.add_module_dependency(caller_info.module_id, root_module_id);
+
                         let module_type = self.check_module_by_id(library.root_module_id)?;
 
                         Ok(hir::Node::Const(hir::Const {
@@ -104,6 +108,10 @@ impl<'s> CheckSess<'s> {
                         }))
                     } else if let Some(result) = self.check_binding_in_std_prelude(name, caller_info) {
                         result
+                    } else if self.check_comptime_blocks(module)? {
+                        // The binding might be injected by one of the module's comptime blocks, which weren't evaluated yet
+                        self.find_checked_top_level_binding(name, module_id, caller_info)
+                            .unwrap_or_else(|| Err(self.name_not_found_error(module_id, name, caller_info)))
                     } else {
                         Err(self.name_not_found_error(module_id, name, caller_info))
                     }
@@ -135,10 +143,10 @@ impl<'s> CheckSess<'s> {
     fn check_binding_in_module(
         &mut self,
         name: Ustr,
-        module: &ast::Module,
+        module: &'s ast::Module,
         caller_info: CallerInfo,
     ) -> Option<CheckResult<hir::Node>> {
-        let (index, binding) = module.find_binding(name)?;
+        let (index, binding) = self.find_module_binding(module, name)?;
 
         // Check that this binding isn't cyclic
         if !self.encountered_items.insert((module.id, index)) {
@@ -186,6 +194,7 @@ impl<'s> CheckSess<'s> {
             let std_root_module = self
                 .modules
                 .iter()
+                .copied()
                 .find(|m| m.id == std_root_module_id)
                 .unwrap_or_else(|| panic!("{:?}", std_root_module_id));
 
@@ -235,6 +244,7 @@ impl<'s> CheckSess<'s> {
         let module = self
             .modules
             .iter()
+            .copied()
             .find(|m| m.id == id)
             .unwrap_or_else(|| panic!("couldn't find {:?}", id));
 
@@ -258,6 +268,7 @@ impl<'s> CheckSess<'s> {
                             module_type,
                             all_complete: false,
                             queued_bindings: HashSet::new(),
+                            comptime_blocks_checked: false,
                         },
                     );
 
@@ -279,16 +290,37 @@ impl<'s> CheckSess<'s> {
 
             self.queued_modules.get_mut(&module.id).unwrap().all_complete = true;
 
-            for r#static in module.comptime_blocks.iter() {
-                let node = self.with_env(module.id, |sess, mut env| r#static.check(sess, &mut env, None))?;
+            self.check_comptime_blocks(module)?;
 
-                if !self.workspace.build_options.check_mode {
-                    self.eval(&node, module.id, r#static.span)?;
-                }
+            Ok(module_type)
+        }
+    }
+
+    // Checks and evaluates the module's comptime blocks, if they weren't checked already.
+    // Returns whether they were checked by this call.
+    fn check_comptime_blocks(&mut self, module: &ast::Module) -> CheckResult<bool> {
+        match self.queued_modules.get_mut(&module.id) {
+            Some(queued_module) if !queued_module.comptime_blocks_checked => {
+                queued_module.comptime_blocks_checked = true
             }
+            _ => return Ok(false),
+        }
 
-            Ok(module_type)
+        for r#static in module.comptime_blocks.iter() {
+            self.check_comptime_block(r#static, module.id)?;
         }
+
+        Ok(true)
+    }
+
+    pub(super) fn check_comptime_block(&mut self, r#static: &ast::Comptime, module_id: ModuleId) -> CheckResult<()> {
+        let node = self.with_env(module_id, |sess, mut env| r#static.check(sess, &mut env, None))?;
+
+        if !self.workspace.build_options.check_mode {
+            self.eval(&node, module_id, r#static.span)?;
+        }
+
+        Ok(())
     }
 
     fn get_completed_module_type(&self, id: ModuleId) -> Option<TypeId> {
@@ -1,5 +1,8 @@
 use super::target::TargetPlatform;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Clone)]
 pub struct BuildOptions {
@@ -13,24 +16,68 @@ pub struct BuildOptions {
     /// If this is None, the target platform will be the current one, and codegen is skipped
     pub target_platform: TargetPlatform,
 
+    /// The target platform of workspaces started at compile-time with `BuildTarget_auto`.
+    /// If this is None, they target the current platform
+    pub auto_target_platform: Option<TargetPlatform>,
+
     /// The overall codegen optimization level
     pub optimization_level: OptimizationLevel,
 
+    /// The runtime checks to emit, such as bounds and overflow checks, which panic when they fail.
+    /// Compile-time code always performs them, except for overflow checks, which follow this option.
+    /// Functions annotated with `no_runtime_checks` emit none of them
+    pub runtime_checks: RuntimeChecks,
+
     pub diagnostic_options: DiagnosticOptions,
     pub codegen_options: CodegenOptions,
 
+    /// The kind of artifact produced by codegen
+    pub output_kind: OutputKind,
+
+    /// Keep the intermediate files of the build, such as the object file of a linked executable
+    pub keep_intermediates: bool,
+
     /// Additional include paths
     pub include_paths: Vec<PathBuf>,
 
+    /// Additional libraries to link against
+    pub libraries: Vec<String>,
+
+    /// Additional paths to search for libraries in while linking
+    pub library_search_paths: Vec<PathBuf>,
+
+    /// The root directory of the target's headers and libraries, used when linking for a foreign target.
+    /// If this is None, the sysroot of the target's installed GNU cross toolchain is used
+    pub sysroot: Option<PathBuf>,
+
+    /// User-defined compile-time constants, queried with `is_defined` and `get_define`
+    pub defines: HashMap<String, String>,
+
     /// Print timing information of every compiler pass
     pub emit_times: bool,
 
     /// Print the Hir into $CWD/hir.pretty.chl
     pub emit_hir: bool,
 
-    /// Print the bytecode into $CWD/vm.out
+    /// Print the optimized bytecode into $CWD/vm.out,
+    /// and the bytecode before optimization into $CWD/vm.unoptimized.out
     pub emit_bytecode: bool,
 
+    /// Count the instructions, calls and time spent in compile-time code,
+    /// and print a report at the end of the build
+    pub profile_comptime: bool,
+
+    /// Write the compile-time profile's call stacks in folded format, which flamegraph tools accept.
+    /// Implies `profile_comptime`
+    pub profile_comptime_folded: Option<PathBuf>,
+
+    /// Pause compile-time code at its first line, and step through it with an interactive prompt
+    pub debug_comptime: bool,
+
+    /// Reuse the results of pure compile-time evaluations from previous builds,
+    /// which are cached in the root directory
+    pub comptime_cache: bool,
+
     /// Whether the workspace is running in check mode
     pub check_mode: bool,
 }
@@ -42,6 +89,7 @@ impl BuildOptions {
 
     pub fn need_entry_point_function(&self) -> bool {
         matches!(self.codegen_options, CodegenOptions::Codegen { .. })
+            && self.output_kind.is_executable()
             && matches!(
                 self.target_platform,
                 TargetPlatform::Windows386
@@ -54,6 +102,7 @@ impl BuildOptions {
                     | TargetPlatform::FreeBSD386
                     | TargetPlatform::FreeBSDAmd64
                     | TargetPlatform::EssenceAmd64
+                    | TargetPlatform::WasiWasm32
             )
     }
 
@@ -70,26 +119,98 @@ impl BuildOptions {
             | TargetPlatform::FreeBSDAmd64
             | TargetPlatform::EssenceAmd64 => "main",
 
+            // wasi-libc's `_start` initializes libc, calls this function with the program's arguments,
+            // and exits with its return value
+            TargetPlatform::WasiWasm32 => "__main_argc_argv",
+
             p => panic!("unexpected TargetPlatform::{:?}", p),
         })
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OptimizationLevel {
-    Debug,
-    Release,
+    /// No optimizations. The optimization passes are skipped entirely
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Like O2, while optimizing for size
+    Os,
+    /// Like Os, while optimizing aggressively for size
+    Oz,
 }
 
 impl OptimizationLevel {
-    #[allow(unused)]
+    /// Debug builds are unoptimized, and include debug information.
+    /// Runtime checks are enabled for them by default
     pub fn is_debug(&self) -> bool {
-        matches!(self, OptimizationLevel::Debug)
+        matches!(self, OptimizationLevel::O0)
+    }
+
+    pub fn size_level(&self) -> u32 {
+        match self {
+            OptimizationLevel::O0 | OptimizationLevel::O1 | OptimizationLevel::O2 | OptimizationLevel::O3 => 0,
+            OptimizationLevel::Os => 1,
+            OptimizationLevel::Oz => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RuntimeChecks {
+    pub division_by_zero: bool,
+    pub null_pointer_dereference: bool,
+    pub overflow: bool,
+    pub index_out_of_bounds: bool,
+    /// Slices whose end is before their start, or whose range is out of bounds
+    pub slice_range: bool,
+}
+
+impl RuntimeChecks {
+    pub fn all() -> Self {
+        Self {
+            division_by_zero: true,
+            null_pointer_dereference: true,
+            overflow: true,
+            index_out_of_bounds: true,
+            slice_range: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            division_by_zero: false,
+            null_pointer_dereference: false,
+            overflow: false,
+            index_out_of_bounds: false,
+            slice_range: false,
+        }
+    }
+
+    /// Runtime checks are only enabled in debug builds by default
+    pub fn default_for(optimization_level: OptimizationLevel) -> Self {
+        if optimization_level.is_debug() {
+            Self::all()
+        } else {
+            Self::none()
+        }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Executable,
+    Object,
+    StaticLibrary,
+    SharedLibrary,
+    Assembly,
+    Bitcode,
+}
 
-    #[allow(unused)]
-    pub fn is_release(&self) -> bool {
-        matches!(self, OptimizationLevel::Release)
+impl OutputKind {
+    pub fn is_executable(&self) -> bool {
+        matches!(self, OutputKind::Executable)
     }
 }
 
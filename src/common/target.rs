@@ -2,41 +2,18 @@ use std::env;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TargetPlatform {
-    #[allow(unused)]
     Windows386,
-
     WindowsAmd64,
-
-    #[allow(unused)]
     Linux386,
-
     LinuxAmd64,
-
-    #[allow(unused)]
     LinuxArm64,
-
-    #[allow(unused)]
     DarwinAmd64,
-
-    #[allow(unused)]
     DarwinArm64,
-
-    #[allow(unused)]
     FreeBSD386,
-
-    #[allow(unused)]
     FreeBSDAmd64,
-
-    #[allow(unused)]
     EssenceAmd64,
-
-    #[allow(unused)]
     FreestandingWasm32,
-
-    #[allow(unused)]
     JsWasm32,
-
-    #[allow(unused)]
     WasiWasm32,
 }
 
@@ -116,7 +93,7 @@ impl TargetPlatform {
                 arch: Arch::Arm64,
                 word_size: 8,
                 max_align: 16,
-                target_triplet: "aarch64-linux-elf",
+                target_triplet: "aarch64-unknown-linux-gnu",
                 target_data_layout: "e-m:e-i8:8:32-i16:32-i64:64-i128:128-n32:64-S128",
             },
             TargetPlatform::DarwinAmd64 => TargetMetrics {
@@ -180,19 +157,71 @@ impl TargetPlatform {
                 arch: Arch::Wasm32,
                 word_size: 4,
                 max_align: 8,
-                target_triplet: "wasm32-wasi-js",
-                target_data_layout: "",
+                target_triplet: "wasm32-unknown-wasi",
+                target_data_layout: "e-m:e-p:32:32-i64:64-n32:64-S128",
             },
         }
     }
 
-    pub fn current() -> Result<Self, &'static str> {
-        match env::consts::OS {
-            "linux" => Ok(Self::LinuxAmd64),
-            "windows" => Ok(Self::WindowsAmd64),
-            os => Err(os),
+    pub fn current() -> Result<Self, String> {
+        match (env::consts::OS, env::consts::ARCH) {
+            ("windows", "x86") => Ok(Self::Windows386),
+            ("windows", "x86_64") => Ok(Self::WindowsAmd64),
+            ("linux", "x86") => Ok(Self::Linux386),
+            ("linux", "x86_64") => Ok(Self::LinuxAmd64),
+            ("linux", "aarch64") => Ok(Self::LinuxArm64),
+            ("macos", "x86_64") => Ok(Self::DarwinAmd64),
+            ("macos", "aarch64") => Ok(Self::DarwinArm64),
+            ("freebsd", "x86") => Ok(Self::FreeBSD386),
+            ("freebsd", "x86_64") => Ok(Self::FreeBSDAmd64),
+            (os, arch) => Err(format!("{}-{}", os, arch)),
         }
     }
+
+    pub fn all() -> &'static [TargetPlatform] {
+        &[
+            TargetPlatform::Windows386,
+            TargetPlatform::WindowsAmd64,
+            TargetPlatform::Linux386,
+            TargetPlatform::LinuxAmd64,
+            TargetPlatform::LinuxArm64,
+            TargetPlatform::DarwinAmd64,
+            TargetPlatform::DarwinArm64,
+            TargetPlatform::FreeBSD386,
+            TargetPlatform::FreeBSDAmd64,
+            TargetPlatform::EssenceAmd64,
+            TargetPlatform::FreestandingWasm32,
+            TargetPlatform::JsWasm32,
+            TargetPlatform::WasiWasm32,
+        ]
+    }
+
+    /// The platform's name in the form of `os-arch`, i.e: `linux-arm64`
+    pub fn name(&self) -> String {
+        let metrics = self.metrics();
+        format!("{}-{}", metrics.os.name(), metrics.arch.name())
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().iter().find(|platform| platform.name() == name).cloned()
+    }
+
+    /// Whether the llvm backend can generate code for this platform
+    pub fn is_codegen_supported(&self) -> bool {
+        matches!(
+            self,
+            TargetPlatform::WindowsAmd64
+                | TargetPlatform::LinuxAmd64
+                | TargetPlatform::LinuxArm64
+                | TargetPlatform::WasiWasm32
+        )
+    }
+
+    /// Whether this is the platform the compiler is running on.
+    /// Code for any other platform is cross-compiled
+    pub fn is_host(&self) -> bool {
+        Self::current().map_or(false, |current| &current == self)
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -218,7 +247,6 @@ pub enum Os {
 }
 
 impl Os {
-    #[allow(unused)]
     pub fn name(&self) -> &'static str {
         match self {
             Os::Windows => "windows",
@@ -3,6 +3,7 @@ use crate::{
     common::{
         build_options::{BuildOptions, CodegenOptions},
         path::{resolve_relative_path, RelativeTo},
+        target::TargetPlatform,
         Stopwatch,
     },
     error::diagnostic::Diagnostic,
@@ -79,6 +80,42 @@ pub fn start_workspace(name: String, build_options: BuildOptions) -> StartWorksp
         return StartWorkspaceResult::new_untyped(workspace);
     }
 
+    // Check that the backend can generate code for the target platform
+    let target_platform = &workspace.build_options.target_platform;
+
+    if matches!(workspace.build_options.codegen_options, CodegenOptions::Codegen { .. })
+        && !target_platform.is_codegen_supported()
+    {
+        let supported_targets = TargetPlatform::all()
+            .iter()
+            .filter(|platform| platform.is_codegen_supported())
+            .map(|platform| format!("`{}`", platform.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        workspace.diagnostics.push(
+            Diagnostic::error()
+                .with_message(format!(
+                    "code generation for target `{}` is not supported",
+                    target_platform.name()
+                ))
+                .with_note(format!("supported targets are: {}", supported_targets)),
+        );
+
+        workspace.emit_diagnostics();
+
+        return StartWorkspaceResult::new_untyped(workspace);
+    }
+
+    // Check that the target can be linked for, when cross-compiling
+    if matches!(workspace.build_options.codegen_options, CodegenOptions::Codegen { .. }) {
+        if let Err(diagnostic) = crate::backend::llvm::check_cross_toolchain(&workspace.build_options) {
+            workspace.diagnostics.push(diagnostic);
+            workspace.emit_diagnostics();
+            return StartWorkspaceResult::new_untyped(workspace);
+        }
+    }
+
     // Parse all source files into ast's
     let (modules, stats) = time! { workspace.build_options.emit_times, "parse", {
             match crate::astgen::generate_ast(&mut workspace) {
@@ -129,6 +166,8 @@ pub fn start_workspace(name: String, build_options: BuildOptions) -> StartWorksp
                 print_stats(stats, all_sw.unwrap().elapsed().as_millis());
             }
 
+            report_comptime_profile(&workspace);
+
             StartWorkspaceResult::new_typed_with_output(workspace, tcx, cache, output_file)
         }
         _ => {
@@ -136,11 +175,30 @@ pub fn start_workspace(name: String, build_options: BuildOptions) -> StartWorksp
                 print_stats(stats, all_sw.unwrap().elapsed().as_millis());
             }
 
+            report_comptime_profile(&workspace);
+
             StartWorkspaceResult::new_typed(workspace, tcx, cache)
         }
     }
 }
 
+fn report_comptime_profile(workspace: &Workspace) {
+    if let Some(profile) = &workspace.comptime_profile {
+        profile.print_report();
+
+        if let Some(path) = &workspace.build_options.profile_comptime_folded {
+            if let Err(err) = profile.write_folded_stacks(path) {
+                println!(
+                    "{} couldn't write comptime profile to `{}`: {}",
+                    "error:".red().bold(),
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
 fn print_stats(stats: AstGenerationStats, elapsed_ms: u128) {
     println!("------------------------");
     println!(
@@ -53,9 +53,21 @@ impl Diagnostics {
             .count()
     }
 
+    pub fn warning_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|d| d.severity == DiagnosticSeverity::Warning)
+            .count()
+    }
+
     pub fn has_errors(&self) -> bool {
         self.error_count() > 0
     }
+
+    // Removes all diagnostics, keeping the files they refer to
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
 }
 
 pub type DiagnosticResult<T> = Result<T, diagnostic::Diagnostic>;
@@ -57,6 +57,8 @@ pub enum AttrKind {
     Dylib,
     LinkName,
     TrackCaller,
+    NoRuntimeChecks,
+    PanicHandler,
 }
 
 pub const ATTR_NAME_INTRINSIC: &str = "intrinsic";
@@ -64,6 +66,8 @@ pub const ATTR_NAME_LIB: &str = "lib";
 pub const ATTR_NAME_DYLIB: &str = "dylib";
 pub const ATTR_NAME_LINK_NAME: &str = "link_name";
 pub const ATTR_NAME_TRACK_CALLER: &str = "track_caller";
+pub const ATTR_NAME_NO_RUNTIME_CHECKS: &str = "no_runtime_checks";
+pub const ATTR_NAME_PANIC_HANDLER: &str = "panic_handler";
 
 impl TryFrom<&str> for AttrKind {
     type Error = ();
@@ -75,6 +79,8 @@ impl TryFrom<&str> for AttrKind {
             ATTR_NAME_DYLIB => Ok(AttrKind::Dylib),
             ATTR_NAME_LINK_NAME => Ok(AttrKind::LinkName),
             ATTR_NAME_TRACK_CALLER => Ok(AttrKind::TrackCaller),
+            ATTR_NAME_NO_RUNTIME_CHECKS => Ok(AttrKind::NoRuntimeChecks),
+            ATTR_NAME_PANIC_HANDLER => Ok(AttrKind::PanicHandler),
             _ => Err(()),
         }
     }
@@ -91,6 +97,8 @@ impl Display for AttrKind {
                 AttrKind::Dylib => ATTR_NAME_DYLIB,
                 AttrKind::LinkName => ATTR_NAME_LINK_NAME,
                 AttrKind::TrackCaller => ATTR_NAME_TRACK_CALLER,
+                AttrKind::NoRuntimeChecks => ATTR_NAME_NO_RUNTIME_CHECKS,
+                AttrKind::PanicHandler => ATTR_NAME_PANIC_HANDLER,
             }
         )
     }
@@ -303,19 +303,27 @@ impl ConstValue {
     }
 
     pub fn from_os(os: Os) -> Self {
+        // Keep in sync with `Os_*` in std/intrinsics.chl
         Self::Int(match os {
             Os::Linux => 0,
             Os::Windows => 1,
-            Os::Darwin | Os::Essence | Os::FreeBSD | Os::Wasi | Os::Js | Os::Freestanding => {
-                todo!("{}", os.name())
-            }
+            Os::Darwin => 2,
+            Os::FreeBSD => 3,
+            Os::Essence => 4,
+            Os::Wasi => 5,
+            Os::Js => 6,
+            Os::Freestanding => 7,
         })
     }
 
     pub fn from_arch(arch: Arch) -> Self {
+        // Keep in sync with `Arch_*` in std/intrinsics.chl
         Self::Int(match arch {
             Arch::Amd64 => 0,
-            Arch::_386 | Arch::Arm64 | Arch::Wasm32 | Arch::Wasm64 => todo!("{}", arch.name()),
+            Arch::_386 => 1,
+            Arch::Arm64 => 2,
+            Arch::Wasm32 => 3,
+            Arch::Wasm64 => 4,
         })
     }
 }
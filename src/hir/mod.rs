@@ -44,6 +44,9 @@ pub struct Cache {
 
     // The entry point function's id (usually named "main"). Resolved during semantic analysis
     pub entry_point_function_id: Option<FunctionId>,
+
+    // The function annotated with `panic_handler`, if any. Resolved during semantic analysis
+    pub panic_handler_function_id: Option<FunctionId>,
 }
 
 impl Cache {
@@ -52,12 +55,17 @@ impl Cache {
             bindings: HashMap::new(),
             functions: IdCache::new(),
             entry_point_function_id: None,
+            panic_handler_function_id: None,
         }
     }
 
     pub fn entry_point_function(&self) -> Option<&Function> {
         self.entry_point_function_id.and_then(|id| self.functions.get(id))
     }
+
+    pub fn panic_handler(&self) -> Option<&Function> {
+        self.panic_handler_function_id.and_then(|id| self.functions.get(id))
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -69,6 +77,7 @@ pub struct Function {
     pub kind: FunctionKind,
     pub ty: TypeId,
     pub span: Span,
+    pub no_runtime_checks: bool,
 }
 
 impl WithId<FunctionId> for Function {
@@ -127,6 +136,10 @@ pub enum Intrinsic {
     Arch,
     CompilerError,
     CompilerWarning,
+    IsDefined,
+    GetDefine,
+    InjectBindings,
+    SymbolTable,
 }
 
 pub const INTRINSIC_NAME_START_WORKSPACE: &str = "start_workspace";
@@ -136,6 +149,10 @@ pub const INTRINSIC_NAME_OS: &str = "os";
 pub const INTRINSIC_NAME_ARCH: &str = "arch";
 pub const INTRINSIC_NAME_COMPILER_ERROR: &str = "compiler_error";
 pub const INTRINSIC_NAME_COMPILER_WARNING: &str = "compiler_warning";
+pub const INTRINSIC_NAME_IS_DEFINED: &str = "is_defined";
+pub const INTRINSIC_NAME_GET_DEFINE: &str = "get_define";
+pub const INTRINSIC_NAME_INJECT_BINDINGS: &str = "inject_bindings";
+pub const INTRINSIC_NAME_SYMBOL_TABLE: &str = "symbol_table";
 
 impl TryFrom<&str> for Intrinsic {
     type Error = ();
@@ -149,6 +166,10 @@ impl TryFrom<&str> for Intrinsic {
             INTRINSIC_NAME_ARCH => Ok(Intrinsic::Arch),
             INTRINSIC_NAME_COMPILER_ERROR => Ok(Intrinsic::CompilerError),
             INTRINSIC_NAME_COMPILER_WARNING => Ok(Intrinsic::CompilerWarning),
+            INTRINSIC_NAME_IS_DEFINED => Ok(Intrinsic::IsDefined),
+            INTRINSIC_NAME_GET_DEFINE => Ok(Intrinsic::GetDefine),
+            INTRINSIC_NAME_INJECT_BINDINGS => Ok(Intrinsic::InjectBindings),
+            INTRINSIC_NAME_SYMBOL_TABLE => Ok(Intrinsic::SymbolTable),
             _ => Err(()),
         }
     }
@@ -167,6 +188,10 @@ impl Display for Intrinsic {
                 Intrinsic::Arch => INTRINSIC_NAME_ARCH,
                 Intrinsic::CompilerError => INTRINSIC_NAME_COMPILER_ERROR,
                 Intrinsic::CompilerWarning => INTRINSIC_NAME_COMPILER_WARNING,
+                Intrinsic::IsDefined => INTRINSIC_NAME_IS_DEFINED,
+                Intrinsic::GetDefine => INTRINSIC_NAME_GET_DEFINE,
+                Intrinsic::InjectBindings => INTRINSIC_NAME_INJECT_BINDINGS,
+                Intrinsic::SymbolTable => INTRINSIC_NAME_SYMBOL_TABLE,
             }
         )
     }
@@ -0,0 +1,985 @@
+use super::{
+    interp::InterpSess,
+    lower::{find_and_lower_top_level_binding, Lower, LowerContext},
+    vm::{
+        byte_seq::ByteSeq,
+        bytecode::{Bytecode, Inst, LocalInfo, Register},
+        value::{Buffer, Function, FunctionAddress, IntrinsicFunction, Value},
+    },
+};
+use crate::{
+    hir::{
+        self,
+        const_value::{ConstArray, ConstElement, ConstValue},
+    },
+    infer::{display::DisplayType, normalize::Normalize, type_ctx::TypeCtx},
+    span::{EndPosition, FileId, Position, Span},
+    types::{FloatType, InferType, IntType, StructType, StructTypeField, StructTypeKind, Type, UintType},
+    workspace::{BindingId, ModuleId, ScopeLevel, Workspace},
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+use ustr::ustr;
+
+// The cache is stored next to the workspace's root file, in a file named after it.
+// This keeps the caches of nested workspaces that share a directory, such as `build.chl` and `main.chl`, apart.
+const CACHE_DIR: &str = ".chili-cache";
+
+// Bumped whenever the format of cached values, or the way keys are computed, changes
+const CACHE_VERSION: u32 = 5;
+
+/// Lowered functions and results of pure compile-time evaluations from previous builds.
+/// Each entry is keyed by a hash of the code's location, the sources of its module and the modules it
+/// depends on, and the build options that affect evaluation - so editing any of them invalidates the entry.
+#[derive(Debug)]
+pub struct ComptimeCache {
+    path: PathBuf,
+    entries: CacheEntries,
+    // Entries that were read or written by this build
+    used_entries: CacheEntries,
+
+    // The modules that each module refers to, through imports or library names
+    module_dependencies: HashMap<ModuleId, HashSet<ModuleId>>,
+    // The virtual files injected into each module by `inject_bindings`
+    injected_files: HashMap<ModuleId, Vec<FileId>>,
+    module_source_hashes: HashMap<ModuleId, u64>,
+
+    // Files, functions and global bindings of the current build by their location.
+    // These resolve the references of cached functions to this build's ids.
+    // Nothing is removed during a build, so each index is only extended with what was added since it was last used.
+    file_ids: HashMap<String, FileId>,
+    function_ids: HashMap<Location, hir::FunctionId>,
+    binding_ids: HashMap<Location, BindingId>,
+    indexed_files: usize,
+    indexed_functions: usize,
+    indexed_bindings: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CacheEntries {
+    values: HashMap<u64, CachedValue>,
+    functions: HashMap<u64, CachedFunction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: CacheEntries,
+}
+
+/// A constant value that doesn't refer to ids of the current build, such as types or functions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CachedValue {
+    Unit,
+    Bool(bool),
+    Int(i128),
+    // Stored as its bits, like `CachedConst::F64`
+    Float(u64),
+    Str(String),
+    Array(Vec<CachedValue>),
+    Tuple(Vec<CachedValue>),
+    Struct(Vec<CachedValue>),
+}
+
+/// The lowered code of a function. Instructions refer to the function's own `constants` and `globals`,
+/// and spans refer to its own `files`, which are mapped to the current build's slots and ids when it is loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedFunction {
+    instructions: Vec<Inst>,
+    spans: Vec<CachedSpan>,
+    locals: Vec<CachedLocal>,
+    registers: u32,
+    constants: Vec<CachedConst>,
+    globals: Vec<Location>,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedLocal {
+    name: String,
+    register: Register,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct CachedSpan {
+    // An index into the function's files, or `None` if the span is unknown
+    file: Option<usize>,
+    start: usize,
+    line: u32,
+    column: u32,
+    end: usize,
+}
+
+// Floats are stored as their bits, since json has no representation for NaN or infinity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum CachedConst {
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Int(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Uint(u64),
+    F32(u32),
+    F64(u64),
+    Bool(bool),
+    Str(String),
+    // A buffer which holds no pointers
+    Buffer { bytes: Vec<u8>, ty: CachedType },
+    Function(Location),
+    Intrinsic(IntrinsicFunction),
+    Type(CachedType),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum CachedType {
+    Never,
+    Unit,
+    Bool,
+    Int(IntType),
+    Uint(UintType),
+    Float(FloatType),
+    Pointer(Box<CachedType>, bool),
+    Array(Box<CachedType>, usize),
+    Slice(Box<CachedType>),
+    Str(Box<CachedType>),
+    Tuple(Vec<CachedType>),
+    Struct {
+        name: String,
+        binding: Option<Location>,
+        fields: Vec<CachedStructField>,
+        kind: StructTypeKind,
+    },
+    Type(Box<CachedType>),
+    AnyType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedStructField {
+    name: String,
+    ty: CachedType,
+    span: CachedSpan,
+}
+
+/// Where a function or a global binding is defined, which identifies it across builds.
+/// The name and type tell apart the instances of polymorphic functions, which share their location.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Location {
+    file: String,
+    index: usize,
+    name: String,
+    ty: String,
+}
+
+impl ComptimeCache {
+    pub fn load(source_file: &Path) -> Self {
+        let file_name = Path::new(source_file.file_stem().unwrap()).with_extension("json");
+        let path = source_file.parent().unwrap().join(CACHE_DIR).join(file_name);
+
+        // a missing or outdated cache is treated as an empty one
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<CacheFile>(&contents).ok())
+            .filter(|file| file.version == CACHE_VERSION)
+            .map_or_else(CacheEntries::default, |file| file.entries);
+
+        Self {
+            path,
+            entries,
+            used_entries: CacheEntries::default(),
+            module_dependencies: HashMap::new(),
+            injected_files: HashMap::new(),
+            module_source_hashes: HashMap::new(),
+            file_ids: HashMap::new(),
+            function_ids: HashMap::new(),
+            binding_ids: HashMap::new(),
+            indexed_files: 0,
+            indexed_functions: 0,
+            indexed_bindings: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: u64, ty: &Type, tcx: &mut TypeCtx, span: Span) -> Option<ConstValue> {
+        let value = self.entries.values.get(&key)?;
+        let const_value = value.to_const_value(ty, tcx, span)?;
+
+        self.used_entries.values.insert(key, value.clone());
+
+        Some(const_value)
+    }
+
+    pub fn insert(&mut self, key: u64, value: &ConstValue) {
+        if let Some(value) = CachedValue::from_const_value(value) {
+            self.entries.values.insert(key, value.clone());
+            self.used_entries.values.insert(key, value);
+        }
+    }
+
+    /// Writes the cache to disk. When `prune` is set, only the entries used by this build are kept,
+    /// which drops entries of code that was edited or removed.
+    pub fn save(&self, prune: bool) {
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            entries: if prune {
+                self.used_entries.clone()
+            } else {
+                self.entries.clone()
+            },
+        };
+
+        // failing to write the cache only makes the next build slower, so errors are ignored
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+
+        if let Ok(contents) = serde_json::to_string(&file) {
+            let _ = fs::write(&self.path, contents);
+        }
+    }
+
+    /// Records that code in `module_id` refers to `dependency`,
+    /// so that editing `dependency` invalidates the cached entries of `module_id`
+    pub fn add_module_dependency(&mut self, module_id: ModuleId, dependency: ModuleId) {
+        if module_id != dependency {
+            self.module_dependencies
+                .entry(module_id)
+                .or_default()
+                .insert(dependency);
+        }
+    }
+
+    /// Records that bindings were injected into `module_id` from the virtual file `file_id`,
+    /// whose source is then a part of the module's source
+    pub fn add_injected_file(&mut self, module_id: ModuleId, file_id: FileId) {
+        self.injected_files.entry(module_id).or_default().push(file_id);
+        self.module_source_hashes.remove(&module_id);
+    }
+
+    /// The key of the code at `span` in the cache.
+    /// It covers the build options that affect evaluation, and the sources of every module the code can reach.
+    pub fn key(&mut self, workspace: &Workspace, module_id: ModuleId, span: Span) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let build_options = &workspace.build_options;
+
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        format!("{:?}", build_options.target_platform).hash(&mut hasher);
+        build_options.runtime_checks.hash(&mut hasher);
+        build_options
+            .defines
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .hash(&mut hasher);
+
+        // file ids depend on the order files were parsed in, so the file's name is used instead
+        if let Some(file) = workspace.diagnostics.get_file(span.file_id) {
+            file.name().hash(&mut hasher);
+        }
+
+        span.start.index.hash(&mut hasher);
+        span.end.index.hash(&mut hasher);
+
+        for module_id in self.reachable_modules(workspace, module_id) {
+            self.module_source_hash(workspace, module_id).hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    fn function_key(&mut self, workspace: &Workspace, tcx: &TypeCtx, function: &hir::Function) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        self.key(workspace, function.module_id, function.span).hash(&mut hasher);
+        function.qualified_name.as_str().hash(&mut hasher);
+        function.ty.display(tcx).hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    // The modules that `module_id` depends on, directly or indirectly, including itself and std.
+    // Sorted by their file path, so that the order doesn't depend on the order they were checked in.
+    fn reachable_modules(&self, workspace: &Workspace, module_id: ModuleId) -> Vec<ModuleId> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![module_id, workspace.std_library().root_module_id];
+
+        while let Some(module_id) = stack.pop() {
+            if visited.insert(module_id) {
+                if let Some(dependencies) = self.module_dependencies.get(&module_id) {
+                    stack.extend(dependencies.iter().copied());
+                }
+            }
+        }
+
+        let mut modules = visited.into_iter().collect::<Vec<_>>();
+        modules.sort_by_key(|module_id| workspace.module_infos.get(*module_id).unwrap().file_path.as_str());
+        modules
+    }
+
+    fn module_source_hash(&mut self, workspace: &Workspace, module_id: ModuleId) -> u64 {
+        if let Some(hash) = self.module_source_hashes.get(&module_id) {
+            return *hash;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        let module_info = workspace.module_infos.get(module_id).unwrap();
+
+        module_info.file_path.as_str().hash(&mut hasher);
+
+        let injected_files = self.injected_files.get(&module_id).into_iter().flatten();
+
+        for file_id in std::iter::once(&module_info.file_id).chain(injected_files) {
+            if let Some(file) = workspace.diagnostics.get_file(*file_id) {
+                file.source().hash(&mut hasher);
+            }
+        }
+
+        let hash = hasher.finish();
+        self.module_source_hashes.insert(module_id, hash);
+        hash
+    }
+
+    fn index_definitions(&mut self, workspace: &Workspace, cache: &hir::Cache, tcx: &TypeCtx) {
+        while let Some(file) = workspace.diagnostics.get_file(self.indexed_files) {
+            self.file_ids.insert(file.name().clone(), self.indexed_files);
+            self.indexed_files += 1;
+        }
+
+        while self.indexed_functions < cache.functions.len() {
+            let id = hir::FunctionId::from(self.indexed_functions);
+
+            if let Some(location) = function_location(workspace, tcx, cache, id) {
+                self.function_ids.insert(location, id);
+            }
+
+            self.indexed_functions += 1;
+        }
+
+        while self.indexed_bindings < workspace.binding_infos.len() {
+            let id = BindingId::from(self.indexed_bindings);
+
+            if let Some(location) = binding_location(workspace, tcx, id) {
+                self.binding_ids.insert(location, id);
+            }
+
+            self.indexed_bindings += 1;
+        }
+    }
+}
+
+fn function_location(
+    workspace: &Workspace,
+    tcx: &TypeCtx,
+    cache: &hir::Cache,
+    id: hir::FunctionId,
+) -> Option<Location> {
+    let function = cache.functions.get(id)?;
+
+    Some(Location {
+        file: workspace.diagnostics.get_file(function.span.file_id)?.name().clone(),
+        index: function.span.start.index,
+        name: function.qualified_name.to_string(),
+        ty: function.ty.display(tcx),
+    })
+}
+
+// Only global bindings have a location, since locals can't be resolved outside of their function
+fn binding_location(workspace: &Workspace, tcx: &TypeCtx, id: BindingId) -> Option<Location> {
+    let binding_info = workspace.binding_infos.get(id)?;
+
+    if !matches!(binding_info.scope_level, ScopeLevel::Global) {
+        return None;
+    }
+
+    Some(Location {
+        file: workspace
+            .diagnostics
+            .get_file(binding_info.span.file_id)?
+            .name()
+            .clone(),
+        index: binding_info.span.start.index,
+        name: binding_info.qualified_name.to_string(),
+        ty: binding_info.ty.display(tcx),
+    })
+}
+
+impl<'i> InterpSess<'i> {
+    /// Stores the functions lowered in this session in the comptime cache.
+    /// Functions that refer to values which only exist in this build, such as the addresses of extern variables,
+    /// or to local static bindings, aren't cached.
+    pub(super) fn cache_lowered_functions(&mut self) {
+        if self.interp.comptime_cache.is_none() {
+            return;
+        }
+
+        let globals_by_slot: HashMap<usize, BindingId> = self
+            .interp
+            .bindings_to_globals
+            .iter()
+            .map(|(&id, &slot)| (slot, id))
+            .collect();
+
+        let mut encoded_functions = vec![];
+
+        for id in self.lowered_functions.iter() {
+            // global initializers aren't hir functions
+            let function = match self.cache.functions.get(*id) {
+                Some(function) if matches!(function.kind, hir::FunctionKind::Orphan { .. }) => function,
+                _ => continue,
+            };
+
+            if let Some(lowered) = self.interp.functions.get(id) {
+                let mut encoder = FunctionEncoder {
+                    sess: self,
+                    globals_by_slot: &globals_by_slot,
+                    constants: vec![],
+                    constant_indices: HashMap::new(),
+                    globals: vec![],
+                    global_indices: HashMap::new(),
+                    files: vec![],
+                    file_indices: HashMap::new(),
+                };
+
+                if let Some(cached) = encoder.encode(&lowered.code) {
+                    encoded_functions.push((function, cached));
+                }
+            }
+        }
+
+        let cache = self.interp.comptime_cache.as_mut().unwrap();
+
+        for (function, cached) in encoded_functions {
+            let key = cache.function_key(self.workspace, self.tcx, function);
+            cache.entries.functions.insert(key, cached.clone());
+            cache.used_entries.functions.insert(key, cached);
+        }
+    }
+
+    /// Loads the lowered code of `function` from the comptime cache.
+    /// Returns false if it isn't cached, or if it refers to something that doesn't exist in this build.
+    pub(super) fn load_cached_function(&mut self, function: &hir::Function) -> bool {
+        let cache = match self.interp.comptime_cache.as_mut() {
+            Some(cache) => cache,
+            None => return false,
+        };
+
+        let key = cache.function_key(self.workspace, self.tcx, function);
+
+        let cached = match cache.entries.functions.get(&key) {
+            Some(cached) => cached.clone(),
+            None => return false,
+        };
+
+        cache.index_definitions(self.workspace, self.cache, self.tcx);
+
+        let decoder = FunctionDecoder {
+            sess: self,
+            cached: &cached,
+        };
+
+        let (constants, globals, spans) = match decoder.decode() {
+            Some(decoded) => decoded,
+            None => return false,
+        };
+
+        let cache = self.interp.comptime_cache.as_mut().unwrap();
+        cache.used_entries.functions.insert(key, cached.clone());
+
+        // the referenced globals are lowered like they are when lowering the function's code
+        let global_slots = globals
+            .into_iter()
+            .map(|id| {
+                self.get_global(id)
+                    .unwrap_or_else(|| find_and_lower_top_level_binding(id, self)) as u32
+            })
+            .collect::<Vec<_>>();
+
+        let referenced_functions = constants
+            .iter()
+            .filter_map(|value| match value {
+                Value::Function(address) => Some(address.id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let const_slots = constants
+            .into_iter()
+            .map(|value| self.add_const(value))
+            .collect::<Vec<_>>();
+
+        let mut code = Bytecode::new();
+
+        code.instructions = cached
+            .instructions
+            .iter()
+            .map(|inst| {
+                let mut inst = *inst;
+
+                if let Some(slot) = inst.const_slot_mut() {
+                    *slot = const_slots[*slot as usize];
+                }
+
+                if let Some(slot) = inst.global_slot_mut() {
+                    *slot = global_slots[*slot as usize];
+                }
+
+                inst
+            })
+            .collect();
+
+        code.spans = spans;
+
+        code.locals = cached
+            .locals
+            .iter()
+            .map(|local| LocalInfo {
+                name: ustr(&local.name),
+                register: local.register,
+                start: local.start,
+                end: local.end,
+            })
+            .collect();
+
+        code.registers = cached.registers;
+
+        self.interp.functions.insert(
+            function.id,
+            Function {
+                id: function.id,
+                name: function.qualified_name,
+                ty: function.ty.normalize(self.tcx).into_function(),
+                code,
+                runtime_checks: !function.no_runtime_checks,
+            },
+        );
+
+        // the function is inserted first, so that recursive references find it
+        for id in referenced_functions {
+            self.cache.functions.get(id).unwrap().lower(
+                self,
+                &mut Bytecode::new(),
+                LowerContext {
+                    take_ptr: false,
+                    dst: 0,
+                },
+            );
+        }
+
+        true
+    }
+}
+
+struct FunctionEncoder<'a, 'i> {
+    sess: &'a InterpSess<'i>,
+    globals_by_slot: &'a HashMap<usize, BindingId>,
+    constants: Vec<CachedConst>,
+    constant_indices: HashMap<u32, u32>,
+    globals: Vec<Location>,
+    global_indices: HashMap<u32, u32>,
+    files: Vec<String>,
+    file_indices: HashMap<FileId, usize>,
+}
+
+impl<'a, 'i> FunctionEncoder<'a, 'i> {
+    fn encode(&mut self, code: &Bytecode) -> Option<CachedFunction> {
+        let mut instructions = code.instructions.clone();
+
+        for inst in instructions.iter_mut() {
+            if let Some(slot) = inst.const_slot_mut() {
+                *slot = self.constant(*slot)?;
+            }
+
+            if let Some(slot) = inst.global_slot_mut() {
+                *slot = self.global(*slot)?;
+            }
+        }
+
+        let spans = code.spans.iter().map(|span| self.span(*span)).collect();
+
+        let locals = code
+            .locals
+            .iter()
+            .map(|local| CachedLocal {
+                name: local.name.to_string(),
+                register: local.register,
+                start: local.start,
+                end: local.end,
+            })
+            .collect();
+
+        Some(CachedFunction {
+            instructions,
+            spans,
+            locals,
+            registers: code.registers,
+            constants: std::mem::take(&mut self.constants),
+            globals: std::mem::take(&mut self.globals),
+            files: std::mem::take(&mut self.files),
+        })
+    }
+
+    fn constant(&mut self, slot: u32) -> Option<u32> {
+        if let Some(index) = self.constant_indices.get(&slot) {
+            return Some(*index);
+        }
+
+        let value = &self.sess.interp.constants[slot as usize];
+
+        let constant = match value {
+            Value::I8(v) => CachedConst::I8(*v),
+            Value::I16(v) => CachedConst::I16(*v),
+            Value::I32(v) => CachedConst::I32(*v),
+            Value::I64(v) => CachedConst::I64(*v),
+            Value::Int(v) => CachedConst::Int(*v as i64),
+            Value::U8(v) => CachedConst::U8(*v),
+            Value::U16(v) => CachedConst::U16(*v),
+            Value::U32(v) => CachedConst::U32(*v),
+            Value::U64(v) => CachedConst::U64(*v),
+            Value::Uint(v) => CachedConst::Uint(*v as u64),
+            Value::F32(v) => CachedConst::F32(v.to_bits()),
+            Value::F64(v) => CachedConst::F64(v.to_bits()),
+            Value::Bool(v) => CachedConst::Bool(*v),
+            // string literals point to interned strings, which are interned again when loaded
+            Value::Buffer(buf) if buf.ty == Type::str_pointer() => {
+                CachedConst::Str(std::str::from_utf8(buf.as_slice::<u8>()).ok()?.to_string())
+            }
+            Value::Buffer(buf) if !holds_pointers(&buf.ty) => CachedConst::Buffer {
+                bytes: buf.bytes.as_ref().to_vec(),
+                ty: self.ty(&buf.ty)?,
+            },
+            Value::Function(address) => CachedConst::Function(function_location(
+                self.sess.workspace,
+                self.sess.tcx,
+                self.sess.cache,
+                address.id,
+            )?),
+            Value::Intrinsic(intrinsic) => CachedConst::Intrinsic(*intrinsic),
+            Value::Type(ty) => CachedConst::Type(self.ty(ty)?),
+            Value::Buffer(_) | Value::Pointer(_) | Value::ExternVariable(_) => return None,
+        };
+
+        let index = self.constants.len() as u32;
+        self.constants.push(constant);
+        self.constant_indices.insert(slot, index);
+
+        Some(index)
+    }
+
+    fn global(&mut self, slot: u32) -> Option<u32> {
+        if let Some(index) = self.global_indices.get(&slot) {
+            return Some(*index);
+        }
+
+        let id = *self.globals_by_slot.get(&(slot as usize))?;
+        let location = binding_location(self.sess.workspace, self.sess.tcx, id)?;
+
+        let index = self.globals.len() as u32;
+        self.globals.push(location);
+        self.global_indices.insert(slot, index);
+
+        Some(index)
+    }
+
+    fn ty(&mut self, ty: &Type) -> Option<CachedType> {
+        let ty = match ty {
+            Type::Never => CachedType::Never,
+            Type::Unit => CachedType::Unit,
+            Type::Bool => CachedType::Bool,
+            Type::Int(ty) => CachedType::Int(*ty),
+            Type::Uint(ty) => CachedType::Uint(*ty),
+            Type::Float(ty) => CachedType::Float(*ty),
+            Type::Pointer(inner, is_mutable) => CachedType::Pointer(Box::new(self.ty(inner)?), *is_mutable),
+            Type::Array(inner, len) => CachedType::Array(Box::new(self.ty(inner)?), *len),
+            Type::Slice(inner) => CachedType::Slice(Box::new(self.ty(inner)?)),
+            Type::Str(inner) => CachedType::Str(Box::new(self.ty(inner)?)),
+            Type::Tuple(elements) => {
+                CachedType::Tuple(elements.iter().map(|ty| self.ty(ty)).collect::<Option<Vec<_>>>()?)
+            }
+            Type::Struct(struct_type) => CachedType::Struct {
+                name: struct_type.name.to_string(),
+                binding: match struct_type.binding_id {
+                    Some(id) => Some(binding_location(self.sess.workspace, self.sess.tcx, id)?),
+                    None => None,
+                },
+                fields: struct_type
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        Some(CachedStructField {
+                            name: field.name.to_string(),
+                            ty: self.ty(&field.ty)?,
+                            span: self.span(field.span),
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+                kind: struct_type.kind,
+            },
+            Type::Type(inner) => CachedType::Type(Box::new(self.ty(inner)?)),
+            Type::AnyType => CachedType::AnyType,
+            // these refer to ids of the current build
+            Type::Function(_) | Type::Module(_) | Type::Var(_) | Type::Infer(_, _) => return None,
+        };
+
+        Some(ty)
+    }
+
+    fn span(&mut self, span: Span) -> CachedSpan {
+        let file = self.sess.workspace.diagnostics.get_file(span.file_id).map(|file| {
+            let files = &mut self.files;
+
+            *self.file_indices.entry(span.file_id).or_insert_with(|| {
+                files.push(file.name().clone());
+                files.len() - 1
+            })
+        });
+
+        CachedSpan {
+            file,
+            start: span.start.index,
+            line: span.start.line,
+            column: span.start.column,
+            end: span.end.index,
+        }
+    }
+}
+
+struct FunctionDecoder<'a, 'i> {
+    sess: &'a InterpSess<'i>,
+    cached: &'a CachedFunction,
+}
+
+impl<'a, 'i> FunctionDecoder<'a, 'i> {
+    // Resolves the function's constants, globals and spans to the current build
+    fn decode(&self) -> Option<(Vec<Value>, Vec<BindingId>, Vec<Span>)> {
+        let file_ids = self
+            .cached
+            .files
+            .iter()
+            .map(|file| self.cache().file_ids.get(file).copied())
+            .collect::<Option<Vec<_>>>()?;
+
+        let constants = self
+            .cached
+            .constants
+            .iter()
+            .map(|constant| self.constant(constant, &file_ids))
+            .collect::<Option<Vec<_>>>()?;
+
+        let globals = self
+            .cached
+            .globals
+            .iter()
+            .map(|location| self.cache().binding_ids.get(location).copied())
+            .collect::<Option<Vec<_>>>()?;
+
+        let spans = self
+            .cached
+            .spans
+            .iter()
+            .map(|span| decode_span(span, &file_ids))
+            .collect();
+
+        Some((constants, globals, spans))
+    }
+
+    fn cache(&self) -> &ComptimeCache {
+        self.sess.interp.comptime_cache.as_ref().unwrap()
+    }
+
+    fn constant(&self, constant: &CachedConst, file_ids: &[FileId]) -> Option<Value> {
+        let value = match constant {
+            CachedConst::I8(v) => Value::I8(*v),
+            CachedConst::I16(v) => Value::I16(*v),
+            CachedConst::I32(v) => Value::I32(*v),
+            CachedConst::I64(v) => Value::I64(*v),
+            CachedConst::Int(v) => Value::Int(*v as isize),
+            CachedConst::U8(v) => Value::U8(*v),
+            CachedConst::U16(v) => Value::U16(*v),
+            CachedConst::U32(v) => Value::U32(*v),
+            CachedConst::U64(v) => Value::U64(*v),
+            CachedConst::Uint(v) => Value::Uint(*v as usize),
+            CachedConst::F32(v) => Value::F32(f32::from_bits(*v)),
+            CachedConst::F64(v) => Value::F64(f64::from_bits(*v)),
+            CachedConst::Bool(v) => Value::Bool(*v),
+            CachedConst::Str(v) => Value::Buffer(Buffer::from_ustr(ustr(v))),
+            CachedConst::Buffer { bytes, ty } => Value::Buffer(Buffer {
+                bytes: ByteSeq::from(bytes.as_slice()),
+                ty: self.ty(ty, file_ids)?,
+            }),
+            CachedConst::Function(location) => {
+                let id = *self.cache().function_ids.get(location)?;
+                let function = self.sess.cache.functions.get(id)?;
+
+                Value::Function(FunctionAddress {
+                    id,
+                    is_extern: function.kind.as_extern().is_some(),
+                    name: function.qualified_name,
+                })
+            }
+            CachedConst::Intrinsic(intrinsic) => Value::Intrinsic(*intrinsic),
+            CachedConst::Type(ty) => Value::Type(self.ty(ty, file_ids)?),
+        };
+
+        Some(value)
+    }
+
+    fn ty(&self, ty: &CachedType, file_ids: &[FileId]) -> Option<Type> {
+        let ty = match ty {
+            CachedType::Never => Type::Never,
+            CachedType::Unit => Type::Unit,
+            CachedType::Bool => Type::Bool,
+            CachedType::Int(ty) => Type::Int(*ty),
+            CachedType::Uint(ty) => Type::Uint(*ty),
+            CachedType::Float(ty) => Type::Float(*ty),
+            CachedType::Pointer(inner, is_mutable) => Type::Pointer(Box::new(self.ty(inner, file_ids)?), *is_mutable),
+            CachedType::Array(inner, len) => Type::Array(Box::new(self.ty(inner, file_ids)?), *len),
+            CachedType::Slice(inner) => Type::Slice(Box::new(self.ty(inner, file_ids)?)),
+            CachedType::Str(inner) => Type::Str(Box::new(self.ty(inner, file_ids)?)),
+            CachedType::Tuple(elements) => Type::Tuple(
+                elements
+                    .iter()
+                    .map(|ty| self.ty(ty, file_ids))
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            CachedType::Struct {
+                name,
+                binding,
+                fields,
+                kind,
+            } => Type::Struct(StructType {
+                name: ustr(name),
+                binding_id: match binding {
+                    Some(location) => Some(*self.cache().binding_ids.get(location)?),
+                    None => None,
+                },
+                fields: fields
+                    .iter()
+                    .map(|field| {
+                        Some(StructTypeField {
+                            name: ustr(&field.name),
+                            ty: self.ty(&field.ty, file_ids)?,
+                            span: decode_span(&field.span, file_ids),
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+                kind: *kind,
+            }),
+            CachedType::Type(inner) => Type::Type(Box::new(self.ty(inner, file_ids)?)),
+            CachedType::AnyType => Type::AnyType,
+        };
+
+        Some(ty)
+    }
+}
+
+fn decode_span(span: &CachedSpan, file_ids: &[FileId]) -> Span {
+    match span.file {
+        Some(file) => Span::new(
+            file_ids[file],
+            Position::new(span.start, span.line, span.column),
+            EndPosition::new(span.end),
+        ),
+        None => Span::unknown(),
+    }
+}
+
+// Pointers in buffers are addresses of this process
+fn holds_pointers(ty: &Type) -> bool {
+    match ty {
+        Type::Pointer(..) | Type::Function(_) => true,
+        Type::Array(inner, _) => holds_pointers(inner),
+        Type::Tuple(elements) => elements.iter().any(holds_pointers),
+        Type::Struct(struct_type) => struct_type.fields.iter().any(|field| holds_pointers(&field.ty)),
+        _ => false,
+    }
+}
+
+impl CachedValue {
+    fn from_const_value(value: &ConstValue) -> Option<Self> {
+        match value {
+            ConstValue::Unit(_) => Some(Self::Unit),
+            ConstValue::Bool(v) => Some(Self::Bool(*v)),
+            ConstValue::Int(v) => Some(Self::Int(*v)),
+            ConstValue::Float(v) => Some(Self::Float(v.to_bits())),
+            ConstValue::Str(v) => Some(Self::Str(v.to_string())),
+            ConstValue::Array(array) => Self::from_values(array.values.iter()).map(Self::Array),
+            ConstValue::Tuple(elements) => Self::from_values(elements.iter().map(|el| &el.value)).map(Self::Tuple),
+            ConstValue::Struct(fields) => Self::from_values(fields.values().map(|el| &el.value)).map(Self::Struct),
+            ConstValue::Type(_) | ConstValue::Function(_) | ConstValue::ExternVariable(_) => None,
+        }
+    }
+
+    fn from_values<'a>(values: impl Iterator<Item = &'a ConstValue>) -> Option<Vec<Self>> {
+        values.map(Self::from_const_value).collect()
+    }
+
+    // Returns None if the value doesn't match `ty`, in which case the entry is ignored
+    fn to_const_value(&self, ty: &Type, tcx: &mut TypeCtx, span: Span) -> Option<ConstValue> {
+        match (self, ty) {
+            (Self::Unit, Type::Unit) => Some(ConstValue::Unit(())),
+            (Self::Bool(v), Type::Bool) => Some(ConstValue::Bool(*v)),
+            (Self::Int(v), Type::Int(_) | Type::Uint(_) | Type::Infer(_, InferType::AnyInt)) => {
+                Some(ConstValue::Int(*v))
+            }
+            (Self::Float(v), Type::Float(_) | Type::Infer(_, InferType::AnyFloat)) => {
+                Some(ConstValue::Float(f64::from_bits(*v)))
+            }
+            (Self::Str(v), Type::Pointer(inner, _)) => match inner.as_ref() {
+                Type::Str(inner) if matches!(inner.as_ref(), Type::Uint(UintType::U8)) => {
+                    Some(ConstValue::Str(ustr(v)))
+                }
+                _ => None,
+            },
+            (Self::Array(values), Type::Array(element_type, len)) if values.len() == *len => {
+                let values = values
+                    .iter()
+                    .map(|value| value.to_const_value(element_type, tcx, span))
+                    .collect::<Option<Vec<_>>>()?;
+
+                Some(ConstValue::Array(ConstArray {
+                    values,
+                    element_type: tcx.bound(element_type.as_ref().clone(), span),
+                }))
+            }
+            (Self::Tuple(values), Type::Tuple(element_types)) if values.len() == element_types.len() => {
+                let elements = values
+                    .iter()
+                    .zip(element_types.iter())
+                    .map(|(value, ty)| {
+                        Some(ConstElement {
+                            value: value.to_const_value(ty, tcx, span)?,
+                            ty: tcx.bound(ty.clone(), span),
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                Some(ConstValue::Tuple(elements))
+            }
+            (Self::Struct(values), Type::Struct(struct_type)) if values.len() == struct_type.fields.len() => {
+                let mut fields = IndexMap::new();
+
+                for (value, field) in values.iter().zip(struct_type.fields.iter()) {
+                    fields.insert(
+                        field.name,
+                        ConstElement {
+                            value: value.to_const_value(&field.ty, tcx, span)?,
+                            ty: tcx.bound(field.ty.clone(), field.span),
+                        },
+                    );
+                }
+
+                Some(ConstValue::Struct(fields))
+            }
+            _ => None,
+        }
+    }
+}
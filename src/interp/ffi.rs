@@ -1,19 +1,29 @@
 use super::{
     interp::Interp,
+    is_64bit,
     vm::{
-        bytecode::Op,
-        value::{ExternFunction, Function, FunctionValue, Pointer, Value},
+        value::{ExternFunction, Function, FunctionValue, Value},
         VM,
     },
-    IS_64BIT,
+    word_size,
+};
+use crate::{
+    hir,
+    types::{size_of::SizeOf, *},
 };
-use crate::types::*;
 use bumpalo::Bump;
 use libffi::{
     low::{ffi_cif, CodePtr},
     middle::{Cif, Closure, Type as FfiType},
 };
-use std::{collections::HashMap, ffi::c_void, path::Path};
+use std::{
+    cell::Cell,
+    collections::{hash_map::Entry, HashMap},
+    ffi::c_void,
+    mem,
+    path::Path,
+    ptr,
+};
 use ustr::{ustr, Ustr, UstrMap};
 
 macro_rules! raw_ptr {
@@ -27,9 +37,17 @@ pub type RawPointer = *mut c_void;
 pub struct Ffi {
     libs: UstrMap<libloading::Library>,
     symbols: HashMap<(Ustr, Ustr), RawPointer>,
+    // Closures are leaked, since foreign code may hold on to them after the interpreter is dropped
+    // (i.e: `atexit`, or a library storing a callback). Calling them after evaluation ended is ignored.
+    closures: HashMap<hir::FunctionId, &'static Closure<'static>>,
     libc: Ustr,
 }
 
+thread_local! {
+    // The VM which is currently calling into foreign code. Used to re-enter the VM from closures.
+    static CURRENT_VM: Cell<*mut c_void> = Cell::new(ptr::null_mut());
+}
+
 fn find_libc() -> String {
     let libc_file_name = match std::env::consts::OS {
         "windows" => return "msvcrt".to_string(),
@@ -55,24 +73,40 @@ impl Ffi {
         Self {
             libs: Default::default(),
             symbols: Default::default(),
+            closures: Default::default(),
             libc: ustr(&find_libc()),
         }
     }
 
     pub unsafe fn load_symbol(&mut self, lib_path: Ustr, name: Ustr) -> &mut RawPointer {
-        self.symbols.entry((lib_path, name)).or_insert_with(|| {
-            let lib_name = match lib_path.as_str() {
-                "c" | "C" => self.libc,
-                _ => lib_path,
-            };
+        if let Err(err) = self.try_load_symbol(lib_path, name) {
+            panic!("{}", err)
+        }
+
+        self.symbols.get_mut(&(lib_path, name)).unwrap()
+    }
 
-            let lib = self
-                .libs
-                .entry(lib_name)
-                .or_insert_with(|| libloading::Library::new(lib_name.as_str()).unwrap());
+    /// Returns the address of the symbol `name` in `lib_path`, loading the library if needed
+    pub unsafe fn try_load_symbol(&mut self, lib_path: Ustr, name: Ustr) -> Result<RawPointer, libloading::Error> {
+        if let Some(symbol) = self.symbols.get(&(lib_path, name)) {
+            return Ok(*symbol);
+        }
+
+        let lib_name = match lib_path.as_str() {
+            "c" | "C" => self.libc,
+            _ => lib_path,
+        };
+
+        let lib = match self.libs.entry(lib_name) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(libloading::Library::new(lib_name.as_str())?),
+        };
+
+        let symbol: RawPointer = *lib.get(name.as_bytes())?;
 
-            *lib.get(name.as_bytes()).unwrap()
-        })
+        self.symbols.insert((lib_path, name), symbol);
+
+        Ok(symbol)
     }
 
     pub unsafe fn call(
@@ -88,6 +122,11 @@ impl Ffi {
         let param_types = function_type.params.iter().map(|p| p.ty.clone()).collect::<Vec<Type>>();
 
         let mut function = if function_type.has_c_varargs() {
+            // variadic arguments go through C's default argument promotions, just like in C
+            for arg in args.iter_mut().skip(function_type.params.len()) {
+                promote_variadic_arg(arg);
+            }
+
             let variadic_arg_types: Vec<Type> = args
                 .iter()
                 .skip(function_type.params.len())
@@ -99,38 +138,109 @@ impl Ffi {
             FfiFunction::new(&param_types, &function_type.return_type)
         };
 
+        let previous_vm = CURRENT_VM.with(|current| current.replace(vm as *mut c_void));
+
         let result = function.call(*symbol, &mut args, self, vm);
 
-        Value::from_type_and_ptr(&function_type.return_type, result as RawPointer)
+        CURRENT_VM.with(|current| current.set(previous_vm));
+
+        result
+    }
+
+    unsafe fn get_or_create_closure(&mut self, function: &Function) -> RawPointer {
+        let closure = self.closures.entry(function.id).or_insert_with(|| {
+            let ffi_function = FfiFunction::new(
+                &function.ty.params.iter().map(|p| &p.ty).cloned().collect::<Vec<Type>>(),
+                &function.ty.return_type,
+            );
+
+            let user_data: &'static ClosureUserData = Box::leak(Box::new(ClosureUserData {
+                id: function.id,
+                name: function.name,
+            }));
+
+            Box::leak(Box::new(Closure::new(ffi_function.cif, closure_callback, user_data)))
+        });
+
+        closure.instantiate_code_ptr::<c_void>() as *const c_void as RawPointer
+    }
+}
+
+/// Returns the first type that can't be passed through ffi, if there is one
+pub(super) fn find_unsupported_ffi_type(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Never
+        | Type::Unit
+        | Type::Bool
+        | Type::Int(_)
+        | Type::Uint(_)
+        | Type::Float(_)
+        | Type::Pointer(_, _)
+        | Type::Array(_, _)
+        | Type::Infer(_, _) => None,
+        Type::Tuple(elements) => elements.iter().find_map(find_unsupported_ffi_type),
+        Type::Struct(struct_type) => match struct_type.kind {
+            // libffi has no notion of unions or packed structs
+            StructTypeKind::Union | StructTypeKind::PackedStruct => Some(ty),
+            StructTypeKind::Struct => struct_type
+                .fields
+                .iter()
+                .find_map(|field| find_unsupported_ffi_type(&field.ty)),
+        },
+        Type::Function(function_type) => {
+            if function_type.has_c_varargs() {
+                return Some(ty);
+            }
+
+            // Note: values of a function type can't be received by a callback, so they are only allowed at the top level
+            function_type
+                .params
+                .iter()
+                .map(|param| &param.ty)
+                .chain(std::iter::once(function_type.return_type.as_ref()))
+                .find_map(|ty| match ty {
+                    Type::Function(_) => Some(ty),
+                    _ => find_unsupported_ffi_type(ty),
+                })
+        }
+        _ => Some(ty),
     }
 }
 
 #[derive(Debug)]
 struct FfiFunction {
     cif: Cif,
+    return_type: Type,
 }
 
 impl FfiFunction {
     unsafe fn new(arg_types: &[Type], return_type: &Type) -> Self {
-        let cif_return_type: FfiType = return_type.as_ffi_type();
-
         let cif_arg_types: Vec<FfiType> = arg_types.iter().map(|arg| arg.as_ffi_type()).collect();
 
-        let cif = Cif::new(cif_arg_types, cif_return_type);
+        let cif = Cif::new(cif_arg_types, return_type.as_ffi_return_type());
 
-        Self { cif }
+        Self {
+            cif,
+            return_type: return_type.clone(),
+        }
     }
 
     unsafe fn new_variadic(arg_types: &[Type], variadic_arg_types: &[Type], return_type: &Type) -> Self {
-        let cif_return_type: FfiType = return_type.as_ffi_type();
-
-        let arg_types: Vec<Type> = arg_types.iter().chain(variadic_arg_types.iter()).cloned().collect();
+        let fixed_arg_count = arg_types.len();
 
-        let cif_arg_types: Vec<FfiType> = arg_types.iter().map(|arg| arg.as_ffi_type()).collect();
+        let cif_arg_types: Vec<FfiType> = arg_types
+            .iter()
+            .chain(variadic_arg_types.iter())
+            .map(|arg| arg.as_ffi_type())
+            .collect();
 
-        let cif = Cif::new_variadic(cif_arg_types, arg_types.len(), cif_return_type);
+        // this uses `ffi_prep_cif_var`, which needs to know where the fixed arguments end
+        let cif = Cif::new_variadic(cif_arg_types, fixed_arg_count, return_type.as_ffi_return_type());
 
-        Self { cif }
+        Self {
+            cif,
+            return_type: return_type.clone(),
+        }
     }
 
     unsafe fn call<'vm>(
@@ -139,7 +249,7 @@ impl FfiFunction {
         arg_values: &mut [Value],
         ffi: &mut Ffi,
         vm: *mut VM<'vm>,
-    ) -> RawPointer {
+    ) -> Value {
         let code_ptr = CodePtr::from_ptr(fun);
 
         let mut args: Vec<RawPointer> = Vec::with_capacity(arg_values.len());
@@ -161,118 +271,144 @@ impl FfiFunction {
                 Value::F32(v) => raw_ptr!(v),
                 Value::F64(v) => raw_ptr!(v),
                 Value::Buffer(buf) => raw_ptr!(buf.bytes.as_mut_ptr()),
-                Value::Pointer(ptr) => match ptr {
-                    Pointer::Buffer(buf) => {
-                        if buf.is_null() {
-                            raw_ptr!(buf)
-                        } else {
-                            raw_ptr!(bump.alloc((&mut **buf).bytes.as_mut_ptr()))
-                        }
-                    }
-                    _ => raw_ptr!(ptr.as_raw()),
-                },
+                Value::Pointer(ptr) => raw_ptr!(&mut ptr.raw),
                 Value::Function(addr) => match (*vm).interp.get_function(addr.id).unwrap() {
                     FunctionValue::Orphan(function) => {
-                        let ffi_function = FfiFunction::new(
-                            &function.ty.params.iter().map(|p| &p.ty).cloned().collect::<Vec<Type>>(),
-                            &function.ty.return_type,
-                        );
-
-                        let user_data = bump.alloc(ClosureUserData { vm, function });
-
-                        let closure = bump.alloc(Closure::new(ffi_function.cif, closure_callback, user_data));
-
-                        let code_ptr = closure.instantiate_code_ptr::<c_void>() as *const c_void as RawPointer;
-
-                        raw_ptr!(code_ptr)
+                        let code_ptr = ffi.get_or_create_closure(function);
+                        raw_ptr!(bump.alloc(code_ptr))
                     }
                     FunctionValue::Extern(function) => {
                         let symbol = ffi.load_symbol(function.lib_path, function.name);
                         raw_ptr!(symbol)
                     }
                 },
-                _ => panic!("can't pass `{}` through ffi", arg.to_string()),
+                // unsupported types are reported when the extern function is lowered
+                _ => unreachable!("can't pass `{}` through ffi", arg.to_string()),
             };
 
             args.push(arg_ptr);
         }
 
-        let mut call_result = std::mem::MaybeUninit::<c_void>::uninit();
+        // The result must be large enough for the return type, and at least the size of a register,
+        // since libffi widens small integral return values
+        let result_size = self.return_type.size_of(word_size()).max(mem::size_of::<u64>());
+        let mut call_result = vec![0u64; (result_size + mem::size_of::<u64>() - 1) / mem::size_of::<u64>()];
 
         libffi::raw::ffi_call(
             self.cif.as_raw_ptr(),
             Some(*code_ptr.as_safe_fun()),
-            call_result.as_mut_ptr(),
+            call_result.as_mut_ptr() as *mut c_void,
             args.as_mut_ptr(),
         );
 
-        call_result.assume_init_mut()
+        Value::from_type_and_ptr(&self.return_type, call_result.as_mut_ptr() as RawPointer)
     }
 }
 
-struct ClosureUserData<'vm> {
-    vm: *mut VM<'vm>,
-    function: *const Function,
+struct ClosureUserData {
+    id: hir::FunctionId,
+    name: Ustr,
 }
 
+// Called by foreign code. Runs the compile-time function on the VM which is currently calling into foreign code.
+// Note that this supports re-entrancy - the function can call into foreign code, which calls back into the VM, etc.
 // TODO: closures don't work in multithreaded code right now.
-// TODO: this isn't a big issue as compile-time code isn't meant to be very complex.
-// TODO: but, this should be fixed.
 unsafe extern "C" fn closure_callback(
-    _cif: &ffi_cif,
+    cif: &ffi_cif,
     result: &mut c_void,
     args: *const *const c_void,
     userdata: &ClosureUserData,
 ) {
-    let mut func = (&*userdata.function).clone();
-    let arg_count = func.ty.params.len();
+    let vm = CURRENT_VM.with(|current| current.get()) as *mut VM;
+
+    if vm.is_null() {
+        // There is no VM to run the function on, and no way to report a diagnostic from here.
+        // Returning a made up result would let foreign code continue with garbage, so abort instead.
+        eprintln!(
+            "error: compile-time function `{}` was called by foreign code after compile-time evaluation ended",
+            userdata.name
+        );
+        eprintln!("note: functions passed to foreign code at compile-time can only be called during the foreign call");
 
-    // set up function args
-    if arg_count > 0 {
-        let args = std::slice::from_raw_parts(args, arg_count);
-        for (param, arg) in func.ty.params.iter().zip(args) {
-            let value = Value::from_type_and_ptr(&param.ty, *arg as RawPointer);
-            (*userdata.vm).stack.push(value);
-        }
+        std::process::abort();
     }
 
-    // we need the VM to Halt instead of Return
-    *func.code.as_mut_slice().last_mut().unwrap() = u8::from(Op::Halt);
+    let function = match (*vm).interp.get_function(userdata.id) {
+        Some(FunctionValue::Orphan(function)) => function as *const Function,
+        _ => panic!("couldn't find function `{}`", userdata.name),
+    };
 
-    let value = (*userdata.vm).run_function(func);
+    let function_type = &(*function).ty;
 
-    // pop the function args manually
-    if arg_count > 0 {
-        for _ in 0..arg_count {
-            (*userdata.vm).stack.pop();
+    // set up function args
+    let arg_values = if function_type.params.is_empty() {
+        vec![]
+    } else {
+        let args = std::slice::from_raw_parts(args, function_type.params.len());
+
+        function_type
+            .params
+            .iter()
+            .zip(args)
+            .map(|(param, arg)| Value::from_type_and_ptr(&param.ty, *arg as RawPointer))
+            .collect()
+    };
+
+    match (*vm).run_nested_function(function, arg_values) {
+        Ok(value) => write_closure_result(value, result as *mut c_void),
+        Err(error) => {
+            // the error can't unwind through foreign code, so it is returned once the foreign call returns.
+            // until then, foreign code gets a zeroed result.
+            (*vm).foreign_error.get_or_insert(error);
+            ptr::write_bytes(result as *mut c_void as *mut u8, 0, (*cif.rtype).size);
         }
     }
+}
 
-    // emulate a return, popping the current frame, and setting the last one
-    (*userdata.vm).frames.pop();
-    (*userdata.vm).frame = (*userdata.vm).frames.last_mut() as _;
-
+unsafe fn write_closure_result(value: Value, result: RawPointer) {
+    // Note: libffi expects integral return values smaller than a register to be widened
     match value {
-        Value::I8(v) => *(result as *mut _ as *mut _) = v,
-        Value::I16(v) => *(result as *mut _ as *mut _) = v,
-        Value::I32(v) => *(result as *mut _ as *mut _) = v,
-        Value::I64(v) => *(result as *mut _ as *mut _) = v,
-        Value::Int(v) => *(result as *mut _ as *mut _) = v,
-        Value::U8(v) => *(result as *mut _ as *mut _) = v,
-        Value::U16(v) => *(result as *mut _ as *mut _) = v,
-        Value::U32(v) => *(result as *mut _ as *mut _) = v,
-        Value::U64(v) => *(result as *mut _ as *mut _) = v,
-        Value::Uint(v) => *(result as *mut _ as *mut _) = v,
-        Value::F32(v) => *(result as *mut _ as *mut _) = v,
-        Value::F64(v) => *(result as *mut _ as *mut _) = v,
-        Value::Bool(v) => *(result as *mut _ as *mut _) = v,
-        Value::Pointer(v) => *(result as *mut _ as *mut _) = v.as_inner_raw(),
-        _ => panic!("unexpected value `{}`", value.to_string()),
+        Value::I8(v) => *(result as *mut isize) = v as isize,
+        Value::I16(v) => *(result as *mut isize) = v as isize,
+        Value::I32(v) => *(result as *mut isize) = v as isize,
+        Value::I64(v) => *(result as *mut i64) = v,
+        Value::Int(v) => *(result as *mut isize) = v,
+        Value::U8(v) => *(result as *mut usize) = v as usize,
+        Value::U16(v) => *(result as *mut usize) = v as usize,
+        Value::U32(v) => *(result as *mut usize) = v as usize,
+        Value::U64(v) => *(result as *mut u64) = v,
+        Value::Uint(v) => *(result as *mut usize) = v,
+        Value::Bool(v) => *(result as *mut usize) = v as usize,
+        Value::F32(v) => *(result as *mut f32) = v,
+        Value::F64(v) => *(result as *mut f64) = v,
+        Value::Pointer(v) => *(result as *mut RawPointer) = v.raw,
+        Value::Buffer(buf) => {
+            // structs and tuples are returned by value, unit has no size
+            ptr::copy_nonoverlapping(buf.bytes.as_ptr(), result as *mut u8, buf.bytes.len())
+        }
+        _ => unreachable!("unexpected value `{}`", value.to_string()),
     }
 }
 
+// Applies C's default argument promotions to a variadic argument
+fn promote_variadic_arg(arg: &mut Value) {
+    let promoted = match arg {
+        Value::I8(v) => Value::I32(*v as i32),
+        Value::I16(v) => Value::I32(*v as i32),
+        Value::U8(v) => Value::I32(*v as i32),
+        Value::U16(v) => Value::I32(*v as i32),
+        Value::Bool(v) => Value::I32(*v as i32),
+        Value::F32(v) => Value::F64(*v as f64),
+        _ => return,
+    };
+
+    *arg = promoted;
+}
+
 trait AsFfiType {
     unsafe fn as_ffi_type(&self) -> FfiType;
+
+    unsafe fn as_ffi_return_type(&self) -> FfiType;
 }
 
 impl AsFfiType for Type {
@@ -285,7 +421,7 @@ impl AsFfiType for Type {
                 IntType::I32 => FfiType::i32(),
                 IntType::I64 => FfiType::i64(),
                 IntType::Int => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         FfiType::i64()
                     } else {
                         FfiType::i32()
@@ -298,7 +434,7 @@ impl AsFfiType for Type {
                 UintType::U32 => FfiType::u32(),
                 UintType::U64 => FfiType::u64(),
                 UintType::Uint => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         FfiType::u64()
                     } else {
                         FfiType::u32()
@@ -309,27 +445,36 @@ impl AsFfiType for Type {
                 FloatType::F16 | FloatType::F32 => FfiType::f32(),
                 FloatType::F64 => FfiType::f64(),
                 FloatType::Float => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         FfiType::f64()
                     } else {
                         FfiType::f32()
                     }
                 }
             },
-            Type::Unit | Type::Pointer(_, _) | Type::Function(_) | Type::Array(_, _) => FfiType::pointer(),
+            Type::Unit | Type::Pointer(_, _) | Type::Function(_) => FfiType::pointer(),
             Type::Slice(_) | Type::Str(_) => FfiType::structure([FfiType::pointer(), FfiType::usize()]),
-            Type::Tuple(tuple_elements) => FfiType::structure(tuple_elements.iter().map(|ty| ty.as_ffi_type())),
-            Type::Struct(st) => FfiType::structure(st.fields.iter().map(|f| f.ty.as_ffi_type())),
+            // libffi has no array types, an array passed by value is laid out like a struct of its elements
+            Type::Array(inner, size) => FfiType::structure((0..*size).map(|_| inner.as_ffi_type())),
+            Type::Tuple(tuple_elements) => FfiType::structure(
+                tuple_elements
+                    .iter()
+                    .filter(|ty| !ty.is_unit())
+                    .map(|ty| ty.as_ffi_type()),
+            ),
+            Type::Struct(st) => {
+                FfiType::structure(st.fields.iter().filter(|f| !f.ty.is_unit()).map(|f| f.ty.as_ffi_type()))
+            }
             Type::Infer(_, ty) => match ty {
                 InferType::AnyInt => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         FfiType::i64()
                     } else {
                         FfiType::i32()
                     }
                 }
                 InferType::AnyFloat => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         FfiType::f64()
                     } else {
                         FfiType::f32()
@@ -340,4 +485,11 @@ impl AsFfiType for Type {
             _ => panic!("invalid type {:?}", self),
         }
     }
+
+    unsafe fn as_ffi_return_type(&self) -> FfiType {
+        match self {
+            Type::Unit | Type::Never => FfiType::void(),
+            _ => self.as_ffi_type(),
+        }
+    }
 }
@@ -1,22 +1,23 @@
 use super::{
+    cache::ComptimeCache,
     ffi::Ffi,
     lower::{Lower, LowerContext},
     vm::{
-        bytecode::{Bytecode, Inst},
+        bytecode::{Bytecode, Inst, Register},
+        debugger::Debugger,
         disassemble::dump_bytecode_to_file,
+        optimize::optimize,
+        profiler::Profiler,
         value::{ExternFunction, Function, FunctionAddress, FunctionValue, Value},
         Constants, Globals, VM,
     },
 };
 use crate::{
-    common::{
-        build_options::BuildOptions,
-        scopes::Scopes,
-        target::{TargetMetrics, TargetPlatform},
-    },
+    common::{build_options::BuildOptions, scopes::Scopes, target::TargetMetrics},
     error::diagnostic::Diagnostic,
     hir,
     infer::type_ctx::TypeCtx,
+    span::Span,
     types::{FunctionType, FunctionTypeKind, Type},
     workspace::{BindingId, ModuleId, Workspace},
 };
@@ -38,20 +39,44 @@ pub struct Interp {
     pub ffi: Ffi,
     pub build_options: BuildOptions,
 
-    bindings_to_globals: HashMap<BindingId, usize>,
+    // Collects profiling information about executed code, if `--profile-comptime` is enabled
+    pub profiler: Option<Profiler>,
+
+    // Pauses execution at breakpoints and steps through code, if `--debug-comptime` is enabled
+    pub debugger: Option<Debugger>,
+
+    // Source code passed to `inject_bindings`, which is parsed and checked after evaluation ends
+    pub injected_bindings: Vec<InjectedBindings>,
+
+    // Lowered functions and results of pure evaluations from previous builds, if `--no-comptime-cache` isn't passed
+    pub comptime_cache: Option<ComptimeCache>,
+
+    // Set when the running code does something that makes its result impure, such as calling foreign code,
+    // calling an intrinsic or accessing a global. The results of impure evaluations aren't cached.
+    pub has_side_effects: bool,
+
+    pub(super) bindings_to_globals: HashMap<BindingId, usize>,
 }
 
 impl Interp {
     pub fn new(build_options: BuildOptions) -> Self {
         Self {
-            target_metrics: TargetPlatform::current()
-                .unwrap_or_else(|name| panic!("target platform `{}` is not supported yet", name))
-                .metrics(),
+            target_metrics: build_options.target_platform.metrics(),
             globals: vec![],
             constants: vec![Value::unit()],
             functions: HashMap::new(),
             extern_functions: HashMap::new(),
             ffi: Ffi::new(),
+            profiler: build_options.profile_comptime.then(Profiler::default),
+            debugger: build_options.debug_comptime.then(Debugger::new),
+            injected_bindings: vec![],
+            // the cache is skipped when comptime code is observed, and in check mode, where code isn't evaluated
+            comptime_cache: (build_options.comptime_cache
+                && !build_options.profile_comptime
+                && !build_options.debug_comptime
+                && !build_options.check_mode)
+                .then(|| ComptimeCache::load(&build_options.source_file)),
+            has_side_effects: false,
             build_options,
             bindings_to_globals: HashMap::new(),
         }
@@ -85,6 +110,13 @@ impl Interp {
     }
 }
 
+#[derive(Debug)]
+pub struct InjectedBindings {
+    pub source: String,
+    // The location of the call to `inject_bindings`
+    pub span: Span,
+}
+
 pub struct InterpSess<'i> {
     pub interp: &'i mut Interp,
     pub workspace: &'i Workspace,
@@ -98,7 +130,8 @@ pub struct InterpSess<'i> {
     // Globals that are going to be statically initialized when the VM starts
     pub statically_initialized_globals: Vec<Bytecode>,
 
-    // Functions currently lowered, cached to prevent infinite recursion in recursive functions
+    // Functions currently lowered, cached to prevent infinite recursion in recursive functions.
+    // Also includes the global initializers, which are optimized along with them before the VM runs.
     pub lowered_functions: HashSet<hir::FunctionId>,
 
     pub bump: Bump,
@@ -118,28 +151,58 @@ impl LoopEnv {
     }
 }
 
-pub(super) type Env = Scopes<BindingId, i16>;
+pub(super) type Env = Scopes<BindingId, Register>;
 
 impl<'i> InterpSess<'i> {
     pub fn eval(&'i mut self, node: &hir::Node, module_id: ModuleId) -> InterpResult {
         let mut start_code = Bytecode::new();
+        let result_register = start_code.alloc_register();
 
         // lower expression tree into instructions
         self.env_stack.push((module_id, Env::default()));
 
         self.env_mut().push_scope();
-        node.lower(self, &mut start_code, LowerContext { take_ptr: false });
+        node.lower(
+            self,
+            &mut start_code,
+            LowerContext {
+                take_ptr: false,
+                dst: result_register,
+            },
+        );
         self.env_mut().pop_scope();
 
         if self.diagnostics.is_empty() {
-            start_code.write_inst(Inst::Halt);
+            start_code.write_inst(Inst::Return { src: result_register });
 
-            let start_code = self.insert_init_instructions(start_code);
+            let mut start_code = self.insert_init_instructions(start_code);
 
             self.env_stack.pop();
 
             if self.workspace.build_options.emit_bytecode {
-                dump_bytecode_to_file(&self.interp, &start_code);
+                dump_bytecode_to_file(&self.interp, &start_code, "vm.unoptimized.out");
+            }
+
+            self.optimize_lowered_code(&mut start_code);
+            self.cache_lowered_functions();
+
+            if self.workspace.build_options.emit_bytecode {
+                dump_bytecode_to_file(&self.interp, &start_code, "vm.out");
+            }
+
+            if let Some(profiler) = &mut self.interp.profiler {
+                let module_info = self.workspace.module_infos.get(module_id).unwrap();
+                let span = node.span();
+
+                profiler.begin_block(format!(
+                    "comptime@{}:{}:{}",
+                    module_info.file_path, span.start.line, span.start.column
+                ));
+            }
+
+            if let Some(debugger) = &mut self.interp.debugger {
+                debugger.sync_files(&self.workspace.diagnostics);
+                debugger.begin_block();
             }
 
             let mut vm = self.create_vm();
@@ -154,18 +217,45 @@ impl<'i> InterpSess<'i> {
                     kind: FunctionTypeKind::Orphan,
                 },
                 code: start_code,
+                runtime_checks: true,
             };
 
             let result = vm.run_function(start_func);
 
-            Ok(result)
+            if let Some(profiler) = &mut vm.interp.profiler {
+                profiler.end_block();
+            }
+
+            result.map_err(|error| vec![error.into_diagnostic(node.span())])
         } else {
             Err(self.diagnostics.clone())
         }
     }
 
+    // optimizes the code lowered in this session, which hasn't been executed yet
+    fn optimize_lowered_code(&mut self, start_code: &mut Bytecode) {
+        let interp = &mut *self.interp;
+
+        // the debugger steps through instructions and inspects locals by register,
+        // which the optimizer may fold away or merge
+        if interp.debugger.is_some() {
+            return;
+        }
+
+        for id in self.lowered_functions.iter() {
+            if let Some(function) = interp.functions.get_mut(id) {
+                optimize(&mut function.code, &mut interp.constants);
+            }
+        }
+
+        optimize(start_code, &mut interp.constants);
+    }
+
     // pushes initialization instructions such as global evaluation to the start
-    fn insert_init_instructions(&mut self, mut code: Bytecode) -> Bytecode {
+    fn insert_init_instructions(&mut self, code: Bytecode) -> Bytecode {
+        let mut init_code = Bytecode::new();
+        let callee = init_code.alloc_register();
+
         for (i, global_eval_code) in self.statically_initialized_globals.iter().enumerate() {
             let const_slot = self.interp.constants.len();
 
@@ -184,36 +274,56 @@ impl<'i> InterpSess<'i> {
                         kind: FunctionTypeKind::Orphan,
                     },
                     code: global_eval_code.clone(),
+                    runtime_checks: true,
                 },
             );
 
+            self.lowered_functions.insert(id);
+
             self.interp.constants.push(Value::Function(FunctionAddress {
                 id,
                 is_extern: false,
                 name,
             }));
 
-            code.write_inst(Inst::LoadConst(const_slot as u32));
+            init_code.write_inst(Inst::LoadConst {
+                dst: callee,
+                slot: const_slot as u32,
+            });
+
+            init_code.write_inst(Inst::Call {
+                dst: callee,
+                callee,
+                args: callee + 1,
+                arg_count: 0,
+            });
         }
 
-        code
+        init_code.append(code);
+
+        init_code
     }
 
     pub fn create_vm(&'i mut self) -> VM<'i> {
-        VM::new(self.interp, &mut self.bump)
+        VM::new(self.interp, self.workspace, &mut self.bump)
     }
 
-    pub fn push_const(&mut self, code: &mut Bytecode, value: Value) -> usize {
+    pub fn add_const(&mut self, value: Value) -> u32 {
         let slot = self.interp.constants.len();
         self.interp.constants.push(value);
-        code.write_inst(Inst::LoadConst(slot as u32));
+        slot as u32
+    }
+
+    pub fn push_const(&mut self, code: &mut Bytecode, dst: Register, value: Value) -> u32 {
+        let slot = self.add_const(value);
+        code.write_inst(Inst::LoadConst { dst, slot });
         slot
     }
 
-    pub fn push_const_unit(&mut self, code: &mut Bytecode) {
+    pub fn push_const_unit(&mut self, code: &mut Bytecode, dst: Register) {
         // to avoid redundancy, when pushing a unit value,
         // we just use the first value in the constants vec
-        code.write_inst(Inst::LoadConst(0));
+        code.write_inst(Inst::LoadConst { dst, slot: 0 });
     }
 
     pub fn insert_global(&mut self, id: BindingId, value: Value) -> usize {
@@ -260,9 +370,4 @@ impl<'i> InterpSess<'i> {
                 )
             })
     }
-
-    pub fn add_local(&mut self, code: &mut Bytecode, id: BindingId) {
-        self.env_mut().insert(id, code.locals as i16);
-        code.locals += 1;
-    }
 }
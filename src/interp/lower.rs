@@ -1,11 +1,13 @@
 use super::{
+    ffi::find_unsupported_ffi_type,
     interp::{Env, InterpSess, LoopEnv},
+    is_64bit,
     vm::{
         byte_seq::{ByteSeq, PutValue},
-        bytecode::{Bytecode, Inst},
-        value::{Buffer, ExternFunction, ExternVariable, Function, IntrinsicFunction, Value},
+        bytecode::{Bytecode, Inst, JmpTarget, Register, INVALID_JMP_TARGET},
+        value::{Buffer, ExternFunction, ExternVariable, Function, IntrinsicFunction, Pointer, Value, ValueKind},
     },
-    IS_64BIT, WORD_SIZE,
+    word_size, wrap_int, wrap_uint, HOST_WORD_SIZE,
 };
 use crate::{
     error::diagnostic::{Diagnostic, Label},
@@ -13,17 +15,19 @@ use crate::{
         self,
         const_value::{ConstExternVariable, ConstValue},
     },
-    infer::normalize::Normalize,
+    infer::{display::DisplayType, normalize::Normalize},
     interp::vm::value::FunctionAddress,
+    span::Span,
     types::{offset_of::OffsetOf, size_of::SizeOf, FloatType, InferType, IntType, Type, TypeId, UintType},
     workspace::{BindingId, BindingInfoKind},
 };
-use byteorder::{NativeEndian, WriteBytesExt};
 use ustr::ustr;
 
 #[derive(Clone, Copy)]
 pub struct LowerContext {
     pub take_ptr: bool,
+    // The register the lowered value is written to
+    pub dst: Register,
 }
 
 pub trait Lower {
@@ -32,6 +36,8 @@ pub trait Lower {
 
 impl Lower for hir::Node {
     fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        let prev_span = code.set_span(self.span());
+
         match self {
             hir::Node::Const(x) => x.lower(sess, code, ctx),
             hir::Node::Binding(x) => x.lower(sess, code, ctx),
@@ -45,13 +51,20 @@ impl Lower for hir::Node {
             hir::Node::Builtin(x) => x.lower(sess, code, ctx),
             hir::Node::Literal(x) => x.lower(sess, code, ctx),
         }
+
+        code.set_span(prev_span);
     }
 }
 
 impl Lower for hir::Const {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        if let ConstValue::ExternVariable(variable) = &self.value {
+            lower_extern_variable(variable, self.span, sess, code, ctx);
+            return;
+        }
+
         let value = const_value_to_value(&self.value, self.ty, sess);
-        sess.push_const(code, value);
+        sess.push_const(code, ctx.dst, value);
     }
 }
 
@@ -64,21 +77,36 @@ impl Lower for hir::Function {
         let function_type = self.ty.normalize(sess.tcx).into_function();
 
         match &self.kind {
+            hir::FunctionKind::Orphan { .. } if sess.load_cached_function(self) => {
+                // cached code was optimized when it was stored, so it isn't optimized again
+                sess.lowered_functions.remove(&self.id);
+            }
             hir::FunctionKind::Orphan { params, body, .. } => {
                 sess.env_mut().push_scope();
 
                 let mut function_code = Bytecode::new();
 
-                for (index, param) in params.iter().enumerate() {
-                    let offset = -(params.len() as i16) + index as i16;
-                    sess.env_mut().insert(param.id, offset);
+                // the caller places the arguments in the function's first registers
+                for param in params.iter() {
+                    let register = function_code.alloc_local();
+                    sess.env_mut().insert(param.id, register);
+
+                    let name = sess.workspace.binding_infos.get(param.id).unwrap().name;
+                    function_code.name_local(register, name);
                 }
 
-                body.as_ref()
-                    .unwrap()
-                    .lower(sess, &mut function_code, LowerContext { take_ptr: false });
+                let result_register = function_code.alloc_register();
+
+                body.as_ref().unwrap().lower(
+                    sess,
+                    &mut function_code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: result_register,
+                    },
+                );
 
-                function_code.write_inst(Inst::Return);
+                function_code.write_inst(Inst::Return { src: result_register });
 
                 sess.env_mut().pop_scope();
 
@@ -89,6 +117,7 @@ impl Lower for hir::Function {
                         name: self.qualified_name,
                         ty: function_type,
                         code: function_code,
+                        runtime_checks: !self.no_runtime_checks,
                     },
                 );
             }
@@ -110,6 +139,33 @@ impl Lower for hir::Function {
                     |lib| ustr(&lib.path()),
                 );
 
+                if word_size() != HOST_WORD_SIZE {
+                    sess.diagnostics.push(
+                        Diagnostic::error()
+                            .with_message(format!(
+                                "cannot call extern function `{}` at compile-time when cross-compiling",
+                                self.name
+                            ))
+                            .with_label(Label::primary(self.span, "cannot use during compile-time"))
+                            .with_note(word_size_mismatch_note()),
+                    );
+
+                    return;
+                }
+
+                let unsupported_type = function_type
+                    .params
+                    .iter()
+                    .map(|param| &param.ty)
+                    .chain(std::iter::once(function_type.return_type.as_ref()))
+                    .find_map(find_unsupported_ffi_type);
+
+                if let Some(ty) = unsupported_type {
+                    let diagnostic = unsupported_ffi_type_diagnostic(ty, self.span, sess);
+                    sess.diagnostics.push(diagnostic);
+                    return;
+                }
+
                 sess.interp.extern_functions.insert(
                     self.id,
                     ExternFunction {
@@ -127,21 +183,7 @@ impl Lower for hir::Function {
 }
 
 impl Lower for hir::Binding {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
-        if let Some(ConstValue::ExternVariable(ConstExternVariable { lib: None, .. })) = self.value.as_const_value() {
-            sess.diagnostics.push(
-                Diagnostic::error()
-                    .with_message(format!(
-                        "must specify a library to use extern variable `{}` at compile-time",
-                        self.name
-                    ))
-                    .with_label(Label::primary(self.span, "cannot use during compile-time"))
-                    .with_note("add #[lib = \"your_lib\"] above the declaration"),
-            );
-
-            return;
-        }
-
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let binding_info = sess.workspace.binding_infos.get(self.id).unwrap();
 
         match &binding_info.kind {
@@ -149,15 +191,24 @@ impl Lower for hir::Binding {
                 lower_static_binding(self, sess);
             }
             _ => {
-                self.value.lower(sess, code, LowerContext { take_ptr: false });
+                // the value is lowered straight into the local's register
+                let register = code.alloc_local();
 
-                sess.add_local(code, self.id);
-                let last_local = code.locals as i32 - 1;
-                code.write_inst(Inst::StoreLocal(last_local));
+                self.value.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: register,
+                    },
+                );
+
+                sess.env_mut().insert(self.id, register);
+                code.name_local(register, self.name);
             }
         }
 
-        sess.push_const_unit(code);
+        sess.push_const_unit(code, ctx.dst);
     }
 }
 
@@ -168,13 +219,20 @@ impl Lower for hir::Id {
                 // Note (Ron): We do nothing with modules, since they are not an actual value
             }
             _ => {
-                if let Some(slot) = sess.env().value(self.id) {
-                    let slot = *slot as i32;
+                if let Some(&register) = sess.env().value(self.id) {
                     code.write_inst(if ctx.take_ptr {
-                        Inst::PeekPtr(slot)
+                        Inst::RegisterPtr {
+                            dst: ctx.dst,
+                            src: register,
+                        }
                     } else {
-                        Inst::Peek(slot)
+                        Inst::Move {
+                            dst: ctx.dst,
+                            src: register,
+                        }
                     });
+                } else if let Some(ConstValue::ExternVariable(variable)) = extern_variable_value(self.id, sess) {
+                    lower_extern_variable(&variable, self.span, sess, code, ctx);
                 } else {
                     let slot = sess
                         .get_global(self.id)
@@ -182,9 +240,9 @@ impl Lower for hir::Id {
                         as u32;
 
                     code.write_inst(if ctx.take_ptr {
-                        Inst::LoadGlobalPtr(slot)
+                        Inst::LoadGlobalPtr { dst: ctx.dst, slot }
                     } else {
-                        Inst::LoadGlobal(slot)
+                        Inst::LoadGlobal { dst: ctx.dst, slot }
                     });
                 }
             }
@@ -193,120 +251,285 @@ impl Lower for hir::Id {
 }
 
 impl Lower for hir::Assign {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
-        self.rhs.lower(sess, code, LowerContext { take_ptr: false });
-        self.lhs.lower(sess, code, LowerContext { take_ptr: true });
-        code.write_inst(Inst::Assign);
-        sess.push_const_unit(code);
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        let mark = code.register_mark();
+
+        let rhs = lower_to_register(&self.rhs, sess, code, false);
+
+        match local_register(&self.lhs, sess) {
+            Some(register) => {
+                code.write_inst(Inst::Move {
+                    dst: register,
+                    src: rhs,
+                });
+            }
+            None => {
+                let lhs = lower_to_register(&self.lhs, sess, code, true);
+                code.write_inst(Inst::Assign { ptr: lhs, src: rhs });
+            }
+        }
+
+        code.free_registers(mark);
+
+        sess.push_const_unit(code, ctx.dst);
     }
 }
 
 impl Lower for hir::MemberAccess {
     fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
-        self.value.lower(sess, code, ctx);
+        let mark = code.register_mark();
+
+        let value = lower_to_register(&self.value, sess, code, ctx.take_ptr);
+
         code.write_inst(if ctx.take_ptr {
-            Inst::ConstIndexPtr(self.member_index)
+            Inst::ConstIndexPtr {
+                dst: ctx.dst,
+                src: value,
+                index: self.member_index,
+            }
         } else {
-            Inst::ConstIndex(self.member_index)
+            Inst::ConstIndex {
+                dst: ctx.dst,
+                src: value,
+                index: self.member_index,
+            }
         });
+
+        code.free_registers(mark);
     }
 }
 
 impl Lower for hir::Call {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
-        for arg in self.args.iter() {
-            arg.lower(sess, code, LowerContext { take_ptr: false });
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        if let Type::Function(function_type) = self.callee.ty().normalize(sess.tcx) {
+            if function_type.kind.is_extern() && function_type.has_c_varargs() {
+                for arg in self.args.iter().skip(function_type.params.len()) {
+                    let arg_type = arg.ty().normalize(sess.tcx);
+
+                    if let Some(ty) = find_unsupported_ffi_type(&arg_type) {
+                        let diagnostic = unsupported_ffi_type_diagnostic(ty, arg.span(), sess);
+                        sess.diagnostics.push(diagnostic);
+                    }
+                }
+            }
         }
 
-        self.callee.lower(sess, code, LowerContext { take_ptr: false });
+        let mark = code.register_mark();
+
+        let callee = lower_to_register(&self.callee, sess, code, false);
+
+        // the arguments are passed in consecutive registers
+        let args = code.alloc_registers(self.args.len());
 
-        code.write_inst(Inst::Call(self.args.len() as u32));
+        for (index, arg) in self.args.iter().enumerate() {
+            let arg_mark = code.register_mark();
+
+            arg.lower(
+                sess,
+                code,
+                LowerContext {
+                    take_ptr: false,
+                    dst: args + index as Register,
+                },
+            );
+
+            code.free_registers(arg_mark);
+        }
+
+        code.write_inst(Inst::Call {
+            dst: ctx.dst,
+            callee,
+            args,
+            arg_count: self.args.len() as u32,
+        });
+
+        code.free_registers(mark);
     }
 }
 
 impl Lower for hir::Cast {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let target_type = self.ty.normalize(sess.tcx);
 
+        // pointers in registers are host addresses, which don't fit in a narrower target's integers
+        if word_size() != HOST_WORD_SIZE {
+            let value_type = self.value.ty().normalize(sess.tcx);
+
+            // zero is the null pointer, which is the same on every platform
+            let is_null = matches!(
+                self.value.as_ref(),
+                hir::Node::Const(hir::Const {
+                    value: ConstValue::Int(0),
+                    ..
+                })
+            );
+
+            if !is_null && is_pointer_int_cast(&value_type, &target_type) {
+                sess.diagnostics.push(
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "cannot cast `{}` to `{}` at compile-time when cross-compiling",
+                            value_type.display(sess.tcx),
+                            target_type.display(sess.tcx)
+                        ))
+                        .with_label(Label::primary(self.span, "cannot use during compile-time"))
+                        .with_note(format!(
+                            "the target's word size ({} bytes) differs from the host's ({} bytes), so pointers and integers can't be cast to each other",
+                            word_size(),
+                            HOST_WORD_SIZE
+                        )),
+                );
+
+                return;
+            }
+        }
+
         match target_type {
             Type::Never | Type::Unit | Type::Bool => {
-                self.value.lower(sess, code, LowerContext { take_ptr: false });
+                self.value.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: ctx.dst,
+                    },
+                );
             }
             Type::Pointer(ref inner, _) => match inner.as_ref() {
                 Type::Slice(_) | Type::Str(_) => {
-                    let value_type_size = target_type.size_of(WORD_SIZE) as u32;
-                    let inner_type_size = target_type.element_type().unwrap().size_of(WORD_SIZE);
-
-                    sess.push_const(code, Value::Type(target_type.clone()));
-                    code.write_inst(Inst::BufferAlloc(value_type_size));
-
-                    self.value.lower(sess, code, LowerContext { take_ptr: false });
+                    let value_type_size = target_type.size_of(word_size()) as u32;
+                    let value_type = self.value.ty().normalize(sess.tcx);
 
-                    // calculate the new slice's offset
-                    sess.push_const(code, Value::Uint(0));
-                    sess.push_const(code, Value::Uint(inner_type_size));
-                    code.write_inst(Inst::Mul);
-                    code.write_inst(Inst::Offset);
+                    let mark = code.register_mark();
 
-                    let value_type = self.value.ty().normalize(sess.tcx);
+                    let value = lower_to_register(&self.value, sess, code, false);
 
-                    code.write_inst(Inst::BufferPut(0));
+                    let data = code.alloc_register();
+                    let len = code.alloc_register();
 
-                    // calculate the slice length, by doing `high - low`
+                    // the new slice starts where the value starts, and spans all of its elements
                     match &value_type {
                         Type::Array(_, size) => {
-                            sess.push_const(code, Value::Uint(*size));
+                            sess.push_const(code, data, Value::Uint(0));
+                            code.write_inst(Inst::Offset {
+                                dst: data,
+                                src: value,
+                                offset: data,
+                            });
+                            sess.push_const(code, len, Value::Uint(*size));
                         }
                         Type::Pointer(inner, _) => match inner.as_ref() {
                             Type::Array(_, size) => {
-                                sess.push_const(code, Value::Uint(*size));
+                                sess.push_const(code, data, Value::Uint(0));
+                                code.write_inst(Inst::Offset {
+                                    dst: data,
+                                    src: value,
+                                    offset: data,
+                                });
+                                sess.push_const(code, len, Value::Uint(*size));
                             }
                             Type::Slice(_) | Type::Str(_) => {
-                                code.write_inst(Inst::ConstIndex(1));
+                                code.write_inst(Inst::ConstIndex {
+                                    dst: data,
+                                    src: value,
+                                    index: 0,
+                                });
+                                code.write_inst(Inst::ConstIndex {
+                                    dst: len,
+                                    src: value,
+                                    index: 1,
+                                });
                             }
                             _ => unreachable!("unexpected type `{:?}`", value_type),
                         },
                         ty => unreachable!("unexpected type `{:?}`", ty),
                     }
 
-                    sess.push_const(code, Value::Uint(0));
-                    code.write_inst(Inst::Sub);
+                    let ty = sess.add_const(Value::Type(target_type.clone()));
 
-                    code.write_inst(Inst::BufferPut(WORD_SIZE as u32));
-                }
-                _ => {
-                    self.value.lower(sess, code, LowerContext { take_ptr: false });
-                    sess.push_const(code, Value::Type(target_type));
-                    code.write_inst(Inst::Cast);
+                    code.write_inst(Inst::BufferAlloc {
+                        dst: ctx.dst,
+                        ty,
+                        size: value_type_size,
+                    });
+
+                    code.write_inst(Inst::BufferPut {
+                        buf: ctx.dst,
+                        offset: 0,
+                        src: data,
+                    });
+
+                    code.write_inst(Inst::BufferPut {
+                        buf: ctx.dst,
+                        offset: word_size() as u32,
+                        src: len,
+                    });
+
+                    code.free_registers(mark);
                 }
+                _ => lower_cast(&self.value, target_type.clone(), sess, code, ctx),
             },
-            _ => {
-                self.value.lower(sess, code, LowerContext { take_ptr: false });
-                sess.push_const(code, Value::Type(target_type));
-                code.write_inst(Inst::Cast);
-            }
+            _ => lower_cast(&self.value, target_type, sess, code, ctx),
         }
     }
 }
 
+fn is_pointer_int_cast(from: &Type, to: &Type) -> bool {
+    let is_int = |ty: &Type| matches!(ty, Type::Int(_) | Type::Uint(_) | Type::Infer(_, InferType::AnyInt));
+    (from.is_pointer() && is_int(to)) || (is_int(from) && to.is_pointer())
+}
+
+fn lower_cast(value: &hir::Node, target_type: Type, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+    let mark = code.register_mark();
+
+    let value = lower_to_register(value, sess, code, false);
+    let ty = sess.add_const(Value::Type(target_type));
+
+    code.write_inst(Inst::Cast {
+        dst: ctx.dst,
+        src: value,
+        ty,
+    });
+
+    code.free_registers(mark);
+}
+
 impl Lower for hir::Sequence {
     fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        let mark = code.register_mark();
+
         if self.is_scope {
             sess.env_mut().push_scope();
         }
 
+        if self.statements.is_empty() {
+            sess.push_const_unit(code, ctx.dst);
+        }
+
         for (index, expr) in self.statements.iter().enumerate() {
             let is_last = index == self.statements.len() - 1;
 
-            expr.lower(sess, code, if is_last { ctx } else { LowerContext { take_ptr: false } });
+            let statement_mark = code.register_mark();
 
-            if !is_last {
-                code.write_inst(Inst::Pop);
-            }
+            expr.lower(
+                sess,
+                code,
+                if is_last {
+                    ctx
+                } else {
+                    LowerContext {
+                        take_ptr: false,
+                        dst: ctx.dst,
+                    }
+                },
+            );
+
+            code.free_registers(statement_mark);
         }
 
         if self.is_scope {
             sess.env_mut().pop_scope();
+            code.pop_registers(mark);
         }
     }
 }
@@ -318,29 +541,38 @@ impl Lower for hir::Control {
             hir::Control::While(x) => x.lower(sess, code, ctx),
             hir::Control::Return(x) => x.lower(sess, code, ctx),
             hir::Control::Break(_) => {
-                let pos = code.write_inst(Inst::Jmp(INVALID_JMP_OFFSET));
+                let pos = code.write_inst(Inst::Jmp {
+                    target: INVALID_JMP_TARGET,
+                });
                 sess.loop_env_stack.last_mut().unwrap().break_offsets.push(pos);
             }
             hir::Control::Continue(_) => {
-                let pos = code.write_inst(Inst::Jmp(INVALID_JMP_OFFSET));
-                sess.loop_env_stack.last_mut().unwrap().break_offsets.push(pos);
+                let pos = code.write_inst(Inst::Jmp {
+                    target: INVALID_JMP_TARGET,
+                });
+                sess.loop_env_stack.last_mut().unwrap().continue_offsets.push(pos);
             }
         }
     }
 }
 
 impl Lower for hir::If {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+        let branch_ctx = LowerContext {
+            take_ptr: false,
+            dst: ctx.dst,
+        };
+
         lower_conditional(
             sess,
             code,
-            |sess, code| self.condition.lower(sess, code, LowerContext { take_ptr: false }),
-            |sess, code| self.then.lower(sess, code, LowerContext { take_ptr: false }),
+            |sess, code| lower_to_register(&self.condition, sess, code, false),
+            |sess, code| self.then.lower(sess, code, branch_ctx),
             |sess, code| {
                 if let Some(otherwise) = &self.otherwise {
-                    otherwise.lower(sess, code, LowerContext { take_ptr: false });
+                    otherwise.lower(sess, code, branch_ctx);
                 } else {
-                    sess.push_const_unit(code);
+                    sess.push_const_unit(code, ctx.dst);
                 }
             },
         );
@@ -350,336 +582,507 @@ impl Lower for hir::If {
 fn lower_conditional(
     sess: &mut InterpSess,
     code: &mut Bytecode,
-    condition: impl FnOnce(&mut InterpSess, &mut Bytecode),
+    condition: impl FnOnce(&mut InterpSess, &mut Bytecode) -> Register,
     then: impl FnOnce(&mut InterpSess, &mut Bytecode),
     otherwise: impl FnOnce(&mut InterpSess, &mut Bytecode),
 ) {
-    condition(sess, code);
+    let mark = code.register_mark();
+
+    let cond = condition(sess, code);
 
-    let otherwise_jmp = code.write_inst(Inst::Jmpf(INVALID_JMP_OFFSET));
+    let otherwise_jmp = code.write_inst(Inst::Jmpf {
+        cond,
+        target: INVALID_JMP_TARGET,
+    });
+
+    code.free_registers(mark);
 
     then(sess, code);
 
-    let exit_jmp = code.write_inst(Inst::Jmp(INVALID_JMP_OFFSET));
+    let exit_jmp = code.write_inst(Inst::Jmp {
+        target: INVALID_JMP_TARGET,
+    });
 
-    patch_jmp(code, otherwise_jmp);
+    code.patch_jmp(otherwise_jmp, code.len());
 
     otherwise(sess, code);
 
-    patch_jmp(code, exit_jmp);
+    code.patch_jmp(exit_jmp, code.len());
 }
 
 impl Lower for hir::While {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let loop_start = code.len();
 
-        self.condition.lower(sess, code, LowerContext { take_ptr: false });
+        let mark = code.register_mark();
+
+        let cond = lower_to_register(&self.condition, sess, code, false);
 
-        let exit_jmp = code.write_inst(Inst::Jmpf(INVALID_JMP_OFFSET));
+        let exit_jmp = code.write_inst(Inst::Jmpf {
+            cond,
+            target: INVALID_JMP_TARGET,
+        });
+
+        code.free_registers(mark);
 
         sess.loop_env_stack.push(LoopEnv::new());
 
-        self.body.lower(sess, code, LowerContext { take_ptr: false });
+        self.body.lower(
+            sess,
+            code,
+            LowerContext {
+                take_ptr: false,
+                dst: ctx.dst,
+            },
+        );
 
         let loop_env = sess.loop_env_stack.pop().unwrap();
 
-        code.write_inst(Inst::Pop);
+        code.write_inst(Inst::Jmp {
+            target: loop_start as JmpTarget,
+        });
 
-        let offset = code.len() - loop_start;
-        code.write_inst(Inst::Jmp(-(offset as i32)));
+        let loop_end = code.len();
 
-        patch_jmp(code, exit_jmp);
+        code.patch_jmp(exit_jmp, loop_end);
 
         // patch all break/continue jmp instructions
-        let len = code.len();
-        for pos in &loop_env.break_offsets {
-            let target_offset = (len - *pos) as i32;
-            (&mut code.as_mut_slice()[*pos + 1..])
-                .write_i32::<NativeEndian>(target_offset)
-                .unwrap();
+        for &pos in &loop_env.break_offsets {
+            code.patch_jmp(pos, loop_end);
         }
 
-        for pos in &loop_env.continue_offsets {
-            let target_offset = loop_start as i32 - *pos as i32;
-            (&mut code.as_mut_slice()[*pos + 1..])
-                .write_i32::<NativeEndian>(target_offset)
-                .unwrap();
+        for &pos in &loop_env.continue_offsets {
+            code.patch_jmp(pos, loop_start);
         }
 
-        sess.push_const_unit(code);
+        sess.push_const_unit(code, ctx.dst);
     }
 }
 
 impl Lower for hir::Return {
     fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
-        self.value.lower(sess, code, LowerContext { take_ptr: false });
-        code.write_inst(Inst::Return);
+        let mark = code.register_mark();
+
+        let value = lower_to_register(&self.value, sess, code, false);
+        code.write_inst(Inst::Return { src: value });
+
+        code.free_registers(mark);
     }
 }
 
 impl Lower for hir::Builtin {
     fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         match self {
-            hir::Builtin::Add(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Add);
-            }
-            hir::Builtin::Sub(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Sub);
-            }
-            hir::Builtin::Mul(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Mul);
-            }
-            hir::Builtin::Div(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Div);
-            }
-            hir::Builtin::Rem(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Rem);
-            }
-            hir::Builtin::Shl(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Shl);
-            }
-            hir::Builtin::Shr(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Shr);
-            }
+            hir::Builtin::Add(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Add {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Sub(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Sub {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Mul(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Mul {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Div(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Div {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Rem(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Rem {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Shl(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Shl {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Shr(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Shr {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
             hir::Builtin::And(binary) => {
+                let branch_ctx = LowerContext {
+                    take_ptr: false,
+                    dst: ctx.dst,
+                };
+
                 lower_conditional(
                     sess,
                     code,
+                    |sess, code| lower_to_register(&binary.lhs, sess, code, false),
+                    |sess, code| binary.rhs.lower(sess, code, branch_ctx),
                     |sess, code| {
-                        binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                    },
-                    |sess, code| {
-                        binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-                    },
-                    |sess, code| {
-                        sess.push_const(code, Value::Bool(false));
+                        sess.push_const(code, ctx.dst, Value::Bool(false));
                     },
                 );
             }
             hir::Builtin::Or(binary) => {
+                let branch_ctx = LowerContext {
+                    take_ptr: false,
+                    dst: ctx.dst,
+                };
+
                 lower_conditional(
                     sess,
                     code,
+                    |sess, code| lower_to_register(&binary.lhs, sess, code, false),
                     |sess, code| {
-                        binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                    },
-                    |sess, code| {
-                        sess.push_const(code, Value::Bool(true));
-                    },
-                    |sess, code| {
-                        binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
+                        sess.push_const(code, ctx.dst, Value::Bool(true));
                     },
+                    |sess, code| binary.rhs.lower(sess, code, branch_ctx),
                 );
             }
-            hir::Builtin::Lt(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Lt);
-            }
-            hir::Builtin::Le(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Le);
-            }
-            hir::Builtin::Gt(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Gt);
-            }
-            hir::Builtin::Ge(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Ge);
-            }
-            hir::Builtin::Eq(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Eq);
-            }
-            hir::Builtin::Ne(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Ne);
-            }
-            hir::Builtin::BitAnd(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::And);
-            }
-            hir::Builtin::BitOr(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Or);
-            }
-            hir::Builtin::BitXor(binary) => {
-                binary.lhs.lower(sess, code, LowerContext { take_ptr: false });
-                binary.rhs.lower(sess, code, LowerContext { take_ptr: false });
-
-                code.write_inst(Inst::Xor);
-            }
-            hir::Builtin::Not(unary) => {
-                unary.value.lower(sess, code, LowerContext { take_ptr: false });
-                code.write_inst(Inst::Not);
-            }
-            hir::Builtin::Neg(unary) => {
-                unary.value.lower(sess, code, LowerContext { take_ptr: false });
-                code.write_inst(Inst::Neg);
-            }
+            hir::Builtin::Lt(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Lt {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Le(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Le {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Gt(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Gt {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Ge(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Ge {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Eq(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Eq {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Ne(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::Ne {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::BitAnd(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::BitAnd {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::BitOr(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::BitOr {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::BitXor(binary) => lower_binary(binary, sess, code, ctx, |dst, lhs, rhs, ty| Inst::BitXor {
+                dst,
+                lhs,
+                rhs,
+                ty,
+            }),
+            hir::Builtin::Not(unary) => lower_unary(unary, sess, code, ctx, |dst, src, ty| Inst::Not { dst, src, ty }),
+            hir::Builtin::Neg(unary) => lower_unary(unary, sess, code, ctx, |dst, src, ty| Inst::Neg { dst, src, ty }),
             hir::Builtin::Ref(unary) => {
-                unary.value.lower(sess, code, LowerContext { take_ptr: true });
+                unary.value.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: true,
+                        dst: ctx.dst,
+                    },
+                );
             }
             hir::Builtin::Deref(unary) => {
-                unary.value.lower(sess, code, LowerContext { take_ptr: false });
-                code.write_inst(Inst::Deref);
+                if ctx.take_ptr {
+                    // the address of a dereferenced pointer is the pointer itself
+                    unary.value.lower(
+                        sess,
+                        code,
+                        LowerContext {
+                            take_ptr: false,
+                            dst: ctx.dst,
+                        },
+                    );
+                } else {
+                    lower_unary(unary, sess, code, ctx, |dst, src, _| Inst::Deref { dst, src });
+                }
             }
             hir::Builtin::Offset(offset) => {
-                offset.value.lower(sess, code, LowerContext { take_ptr: false });
+                let mark = code.register_mark();
 
                 let value_type = offset.value.ty().normalize(sess.tcx);
 
-                let elem_size = match value_type {
+                // an element's address is taken through its array's address, so writes to it reach the array
+                let value = lower_to_register(&offset.value, sess, code, ctx.take_ptr && value_type.is_array());
+
+                let (data, elem_size) = match &value_type {
                     Type::Pointer(inner, _) => match inner.as_ref() {
                         Type::Slice(inner) | Type::Str(inner) => {
-                            code.write_inst(Inst::ConstIndex(0));
-                            inner.size_of(WORD_SIZE)
+                            let data = code.alloc_register();
+
+                            code.write_inst(Inst::ConstIndex {
+                                dst: data,
+                                src: value,
+                                index: 0,
+                            });
+
+                            (data, inner.size_of(word_size()))
                         }
-                        _ => inner.size_of(WORD_SIZE),
+                        Type::Array(inner, _) => (value, inner.size_of(word_size())),
+                        _ => (value, inner.size_of(word_size())),
                     },
-                    Type::Array(inner, _) => inner.size_of(WORD_SIZE),
+                    Type::Array(inner, _) => (value, inner.size_of(word_size())),
                     _ => unreachable!("{:?}", value_type),
                 };
 
-                offset.index.lower(sess, code, LowerContext { take_ptr: false });
+                let index = code.alloc_register();
+
+                offset.index.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: index,
+                    },
+                );
 
-                sess.push_const(code, Value::Uint(elem_size));
-                code.write_inst(Inst::Mul);
+                if let Some(len) = lower_len(value, &value_type, sess, code) {
+                    code.write_inst(Inst::CheckIndexOutOfBounds { index, len });
+                }
 
-                code.write_inst(Inst::Offset);
+                let elem_size_register = code.alloc_register();
+                sess.push_const(code, elem_size_register, Value::Uint(elem_size));
+
+                code.write_inst(Inst::Mul {
+                    dst: index,
+                    lhs: index,
+                    rhs: elem_size_register,
+                    ty: ValueKind::Uint,
+                });
+
+                if ctx.take_ptr {
+                    code.write_inst(Inst::Offset {
+                        dst: ctx.dst,
+                        src: data,
+                        offset: index,
+                    });
+                } else {
+                    code.write_inst(Inst::Offset {
+                        dst: index,
+                        src: data,
+                        offset: index,
+                    });
 
-                if !ctx.take_ptr {
-                    code.write_inst(Inst::Deref);
+                    code.write_inst(Inst::Deref {
+                        dst: ctx.dst,
+                        src: index,
+                    });
                 }
+
+                code.free_registers(mark);
             }
             hir::Builtin::Slice(slice) => {
                 let result_type = Type::Pointer(Box::new(slice.ty.normalize(sess.tcx)), true);
                 // Size of a fat pointer
-                let result_type_size = WORD_SIZE as u32 * 2;
+                let result_type_size = word_size() as u32 * 2;
 
                 let elem_size = match &result_type {
                     Type::Pointer(inner, _) => match inner.as_ref() {
-                        Type::Array(inner, _) => inner.size_of(WORD_SIZE),
-                        Type::Slice(inner) | Type::Str(inner) => inner.size_of(WORD_SIZE),
-                        _ => inner.size_of(WORD_SIZE),
+                        Type::Array(inner, _) => inner.size_of(word_size()),
+                        Type::Slice(inner) | Type::Str(inner) => inner.size_of(word_size()),
+                        _ => inner.size_of(word_size()),
                     },
-                    Type::Array(inner, _) => inner.size_of(WORD_SIZE),
-                    Type::Slice(inner) | Type::Str(inner) => inner.size_of(WORD_SIZE),
+                    Type::Array(inner, _) => inner.size_of(word_size()),
+                    Type::Slice(inner) | Type::Str(inner) => inner.size_of(word_size()),
                     _ => unreachable!("{:?}", result_type),
                 };
 
-                match slice.value.ty().normalize(sess.tcx).into_inner() {
+                let mark = code.register_mark();
+
+                let value = lower_to_register(&slice.value, sess, code, false);
+
+                let data = match slice.value.ty().normalize(sess.tcx).into_inner() {
                     Type::Slice(_) | Type::Str(_) => {
-                        slice.value.lower(sess, code, LowerContext { take_ptr: false });
+                        let data = code.alloc_register();
 
-                        code.write_inst(Inst::Copy(0));
-                        code.write_inst(Inst::ConstIndex(1));
-                        code.write_inst(Inst::Swap(1));
-                        code.write_inst(Inst::ConstIndex(0));
+                        code.write_inst(Inst::ConstIndex {
+                            dst: data,
+                            src: value,
+                            index: 0,
+                        });
 
-                        sess.push_const(code, Value::Type(result_type.clone()));
-                        code.write_inst(Inst::BufferAlloc(result_type_size));
+                        data
+                    }
+                    // arrays (behind a pointer) and raw pointers are offset directly
+                    _ => value,
+                };
 
-                        code.write_inst(Inst::Swap(1));
+                let low = code.alloc_register();
 
-                        // calculate the new slice's offset
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        sess.push_const(code, Value::Uint(elem_size));
-                        code.write_inst(Inst::Mul);
-                        code.write_inst(Inst::Offset);
+                slice.low.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: low,
+                    },
+                );
 
-                        code.write_inst(Inst::BufferPut(0));
+                let high = code.alloc_register();
 
-                        // calculate the slice length, by doing `high - low`
-                        slice.high.lower(sess, code, LowerContext { take_ptr: false });
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        code.write_inst(Inst::Sub);
+                slice.high.lower(
+                    sess,
+                    code,
+                    LowerContext {
+                        take_ptr: false,
+                        dst: high,
+                    },
+                );
 
-                        code.write_inst(Inst::BufferPut(WORD_SIZE as u32));
-                    }
-                    Type::Array(..) => {
-                        sess.push_const(code, Value::Type(result_type.clone()));
-                        code.write_inst(Inst::BufferAlloc(result_type_size));
+                let prev_span = code.set_span(slice.value.span());
 
-                        slice.value.lower(sess, code, LowerContext { take_ptr: false });
+                code.write_inst(Inst::CheckSliceEndBeforeStart { low, high });
 
-                        code.write_inst(Inst::Deref);
+                if let Some(len) = lower_len(value, &slice.value.ty().normalize(sess.tcx), sess, code) {
+                    code.write_inst(Inst::CheckSliceRangeOutOfBounds { low, high, len });
+                }
 
-                        // calculate the new slice's offset
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        sess.push_const(code, Value::Uint(elem_size));
-                        code.write_inst(Inst::Mul);
-                        code.write_inst(Inst::Offset);
+                code.set_span(prev_span);
+
+                // calculate the new slice's offset
+                let ptr = code.alloc_register();
+                sess.push_const(code, ptr, Value::Uint(elem_size));
+
+                code.write_inst(Inst::Mul {
+                    dst: ptr,
+                    lhs: low,
+                    rhs: ptr,
+                    ty: ValueKind::Uint,
+                });
+
+                code.write_inst(Inst::Offset {
+                    dst: ptr,
+                    src: data,
+                    offset: ptr,
+                });
+
+                // calculate the slice length, by doing `high - low`
+                code.write_inst(Inst::Sub {
+                    dst: high,
+                    lhs: high,
+                    rhs: low,
+                    ty: ValueKind::Uint,
+                });
+
+                let ty = sess.add_const(Value::Type(result_type));
+
+                code.write_inst(Inst::BufferAlloc {
+                    dst: ctx.dst,
+                    ty,
+                    size: result_type_size,
+                });
+
+                code.write_inst(Inst::BufferPut {
+                    buf: ctx.dst,
+                    offset: 0,
+                    src: ptr,
+                });
+
+                code.write_inst(Inst::BufferPut {
+                    buf: ctx.dst,
+                    offset: word_size() as u32,
+                    src: high,
+                });
+
+                code.free_registers(mark);
+            }
+        }
+    }
+}
 
-                        code.write_inst(Inst::BufferPut(0));
+// Lowers the length of an array or a slice, used for bounds checking. Raw pointers don't have a length
+fn lower_len(value: Register, ty: &Type, sess: &mut InterpSess, code: &mut Bytecode) -> Option<Register> {
+    match ty {
+        Type::Array(_, size) => {
+            let len = code.alloc_register();
+            sess.push_const(code, len, Value::Uint(*size));
+            Some(len)
+        }
+        Type::Pointer(inner, _) => match inner.as_ref() {
+            Type::Array(..) => lower_len(value, inner, sess, code),
+            Type::Slice(_) | Type::Str(_) => {
+                let len = code.alloc_register();
+
+                code.write_inst(Inst::ConstIndex {
+                    dst: len,
+                    src: value,
+                    index: 1,
+                });
+
+                Some(len)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
 
-                        // calculate the slice length, by doing `high - low`
-                        slice.high.lower(sess, code, LowerContext { take_ptr: false });
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        code.write_inst(Inst::Sub);
+fn lower_binary(
+    binary: &hir::Binary,
+    sess: &mut InterpSess,
+    code: &mut Bytecode,
+    ctx: LowerContext,
+    inst: fn(Register, Register, Register, ValueKind) -> Inst,
+) {
+    let mark = code.register_mark();
 
-                        code.write_inst(Inst::BufferPut(WORD_SIZE as u32));
-                    }
-                    _ => {
-                        sess.push_const(code, Value::Type(result_type.clone()));
-                        code.write_inst(Inst::BufferAlloc(result_type_size));
+    let lhs = lower_to_register(&binary.lhs, sess, code, false);
+    let rhs = lower_to_register(&binary.rhs, sess, code, false);
 
-                        slice.value.lower(sess, code, LowerContext { take_ptr: false });
+    // passing the operands' type lets the vm read them without checking both values' variants
+    let ty = ValueKind::from(&binary.lhs.ty().normalize(sess.tcx));
 
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        sess.push_const(code, Value::Uint(elem_size));
-                        code.write_inst(Inst::Mul);
-                        code.write_inst(Inst::Offset);
+    code.write_inst(inst(ctx.dst, lhs, rhs, ty));
 
-                        code.write_inst(Inst::BufferPut(0));
+    code.free_registers(mark);
+}
 
-                        slice.high.lower(sess, code, LowerContext { take_ptr: false });
-                        slice.low.lower(sess, code, LowerContext { take_ptr: false });
-                        code.write_inst(Inst::Sub);
+fn lower_unary(
+    unary: &hir::Unary,
+    sess: &mut InterpSess,
+    code: &mut Bytecode,
+    ctx: LowerContext,
+    inst: fn(Register, Register, ValueKind) -> Inst,
+) {
+    let mark = code.register_mark();
 
-                        code.write_inst(Inst::BufferPut(WORD_SIZE as u32));
-                    }
-                }
-            }
-        }
-    }
+    let value = lower_to_register(&unary.value, sess, code, false);
+    let ty = ValueKind::from(&unary.value.ty().normalize(sess.tcx));
+
+    code.write_inst(inst(ctx.dst, value, ty));
+
+    code.free_registers(mark);
 }
 
 impl Lower for hir::Literal {
@@ -694,13 +1097,18 @@ impl Lower for hir::Literal {
 }
 
 impl Lower for hir::StructLiteral {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let ty = self.ty.normalize(sess.tcx);
         let struct_type = ty.as_struct();
-        let struct_size = struct_type.size_of(WORD_SIZE) as u32;
+        let struct_size = struct_type.size_of(word_size()) as u32;
 
-        sess.push_const(code, Value::Type(ty.clone()));
-        code.write_inst(Inst::BufferAlloc(struct_size));
+        let type_slot = sess.add_const(Value::Type(ty.clone()));
+
+        code.write_inst(Inst::BufferAlloc {
+            dst: ctx.dst,
+            ty: type_slot,
+            size: struct_size,
+        });
 
         let mut ordered_fields = self.fields.clone();
 
@@ -710,57 +1118,126 @@ impl Lower for hir::StructLiteral {
             index_1.cmp(&index_2)
         });
 
-        for (index, field) in ordered_fields.iter().enumerate() {
-            field.value.lower(sess, code, LowerContext { take_ptr: false });
+        for field in ordered_fields.iter() {
+            let index = struct_type.field_position(field.name).unwrap();
 
-            code.write_inst(Inst::BufferPut(struct_type.offset_of(index, WORD_SIZE) as u32));
+            lower_buffer_put(
+                &field.value,
+                struct_type.offset_of(index, word_size()) as u32,
+                sess,
+                code,
+                ctx,
+            );
         }
     }
 }
 
 impl Lower for hir::TupleLiteral {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let tuple_type = self.ty.normalize(sess.tcx);
-        let tuple_size = tuple_type.size_of(WORD_SIZE) as u32;
+        let tuple_size = tuple_type.size_of(word_size()) as u32;
 
-        sess.push_const(code, Value::Type(tuple_type.clone()));
-        code.write_inst(Inst::BufferAlloc(tuple_size));
+        let type_slot = sess.add_const(Value::Type(tuple_type.clone()));
+
+        code.write_inst(Inst::BufferAlloc {
+            dst: ctx.dst,
+            ty: type_slot,
+            size: tuple_size,
+        });
 
         for (index, element) in self.elements.iter().enumerate() {
-            element.lower(sess, code, LowerContext { take_ptr: false });
-            code.write_inst(Inst::BufferPut(tuple_type.offset_of(index, WORD_SIZE) as u32));
+            lower_buffer_put(
+                element,
+                tuple_type.offset_of(index, word_size()) as u32,
+                sess,
+                code,
+                ctx,
+            );
         }
     }
 }
 
 impl Lower for hir::ArrayLiteral {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let ty = self.ty.normalize(sess.tcx);
-        let inner_ty_size = ty.element_type().unwrap().size_of(WORD_SIZE);
+        let inner_ty_size = ty.element_type().unwrap().size_of(word_size());
 
-        sess.push_const(code, Value::Type(ty));
-        code.write_inst(Inst::BufferAlloc((self.elements.len() * inner_ty_size) as u32));
+        let type_slot = sess.add_const(Value::Type(ty));
+
+        code.write_inst(Inst::BufferAlloc {
+            dst: ctx.dst,
+            ty: type_slot,
+            size: (self.elements.len() * inner_ty_size) as u32,
+        });
 
         for (index, element) in self.elements.iter().enumerate() {
-            element.lower(sess, code, LowerContext { take_ptr: false });
-            code.write_inst(Inst::BufferPut((index * inner_ty_size) as u32));
+            lower_buffer_put(element, (index * inner_ty_size) as u32, sess, code, ctx);
         }
     }
 }
 
 impl Lower for hir::ArrayFillLiteral {
-    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, _ctx: LowerContext) {
+    fn lower(&self, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
         let ty = self.ty.normalize(sess.tcx);
-        let inner_ty_size = ty.element_type().unwrap().size_of(WORD_SIZE);
+        let inner_ty_size = ty.element_type().unwrap().size_of(word_size());
 
         let size = if let Type::Array(_, size) = ty { size } else { panic!() };
 
-        sess.push_const(code, Value::Type(ty));
-        code.write_inst(Inst::BufferAlloc((size * inner_ty_size) as u32));
+        let type_slot = sess.add_const(Value::Type(ty));
+
+        code.write_inst(Inst::BufferAlloc {
+            dst: ctx.dst,
+            ty: type_slot,
+            size: (size * inner_ty_size) as u32,
+        });
+
+        let mark = code.register_mark();
 
-        self.value.lower(sess, code, LowerContext { take_ptr: false });
+        let value = lower_to_register(&self.value, sess, code, false);
 
-        code.write_inst(Inst::BufferFill(size as u32));
+        code.write_inst(Inst::BufferFill {
+            buf: ctx.dst,
+            src: value,
+            count: size as u32,
+        });
+
+        code.free_registers(mark);
+    }
+}
+
+// Lowers `value` and writes it at `offset` of the buffer in `ctx.dst`
+fn lower_buffer_put(value: &hir::Node, offset: u32, sess: &mut InterpSess, code: &mut Bytecode, ctx: LowerContext) {
+    let mark = code.register_mark();
+
+    let value = lower_to_register(value, sess, code, false);
+
+    code.write_inst(Inst::BufferPut {
+        buf: ctx.dst,
+        offset,
+        src: value,
+    });
+
+    code.free_registers(mark);
+}
+
+// Returns the register of `node` if it refers to a local binding
+fn local_register(node: &hir::Node, sess: &InterpSess) -> Option<Register> {
+    match node {
+        hir::Node::Id(id) => sess.env().value(id.id).copied(),
+        _ => None,
+    }
+}
+
+// Lowers `node` into a fresh register and returns it.
+// Locals are read in place, instead of being copied into a temporary.
+fn lower_to_register(node: &hir::Node, sess: &mut InterpSess, code: &mut Bytecode, take_ptr: bool) -> Register {
+    match local_register(node, sess) {
+        Some(register) if !take_ptr => register,
+        _ => {
+            let dst = code.alloc_register();
+            node.lower(sess, code, LowerContext { take_ptr, dst });
+            dst
+        }
     }
 }
 
@@ -777,20 +1254,20 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
                 IntType::I16 => Value::I16(*v as _),
                 IntType::I32 => Value::I32(*v as _),
                 IntType::I64 => Value::I64(*v as _),
-                IntType::Int => Value::Int(*v as _),
+                IntType::Int => Value::Int(wrap_int(*v as _)),
             },
             Type::Uint(ty) => match ty {
                 UintType::U8 => Value::U8(*v as _),
                 UintType::U16 => Value::U16(*v as _),
                 UintType::U32 => Value::U32(*v as _),
                 UintType::U64 => Value::U64(*v as _),
-                UintType::Uint => Value::Uint(*v as _),
+                UintType::Uint => Value::Uint(wrap_uint(*v as _)),
             },
             Type::Float(ty) => match ty {
                 FloatType::F16 | FloatType::F32 => Value::F32(*v as _),
                 FloatType::F64 => Value::F64(*v as _),
                 FloatType::Float => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         Value::F64(*v as _)
                     } else {
                         Value::F32(*v as _)
@@ -799,7 +1276,7 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
             },
             Type::Infer(_, InferType::AnyInt) => Value::Int(*v as _),
             Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
+                if is_64bit() {
                     Value::F64(*v as _)
                 } else {
                     Value::F32(*v as _)
@@ -812,7 +1289,7 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
                 FloatType::F16 | FloatType::F32 => Value::F32(*v as _),
                 FloatType::F64 => Value::F64(*v as _),
                 FloatType::Float => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         Value::F64(*v as _)
                     } else {
                         Value::F32(*v as _)
@@ -820,7 +1297,7 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
                 }
             },
             Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
+                if is_64bit() {
                     Value::F64(*v as _)
                 } else {
                     Value::F32(*v as _)
@@ -846,7 +1323,7 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
 
             let elem_type = array.element_type;
             let elem_type_kind = elem_type.normalize(sess.tcx);
-            let elem_size = elem_type_kind.size_of(WORD_SIZE);
+            let elem_size = elem_type_kind.size_of(word_size());
 
             let mut bytes = ByteSeq::new(array_len * elem_size);
 
@@ -866,7 +1343,14 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
             match &function.kind {
                 hir::FunctionKind::Intrinsic(intrinsic) => Value::Intrinsic(IntrinsicFunction::from(*intrinsic)),
                 _ => {
-                    function.lower(sess, &mut Bytecode::new(), LowerContext { take_ptr: false });
+                    function.lower(
+                        sess,
+                        &mut Bytecode::new(),
+                        LowerContext {
+                            take_ptr: false,
+                            dst: 0,
+                        },
+                    );
                     Value::Function(FunctionAddress {
                         id: f.id,
                         is_extern: function.kind.as_extern().is_some(),
@@ -883,8 +1367,120 @@ fn const_value_to_value(const_value: &ConstValue, ty: TypeId, sess: &mut InterpS
     }
 }
 
-fn find_and_lower_top_level_binding(id: BindingId, sess: &mut InterpSess) -> usize {
-    dbg!("{}", sess.workspace.binding_infos.get(id));
+fn unsupported_ffi_type_diagnostic(ty: &Type, span: Span, sess: &InterpSess) -> Diagnostic {
+    Diagnostic::error()
+        .with_message(format!(
+            "type `{}` cannot be passed through ffi at compile-time",
+            ty.display(sess.tcx)
+        ))
+        .with_label(Label::primary(span, "cannot use during compile-time"))
+}
+
+fn word_size_mismatch_note() -> String {
+    format!(
+        "the target's word size ({} bytes) differs from the host's ({} bytes), so foreign code can't be called",
+        word_size(),
+        HOST_WORD_SIZE
+    )
+}
+
+// Extern variables are accessed through their symbol's address, so that writes reach the foreign variable
+fn lower_extern_variable(
+    variable: &ConstExternVariable,
+    span: Span,
+    sess: &mut InterpSess,
+    code: &mut Bytecode,
+    ctx: LowerContext,
+) {
+    let lib = match &variable.dylib {
+        Some(lib) => lib.path(),
+        None => {
+            sess.diagnostics.push(
+                Diagnostic::error()
+                    .with_message(format!(
+                        "must specify a library to use extern variable `{}` at compile-time",
+                        variable.name
+                    ))
+                    .with_label(Label::primary(span, "cannot use during compile-time"))
+                    .with_note("add #[lib = \"your_lib\"] above the declaration"),
+            );
+
+            return;
+        }
+    };
+
+    if word_size() != HOST_WORD_SIZE {
+        sess.diagnostics.push(
+            Diagnostic::error()
+                .with_message(format!(
+                    "cannot use extern variable `{}` at compile-time when cross-compiling",
+                    variable.name
+                ))
+                .with_label(Label::primary(span, "cannot use during compile-time"))
+                .with_note(word_size_mismatch_note()),
+        );
+
+        return;
+    }
+
+    let ty = variable.ty.normalize(sess.tcx);
+
+    // the vm can't call through a raw function pointer
+    if ty.is_function() {
+        let diagnostic = unsupported_ffi_type_diagnostic(&ty, span, sess);
+        sess.diagnostics.push(diagnostic);
+        return;
+    }
+
+    let symbol = match unsafe { sess.interp.ffi.try_load_symbol(ustr(&lib), variable.name) } {
+        Ok(symbol) => symbol,
+        Err(err) => {
+            sess.diagnostics.push(
+                Diagnostic::error()
+                    .with_message(format!(
+                        "failed to load extern variable `{}` from `{}`",
+                        variable.name, lib
+                    ))
+                    .with_label(Label::primary(span, "cannot use during compile-time"))
+                    .with_note(err.to_string()),
+            );
+
+            return;
+        }
+    };
+
+    let ptr = Value::Pointer(Pointer::new(symbol, ty));
+
+    if ctx.take_ptr {
+        sess.push_const(code, ctx.dst, ptr);
+    } else {
+        let mark = code.register_mark();
+        let ptr_register = code.alloc_register();
+
+        sess.push_const(code, ptr_register, ptr);
+
+        code.write_inst(Inst::Deref {
+            dst: ctx.dst,
+            src: ptr_register,
+        });
+
+        code.free_registers(mark);
+    }
+}
+
+fn extern_variable_value(id: BindingId, sess: &InterpSess) -> Option<ConstValue> {
+    match sess.workspace.binding_infos.get(id)?.kind {
+        BindingInfoKind::ExternVariable => sess
+            .cache
+            .bindings
+            .get(&id)
+            .and_then(|binding| binding.value.as_const_value())
+            .cloned(),
+        _ => None,
+    }
+}
+
+pub(super) fn find_and_lower_top_level_binding(id: BindingId, sess: &mut InterpSess) -> usize {
     let binding = sess
         .cache
         .bindings
@@ -905,32 +1501,32 @@ fn lower_top_level_binding(binding: &hir::Binding, sess: &mut InterpSess) -> usi
 
 fn lower_static_binding(binding: &hir::Binding, sess: &mut InterpSess) -> usize {
     let mut code = Bytecode::new();
+    let result_register = code.alloc_register();
 
     sess.env_stack.push((binding.module_id, Env::default()));
 
-    binding.value.lower(sess, &mut code, LowerContext { take_ptr: false });
+    binding.value.lower(
+        sess,
+        &mut code,
+        LowerContext {
+            take_ptr: false,
+            dst: result_register,
+        },
+    );
 
     sess.env_stack.pop();
 
     let slot = sess.insert_global(binding.id, Value::default());
 
-    code.write_inst(Inst::StoreGlobal(slot as u32));
-    sess.push_const_unit(&mut code);
-    code.write_inst(Inst::Return);
+    code.write_inst(Inst::StoreGlobal {
+        slot: slot as u32,
+        src: result_register,
+    });
+
+    sess.push_const_unit(&mut code, result_register);
+    code.write_inst(Inst::Return { src: result_register });
 
     sess.statically_initialized_globals.push(code);
 
     slot
 }
-
-const INVALID_JMP_OFFSET: i32 = i32::MAX;
-
-fn patch_jmp(code: &mut Bytecode, op_pos: usize) {
-    // This function assumes that the Op is some sort of Jmp.
-    // We patch the op's operand by directly writing to the buffer.
-    let target_offset = (code.len() - op_pos) as i32;
-
-    (&mut code.as_mut_slice()[op_pos + 1..])
-        .write_i32::<NativeEndian>(target_offset)
-        .unwrap();
-}
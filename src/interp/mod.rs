@@ -1,8 +1,106 @@
+pub mod cache;
 pub mod ffi;
 pub mod interp;
 mod lower;
 pub mod vm;
 pub mod workspace;
 
-const WORD_SIZE: usize = std::mem::size_of::<usize>();
-const IS_64BIT: bool = WORD_SIZE == 8;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+};
+
+pub(crate) const HOST_WORD_SIZE: usize = mem::size_of::<usize>();
+
+thread_local! {
+    // The word size of the platform we are currently evaluating for.
+    // This is set for the duration of each evaluation - see `TargetWordSizeGuard`.
+    static TARGET_WORD_SIZE: Cell<usize> = Cell::new(HOST_WORD_SIZE);
+
+    // Host pointers that were stored in memory narrower than the host's word.
+    // These are stored as handles into this table, which are the pointer's index + 1 (0 is null).
+    // Registers always hold host addresses, so handles never escape memory - casting between pointers and
+    // integers is rejected in this case. Entries live as long as the compiler, since memory holding a handle
+    // (i.e: a global) can outlive the evaluation that stored it.
+    static POINTER_HANDLES: RefCell<PointerHandles> = RefCell::new(PointerHandles::default());
+}
+
+#[derive(Default)]
+struct PointerHandles {
+    pointers: Vec<usize>,
+    handles: HashMap<usize, u64>,
+}
+
+pub(crate) fn word_size() -> usize {
+    TARGET_WORD_SIZE.with(|word_size| word_size.get())
+}
+
+pub(crate) fn is_64bit() -> bool {
+    word_size() == 8
+}
+
+/// Sets the target word size used by the VM, restoring the previous one when dropped.
+/// Nested workspaces (started from `start_workspace`) may target a different platform than their parent.
+pub(crate) struct TargetWordSizeGuard {
+    previous: usize,
+}
+
+impl TargetWordSizeGuard {
+    pub(crate) fn new(word_size: usize) -> Self {
+        let previous = TARGET_WORD_SIZE.with(|current| current.replace(word_size));
+        Self { previous }
+    }
+}
+
+impl Drop for TargetWordSizeGuard {
+    fn drop(&mut self) {
+        TARGET_WORD_SIZE.with(|current| current.set(self.previous));
+    }
+}
+
+/// Encodes a host pointer so that it fits in a target word
+pub(crate) fn encode_ptr<T>(ptr: *const T) -> u64 {
+    if word_size() >= HOST_WORD_SIZE || ptr.is_null() {
+        ptr as usize as u64
+    } else {
+        POINTER_HANDLES.with(|handles| {
+            let mut handles = handles.borrow_mut();
+            let address = ptr as usize;
+
+            if let Some(handle) = handles.handles.get(&address) {
+                *handle
+            } else {
+                handles.pointers.push(address);
+                let handle = handles.pointers.len() as u64;
+                handles.handles.insert(address, handle);
+                handle
+            }
+        })
+    }
+}
+
+/// Decodes a target word, previously encoded with `encode_ptr`, back into a host pointer
+pub(crate) fn decode_ptr<T>(value: u64) -> *mut T {
+    if word_size() >= HOST_WORD_SIZE || value == 0 {
+        value as usize as *mut T
+    } else {
+        POINTER_HANDLES.with(|handles| handles.borrow().pointers[value as usize - 1] as *mut T)
+    }
+}
+
+/// Wraps a signed integer to the target's `int` width
+pub(crate) fn wrap_int(value: isize) -> isize {
+    match word_size() {
+        4 => value as i32 as isize,
+        _ => value,
+    }
+}
+
+/// Wraps an unsigned integer to the target's `uint` width
+pub(crate) fn wrap_uint(value: usize) -> usize {
+    match word_size() {
+        4 => value as u32 as usize,
+        _ => value,
+    }
+}
@@ -1,10 +1,10 @@
 use super::{
-    super::{IS_64BIT, WORD_SIZE},
+    super::{decode_ptr, encode_ptr, is_64bit, word_size},
     value::{Buffer, Pointer, Value},
 };
-use crate::types::{FloatType, InferType, IntType, Type, UintType};
+use crate::types::{size_of::SizeOf, FloatType, InferType, IntType, Type, UintType};
 use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
-use std::{io::Write, slice};
+use std::io::Write;
 
 #[derive(Debug, Clone)]
 pub struct ByteSeq {
@@ -26,15 +26,6 @@ impl ByteSeq {
         }
     }
 
-    pub fn copy_from_raw_parts(data: *const u8, len: usize) -> Self {
-        if data.is_null() {
-            Self::new(len)
-        } else {
-            let slice = unsafe { slice::from_raw_parts(data, len) };
-            Self::copy_from_slice(slice)
-        }
-    }
-
     pub fn copy_from_slice(slice: &[u8]) -> Self {
         let mut vec = vec![0u8; slice.len()];
         vec.copy_from_slice(slice);
@@ -52,7 +43,6 @@ impl ByteSeq {
         &mut self.inner[offset..]
     }
 
-    #[allow(unused)]
     pub fn len(&self) -> usize {
         self.inner.len()
     }
@@ -65,7 +55,6 @@ impl ByteSeq {
         &mut self.inner
     }
 
-    #[allow(unused)]
     pub fn as_ptr(&self) -> *const u8 {
         self.inner.as_ptr()
     }
@@ -92,12 +81,15 @@ impl PutValue for [u8] {
             Value::I16(v) => self.as_mut().write_i16::<NativeEndian>(*v).unwrap(),
             Value::I32(v) => self.as_mut().write_i32::<NativeEndian>(*v).unwrap(),
             Value::I64(v) => self.as_mut().write_i64::<NativeEndian>(*v).unwrap(),
-            Value::Int(v) => self.as_mut().write_int::<NativeEndian>(*v as i64, WORD_SIZE).unwrap(),
+            Value::Int(v) => self.as_mut().write_int::<NativeEndian>(*v as i64, word_size()).unwrap(),
             Value::U8(v) => self.as_mut().write_u8(*v).unwrap(),
             Value::U16(v) => self.as_mut().write_u16::<NativeEndian>(*v).unwrap(),
             Value::U32(v) => self.as_mut().write_u32::<NativeEndian>(*v).unwrap(),
             Value::U64(v) => self.as_mut().write_u64::<NativeEndian>(*v).unwrap(),
-            Value::Uint(v) => self.as_mut().write_uint::<NativeEndian>(*v as u64, WORD_SIZE).unwrap(),
+            Value::Uint(v) => self
+                .as_mut()
+                .write_uint::<NativeEndian>(*v as u64, word_size())
+                .unwrap(),
             Value::F32(v) => self.as_mut().write_f32::<NativeEndian>(*v).unwrap(),
             Value::F64(v) => self.as_mut().write_f64::<NativeEndian>(*v).unwrap(),
             Value::Bool(v) => self.as_mut().write_u8(*v as u8).unwrap(),
@@ -106,7 +98,7 @@ impl PutValue for [u8] {
             }
             Value::Pointer(v) => self
                 .as_mut()
-                .write_uint::<NativeEndian>(v.as_inner_raw() as u64, WORD_SIZE)
+                .write_uint::<NativeEndian>(encode_ptr(v.raw), word_size())
                 .unwrap(),
             Value::Function(_) => todo!(),
             _ => panic!("can't convert `{}` to raw self.as_mut().inner", value.to_string()),
@@ -136,20 +128,20 @@ impl GetValue for [u8] {
                 IntType::I16 => Value::I16(_ref.read_i16::<NativeEndian>().unwrap()),
                 IntType::I32 => Value::I32(_ref.read_i32::<NativeEndian>().unwrap()),
                 IntType::I64 => Value::I64(_ref.read_i64::<NativeEndian>().unwrap()),
-                IntType::Int => Value::Int(_ref.read_int::<NativeEndian>(WORD_SIZE).unwrap() as isize),
+                IntType::Int => Value::Int(_ref.read_int::<NativeEndian>(word_size()).unwrap() as isize),
             },
             Type::Uint(ty) => match ty {
                 UintType::U8 => Value::U8(_ref.read_u8().unwrap()),
                 UintType::U16 => Value::U16(_ref.read_u16::<NativeEndian>().unwrap()),
                 UintType::U32 => Value::U32(_ref.read_u32::<NativeEndian>().unwrap()),
                 UintType::U64 => Value::U64(_ref.read_u64::<NativeEndian>().unwrap()),
-                UintType::Uint => Value::Uint(_ref.read_uint::<NativeEndian>(WORD_SIZE).unwrap() as usize),
+                UintType::Uint => Value::Uint(_ref.read_uint::<NativeEndian>(word_size()).unwrap() as usize),
             },
             Type::Float(ty) => match ty {
                 FloatType::F16 | FloatType::F32 => Value::F32(_ref.read_f32::<NativeEndian>().unwrap()),
                 FloatType::F64 => Value::F64(_ref.read_f64::<NativeEndian>().unwrap()),
                 FloatType::Float => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         Value::F64(_ref.read_f64::<NativeEndian>().unwrap())
                     } else {
                         Value::F32(_ref.read_f32::<NativeEndian>().unwrap())
@@ -158,22 +150,24 @@ impl GetValue for [u8] {
             },
             Type::Pointer(inner, _) => match inner.as_ref() {
                 Type::Slice(_) | Type::Str(_) => Value::Buffer(Buffer {
-                    bytes: ByteSeq::copy_from_slice(self),
+                    bytes: ByteSeq::copy_from_slice(&self[..ty.size_of(word_size())]),
                     ty: ty.clone(),
                 }),
-                _ => Value::Pointer(Pointer::from_type_and_ptr(
-                    inner,
-                    _ref.read_uint::<NativeEndian>(WORD_SIZE).unwrap() as _,
+                _ => Value::Pointer(Pointer::new(
+                    decode_ptr(_ref.read_uint::<NativeEndian>(word_size()).unwrap()),
+                    inner.as_ref().clone(),
                 )),
             },
-
+            // only the aggregate's own bytes are copied, since `self` may extend past it
             Type::Array(_, _) | Type::Tuple(_) | Type::Struct(_) => Value::Buffer(Buffer {
-                bytes: ByteSeq::copy_from_slice(self),
+                bytes: ByteSeq::copy_from_slice(&self[..ty.size_of(word_size())]),
                 ty: ty.clone(),
             }),
-            Type::Infer(_, InferType::AnyInt) => Value::Int(_ref.read_int::<NativeEndian>(WORD_SIZE).unwrap() as isize),
+            Type::Infer(_, InferType::AnyInt) => {
+                Value::Int(_ref.read_int::<NativeEndian>(word_size()).unwrap() as isize)
+            }
             Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
+                if is_64bit() {
                     Value::F64(_ref.read_f64::<NativeEndian>().unwrap())
                 } else {
                     Value::F32(_ref.read_f32::<NativeEndian>().unwrap())
@@ -1,534 +1,535 @@
-use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
+use super::value::ValueKind;
+use crate::span::Span;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
-use std::io::Write;
-use std::mem;
-use std::ops::{Index, IndexMut};
+use ustr::Ustr;
+
+/// A register index, relative to the base of the frame that owns it
+pub type Register = u32;
+
+/// An index into the interpreter's constants
+pub type ConstSlot = u32;
+
+/// An index into the interpreter's globals
+pub type GlobalSlot = u32;
+
+/// An absolute index into a function's instructions
+pub type JmpTarget = u32;
+
+pub const INVALID_JMP_TARGET: JmpTarget = JmpTarget::MAX;
 
 #[derive(Debug, Clone)]
 pub struct Bytecode {
-    buf: Vec<u8>,
-    pub locals: u32,
+    pub instructions: Vec<Inst>,
+    // The source location each instruction was lowered from, parallel to `instructions`
+    pub spans: Vec<Span>,
+    // Named locals and the instructions they're alive in. Used by the debugger
+    pub locals: Vec<LocalInfo>,
+    // The amount of registers a frame running this code needs
+    pub registers: u32,
+    register_top: u32,
+    locals_top: u32,
+    current_span: Span,
 }
 
-impl Index<usize> for Bytecode {
-    type Output = u8;
+#[derive(Debug, Clone, Copy)]
+pub struct LocalInfo {
+    pub name: Ustr,
+    pub register: Register,
+    // The range of instructions in which the local is alive. `end` is exclusive
+    pub start: usize,
+    pub end: usize,
+}
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.buf[index]
+impl LocalInfo {
+    pub fn is_alive_at(&self, pc: usize) -> bool {
+        (self.start..self.end).contains(&pc)
     }
 }
 
-impl IndexMut<usize> for Bytecode {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.buf[index]
-    }
+/// A snapshot of the register allocator, used to free temporaries and scoped locals
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterMark {
+    top: u32,
+    locals_top: u32,
 }
 
 impl Bytecode {
     pub fn new() -> Self {
-        Self { buf: vec![], locals: 0 }
+        Self {
+            instructions: vec![],
+            spans: vec![],
+            locals: vec![],
+            registers: 0,
+            register_top: 0,
+            locals_top: 0,
+            current_span: Span::unknown(),
+        }
     }
 
     #[inline(always)]
     pub fn write_inst(&mut self, inst: Inst) -> usize {
-        match inst {
-            Inst::Pop => self.write_op(Op::Pop),
-            Inst::LoadConst(slot) => {
-                let addr = self.write_op(Op::LoadConst);
-                self.write_u32(slot);
-                addr
-            }
-            Inst::Add => self.write_op(Op::Add),
-            Inst::Sub => self.write_op(Op::Sub),
-            Inst::Mul => self.write_op(Op::Mul),
-            Inst::Div => self.write_op(Op::Div),
-            Inst::Rem => self.write_op(Op::Rem),
-            Inst::Neg => self.write_op(Op::Neg),
-            Inst::Not => self.write_op(Op::Not),
-            Inst::Deref => self.write_op(Op::Deref),
-            Inst::Eq => self.write_op(Op::Eq),
-            Inst::Ne => self.write_op(Op::Ne),
-            Inst::Lt => self.write_op(Op::Lt),
-            Inst::Le => self.write_op(Op::Le),
-            Inst::Gt => self.write_op(Op::Gt),
-            Inst::Ge => self.write_op(Op::Ge),
-            Inst::And => self.write_op(Op::And),
-            Inst::Or => self.write_op(Op::Or),
-            Inst::Shl => self.write_op(Op::Shl),
-            Inst::Shr => self.write_op(Op::Shr),
-            Inst::Xor => self.write_op(Op::Xor),
-            Inst::Jmp(addr) => {
-                let op_addr = self.write_op(Op::Jmp);
-                self.write_i32(addr);
-                op_addr
-            }
-            Inst::Jmpf(addr) => {
-                let op_addr = self.write_op(Op::Jmpf);
-                self.write_i32(addr);
-                op_addr
-            }
-            Inst::Return => self.write_op(Op::Return),
-            Inst::Call(args) => {
-                let addr = self.write_op(Op::Call);
-                self.write_u32(args);
-                addr
-            }
-            Inst::LoadGlobal(slot) => {
-                let addr = self.write_op(Op::LoadGlobal);
-                self.write_u32(slot);
-                addr
-            }
-            Inst::LoadGlobalPtr(slot) => {
-                let addr = self.write_op(Op::LoadGlobalPtr);
-                self.write_u32(slot);
-                addr
-            }
-            Inst::StoreGlobal(slot) => {
-                let addr = self.write_op(Op::StoreGlobal);
-                self.write_u32(slot);
-                addr
-            }
-            Inst::Peek(offset) => {
-                let addr = self.write_op(Op::Peek);
-                self.write_i32(offset);
-                addr
-            }
-            Inst::PeekPtr(offset) => {
-                let addr = self.write_op(Op::PeekPtr);
-                self.write_i32(offset);
-                addr
-            }
-            Inst::StoreLocal(slot) => {
-                let addr = self.write_op(Op::StoreLocal);
-                self.write_i32(slot);
-                addr
-            }
-            Inst::Offset => self.write_op(Op::Offset),
-            Inst::ConstIndex(index) => {
-                let addr = self.write_op(Op::ConstIndex);
-                self.write_u32(index);
-                addr
-            }
-            Inst::ConstIndexPtr(index) => {
-                let addr = self.write_op(Op::ConstIndexPtr);
-                self.write_u32(index);
-                addr
-            }
-            Inst::Assign => self.write_op(Op::Assign),
-            Inst::Cast => self.write_op(Op::Cast),
-            Inst::BufferAlloc(size) => {
-                let addr = self.write_op(Op::BufferAlloc);
-                self.write_u32(size);
-                addr
-            }
-            Inst::BufferPut(offset) => {
-                let addr = self.write_op(Op::BufferPut);
-                self.write_u32(offset);
-                addr
-            }
-            Inst::BufferFill(size) => {
-                let addr = self.write_op(Op::BufferFill);
-                self.write_u32(size);
-                addr
-            }
-            Inst::Copy(offset) => {
-                let addr = self.write_op(Op::Copy);
-                self.write_u32(offset);
-                addr
-            }
-            Inst::Swap(offset) => {
-                let addr = self.write_op(Op::Swap);
-                self.write_u32(offset);
-                addr
-            }
-            Inst::Halt => self.write_op(Op::Halt),
-        }
+        self.instructions.push(inst);
+        self.spans.push(self.current_span);
+        self.instructions.len() - 1
     }
 
-    #[inline(always)]
-    pub fn write_op(&mut self, op: Op) -> usize {
-        self.write_u8(op.into());
-        self.buf.len() - 1
+    /// Sets the span of instructions written from now on, returning the previous one
+    pub fn set_span(&mut self, span: Span) -> Span {
+        std::mem::replace(&mut self.current_span, span)
     }
 
     #[inline(always)]
-    pub fn write_u8(&mut self, x: u8) {
-        self.buf.write_u8(x).unwrap();
+    pub fn len(&self) -> usize {
+        self.instructions.len()
     }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn write_u16(&mut self, x: u16) {
-        self.buf.write_u16::<NativeEndian>(x).unwrap();
+    pub fn alloc_register(&mut self) -> Register {
+        let register = self.register_top;
+        self.register_top += 1;
+        self.registers = self.registers.max(self.register_top);
+        register
     }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn write_i16(&mut self, x: i16) {
-        self.buf.write_i16::<NativeEndian>(x).unwrap();
-    }
+    /// Allocates `count` consecutive registers, returning the first one
+    pub fn alloc_registers(&mut self, count: usize) -> Register {
+        let first = self.register_top;
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn write_u32(&mut self, x: u32) {
-        self.buf.write_u32::<NativeEndian>(x).unwrap();
-    }
+        for _ in 0..count {
+            self.alloc_register();
+        }
 
-    #[inline(always)]
-    pub fn write_i32(&mut self, x: i32) {
-        self.buf.write_i32::<NativeEndian>(x).unwrap();
+        first
     }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn write_all(&mut self, x: &[u8]) {
-        self.buf.write_all(x).unwrap();
+    /// Allocates a register that stays alive until the scope it was allocated in ends
+    pub fn alloc_local(&mut self) -> Register {
+        let register = self.alloc_register();
+        self.locals_top = self.register_top;
+        register
     }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn as_slice(&self) -> &[u8] {
-        &self.buf[..]
+    pub fn register_mark(&self) -> RegisterMark {
+        RegisterMark {
+            top: self.register_top,
+            locals_top: self.locals_top,
+        }
     }
 
-    #[inline(always)]
-    pub fn as_mut_slice(&mut self) -> &mut [u8] {
-        &mut self.buf[..]
+    /// Frees all temporary registers allocated after `mark`. Locals are kept alive.
+    pub fn free_registers(&mut self, mark: RegisterMark) {
+        self.register_top = mark.top.max(self.locals_top);
     }
 
-    #[inline(always)]
-    pub fn len(&self) -> usize {
-        self.buf.len()
-    }
+    /// Frees all registers allocated after `mark`, including locals. Used when a scope ends.
+    pub fn pop_registers(&mut self, mark: RegisterMark) {
+        let end = self.len();
 
-    pub fn reader(&self) -> BytecodeReader {
-        BytecodeReader {
-            bytecode: self,
-            cursor: 0,
+        for local in self.locals.iter_mut() {
+            if local.end == usize::MAX && local.register >= mark.top {
+                local.end = end;
+            }
         }
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct BytecodeReader<'a> {
-    bytecode: &'a Bytecode,
-    cursor: usize,
-}
 
-impl<'a> BytecodeReader<'a> {
-    #[inline(always)]
-    pub fn cursor(&self) -> usize {
-        self.cursor
+        self.register_top = mark.top;
+        self.locals_top = mark.locals_top;
     }
 
-    #[inline(always)]
-    pub fn set_cursor(&mut self, to: usize) {
-        self.cursor = to;
+    /// Names `register`, which is alive from the next instruction until its scope ends
+    pub fn name_local(&mut self, register: Register, name: Ustr) {
+        self.locals.push(LocalInfo {
+            name,
+            register,
+            start: self.len(),
+            end: usize::MAX,
+        });
     }
 
-    #[inline(always)]
-    pub fn has_remaining(&self) -> bool {
-        self.cursor < self.bytecode.buf.len()
-    }
+    pub fn patch_jmp(&mut self, pos: usize, to: usize) {
+        let inst = &mut self.instructions[pos];
 
-    #[inline(always)]
-    pub fn try_read_op(&mut self) -> Option<Op> {
-        if self.has_remaining() {
-            Some(Op::from(self.read_u8()))
-        } else {
-            None
+        match inst.jmp_target_mut() {
+            Some(target) => *target = to as JmpTarget,
+            None => panic!("tried to patch a non-jmp instruction `{}`", inst),
         }
     }
 
-    #[inline(always)]
-    pub fn read_op(&mut self) -> Op {
-        Op::from(self.read_u8())
-    }
-
-    #[inline(always)]
-    pub fn read_u8(&mut self) -> u8 {
-        let value = self.as_slice().read_u8().unwrap();
-        self.cursor += mem::size_of::<u8>();
-        value
-    }
+    /// Appends `other` to the end of this code, relocating its jmp targets.
+    /// Both pieces of code run in the same frame, so their registers overlap.
+    pub fn append(&mut self, other: Bytecode) {
+        let offset = self.instructions.len() as JmpTarget;
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn read_u16(&mut self) -> u16 {
-        let value = self.as_slice().read_u16::<NativeEndian>().unwrap();
-        self.cursor += mem::size_of::<u16>();
-        value
-    }
+        self.instructions.extend(other.instructions.into_iter().map(|mut inst| {
+            if let Some(target) = inst.jmp_target_mut() {
+                *target += offset;
+            }
 
-    #[allow(unused)]
-    #[inline(always)]
-    pub fn read_i16(&mut self) -> i16 {
-        let value = self.as_slice().read_i16::<NativeEndian>().unwrap();
-        self.cursor += mem::size_of::<i16>();
-        value
-    }
+            inst
+        }));
 
-    #[inline(always)]
-    pub fn read_u32(&mut self) -> u32 {
-        let value = self.as_slice().read_u32::<NativeEndian>().unwrap();
-        self.cursor += mem::size_of::<u32>();
-        value
-    }
+        self.spans.extend(other.spans);
 
-    #[inline(always)]
-    pub fn read_i32(&mut self) -> i32 {
-        let value = self.as_slice().read_i32::<NativeEndian>().unwrap();
-        self.cursor += mem::size_of::<i32>();
-        value
-    }
+        self.locals.extend(other.locals.into_iter().map(|local| LocalInfo {
+            start: local.start + offset as usize,
+            end: local.end.saturating_add(offset as usize),
+            ..local
+        }));
 
-    #[inline(always)]
-    fn as_slice(&self) -> &[u8] {
-        &self.bytecode.buf[self.cursor..]
+        self.registers = self.registers.max(other.registers);
     }
 }
 
-#[repr(u8)]
-#[derive(Debug, Clone, Copy)]
-pub enum Op {
-    Pop,
-    LoadConst,
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Rem,
-    Neg,
-    Not,
-    Deref,
-    Eq,
-    Ne,
-    Lt,
-    Le,
-    Gt,
-    Ge,
-    And,
-    Or,
-    Shl,
-    Shr,
-    Xor,
-    Jmp,
-    Jmpf,
-    Return,
-    Call,
-    LoadGlobal,
-    LoadGlobalPtr,
-    StoreGlobal,
-    Peek,
-    PeekPtr,
-    StoreLocal,
-    Offset,
-    ConstIndex,
-    ConstIndexPtr,
-    Assign,
-    Cast,
-    BufferAlloc,
-    BufferPut,
-    BufferFill,
-    Copy,
-    Swap,
-    Halt,
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Inst {
+    LoadConst {
+        dst: Register,
+        slot: ConstSlot,
+    },
+    LoadGlobal {
+        dst: Register,
+        slot: GlobalSlot,
+    },
+    LoadGlobalPtr {
+        dst: Register,
+        slot: GlobalSlot,
+    },
+    StoreGlobal {
+        slot: GlobalSlot,
+        src: Register,
+    },
+    Move {
+        dst: Register,
+        src: Register,
+    },
+    RegisterPtr {
+        dst: Register,
+        src: Register,
+    },
+    Add {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Sub {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Mul {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Div {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Rem {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Shl {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Shr {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    BitAnd {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    BitOr {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    BitXor {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Eq {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Ne {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Lt {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Le {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Gt {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Ge {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+    },
+    Neg {
+        dst: Register,
+        src: Register,
+        ty: ValueKind,
+    },
+    Not {
+        dst: Register,
+        src: Register,
+        ty: ValueKind,
+    },
+    Deref {
+        dst: Register,
+        src: Register,
+    },
+    Jmp {
+        target: JmpTarget,
+    },
+    Jmpf {
+        cond: Register,
+        target: JmpTarget,
+    },
+    // Fused compare-and-branch instructions, jumping to `target` if the comparison is false
+    JmpfEq {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    JmpfNe {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    JmpfLt {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    JmpfLe {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    JmpfGt {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    JmpfGe {
+        lhs: Register,
+        rhs: Register,
+        ty: ValueKind,
+        target: JmpTarget,
+    },
+    // Calls `callee` with the `arg_count` consecutive registers starting at `args`
+    Call {
+        dst: Register,
+        callee: Register,
+        args: Register,
+        arg_count: u32,
+    },
+    Return {
+        src: Register,
+    },
+    Offset {
+        dst: Register,
+        src: Register,
+        offset: Register,
+    },
+    ConstIndex {
+        dst: Register,
+        src: Register,
+        index: u32,
+    },
+    ConstIndexPtr {
+        dst: Register,
+        src: Register,
+        index: u32,
+    },
+    Assign {
+        ptr: Register,
+        src: Register,
+    },
+    // Runtime checks, which panic when they fail
+    CheckIndexOutOfBounds {
+        index: Register,
+        len: Register,
+    },
+    CheckSliceEndBeforeStart {
+        low: Register,
+        high: Register,
+    },
+    CheckSliceRangeOutOfBounds {
+        low: Register,
+        high: Register,
+        len: Register,
+    },
+    Cast {
+        dst: Register,
+        src: Register,
+        ty: ConstSlot,
+    },
+    BufferAlloc {
+        dst: Register,
+        ty: ConstSlot,
+        size: u32,
+    },
+    BufferPut {
+        buf: Register,
+        offset: u32,
+        src: Register,
+    },
+    BufferFill {
+        buf: Register,
+        src: Register,
+        count: u32,
+    },
 }
 
-impl From<u8> for Op {
-    fn from(x: u8) -> Self {
-        use Op::*;
-
-        match x {
-            0 => Pop,
-            1 => LoadConst,
-            2 => Add,
-            3 => Sub,
-            4 => Mul,
-            5 => Div,
-            6 => Rem,
-            7 => Neg,
-            8 => Not,
-            9 => Deref,
-            11 => Eq,
-            12 => Ne,
-            13 => Lt,
-            14 => Le,
-            15 => Gt,
-            16 => Ge,
-            17 => And,
-            18 => Or,
-            19 => Shl,
-            20 => Shr,
-            21 => Xor,
-            22 => Jmp,
-            23 => Jmpf,
-            24 => Return,
-            25 => Call,
-            26 => LoadGlobal,
-            27 => LoadGlobalPtr,
-            28 => StoreGlobal,
-            29 => Peek,
-            30 => PeekPtr,
-            31 => StoreLocal,
-            32 => Offset,
-            33 => ConstIndex,
-            34 => ConstIndexPtr,
-            35 => Assign,
-            36 => Cast,
-            37 => BufferAlloc,
-            38 => BufferPut,
-            39 => BufferFill,
-            40 => Copy,
-            41 => Swap,
-            42 => Halt,
-            _ => panic!(),
+impl Inst {
+    /// The target of a jmp instruction, or `None` if this isn't a jmp
+    pub fn jmp_target_mut(&mut self) -> Option<&mut JmpTarget> {
+        match self {
+            Inst::Jmp { target }
+            | Inst::Jmpf { target, .. }
+            | Inst::JmpfEq { target, .. }
+            | Inst::JmpfNe { target, .. }
+            | Inst::JmpfLt { target, .. }
+            | Inst::JmpfLe { target, .. }
+            | Inst::JmpfGt { target, .. }
+            | Inst::JmpfGe { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn jmp_target(&self) -> Option<JmpTarget> {
+        let mut inst = *self;
+        inst.jmp_target_mut().copied()
+    }
+
+    /// The constant slot read by this instruction, or `None` if it doesn't read a constant
+    pub fn const_slot_mut(&mut self) -> Option<&mut ConstSlot> {
+        match self {
+            Inst::LoadConst { slot, .. } | Inst::Cast { ty: slot, .. } | Inst::BufferAlloc { ty: slot, .. } => {
+                Some(slot)
+            }
+            _ => None,
         }
     }
-}
 
-impl From<Op> for u8 {
-    fn from(x: Op) -> Self {
-        use Op::*;
-
-        match x {
-            Pop => 0,
-            LoadConst => 1,
-            Add => 2,
-            Sub => 3,
-            Mul => 4,
-            Div => 5,
-            Rem => 6,
-            Neg => 7,
-            Not => 8,
-            Deref => 9,
-            Eq => 11,
-            Ne => 12,
-            Lt => 13,
-            Le => 14,
-            Gt => 15,
-            Ge => 16,
-            And => 17,
-            Or => 18,
-            Shl => 19,
-            Shr => 20,
-            Xor => 21,
-            Jmp => 22,
-            Jmpf => 23,
-            Return => 24,
-            Call => 25,
-            LoadGlobal => 26,
-            LoadGlobalPtr => 27,
-            StoreGlobal => 28,
-            Peek => 29,
-            PeekPtr => 30,
-            StoreLocal => 31,
-            Offset => 32,
-            ConstIndex => 33,
-            ConstIndexPtr => 34,
-            Assign => 35,
-            Cast => 36,
-            BufferAlloc => 37,
-            BufferPut => 38,
-            BufferFill => 39,
-            Copy => 40,
-            Swap => 41,
-            Halt => 42,
+    /// The global slot accessed by this instruction, or `None` if it doesn't access a global
+    pub fn global_slot_mut(&mut self) -> Option<&mut GlobalSlot> {
+        match self {
+            Inst::LoadGlobal { slot, .. } | Inst::LoadGlobalPtr { slot, .. } | Inst::StoreGlobal { slot, .. } => {
+                Some(slot)
+            }
+            _ => None,
         }
     }
 }
 
-impl Display for Op {
+impl Display for Inst {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Op::Pop => write!(f, "pop"),
-            Op::LoadConst => write!(f, "load_const"),
-            Op::Add => write!(f, "add"),
-            Op::Sub => write!(f, "sub"),
-            Op::Mul => write!(f, "mul"),
-            Op::Div => write!(f, "div"),
-            Op::Rem => write!(f, "rem"),
-            Op::Neg => write!(f, "neg"),
-            Op::Not => write!(f, "not"),
-            Op::Deref => write!(f, "deref"),
-            Op::Eq => write!(f, "eq"),
-            Op::Ne => write!(f, "ne"),
-            Op::Lt => write!(f, "lt"),
-            Op::Le => write!(f, "le"),
-            Op::Gt => write!(f, "gt"),
-            Op::Ge => write!(f, "ge"),
-            Op::And => write!(f, "and"),
-            Op::Or => write!(f, "or"),
-            Op::Shl => write!(f, "shl"),
-            Op::Shr => write!(f, "shr"),
-            Op::Xor => write!(f, "xor"),
-            Op::Jmp => write!(f, "jmp"),
-            Op::Jmpf => write!(f, "jmpf"),
-            Op::Return => write!(f, "return"),
-            Op::Call => write!(f, "call"),
-            Op::LoadGlobal => write!(f, "load_global"),
-            Op::LoadGlobalPtr => write!(f, "load_global_ptr"),
-            Op::StoreGlobal => write!(f, "store_global"),
-            Op::Peek => write!(f, "peek"),
-            Op::PeekPtr => write!(f, "peek_ptr"),
-            Op::StoreLocal => write!(f, "store_local"),
-            Op::Offset => write!(f, "offset"),
-            Op::ConstIndex => write!(f, "const_index"),
-            Op::ConstIndexPtr => write!(f, "const_index_ptr"),
-            Op::Assign => write!(f, "assign"),
-            Op::Cast => write!(f, "cast"),
-            Op::BufferAlloc => write!(f, "buffer_alloc"),
-            Op::BufferPut => write!(f, "buffer_put"),
-            Op::BufferFill => write!(f, "buffer_fill"),
-            Op::Copy => write!(f, "copy"),
-            Op::Swap => write!(f, "swap"),
-            Op::Halt => write!(f, "halt"),
+            Inst::LoadConst { dst, slot } => write!(f, "load_const r{}, %{}", dst, slot),
+            Inst::LoadGlobal { dst, slot } => write!(f, "load_global r{}, ${}", dst, slot),
+            Inst::LoadGlobalPtr { dst, slot } => write!(f, "load_global_ptr r{}, ${}", dst, slot),
+            Inst::StoreGlobal { slot, src } => write!(f, "store_global ${}, r{}", slot, src),
+            Inst::Move { dst, src } => write!(f, "move r{}, r{}", dst, src),
+            Inst::RegisterPtr { dst, src } => write!(f, "register_ptr r{}, r{}", dst, src),
+            Inst::Add { dst, lhs, rhs, ty } => write!(f, "add.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Sub { dst, lhs, rhs, ty } => write!(f, "sub.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Mul { dst, lhs, rhs, ty } => write!(f, "mul.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Div { dst, lhs, rhs, ty } => write!(f, "div.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Rem { dst, lhs, rhs, ty } => write!(f, "rem.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Shl { dst, lhs, rhs, ty } => write!(f, "shl.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Shr { dst, lhs, rhs, ty } => write!(f, "shr.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::BitAnd { dst, lhs, rhs, ty } => write!(f, "bit_and.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::BitOr { dst, lhs, rhs, ty } => write!(f, "bit_or.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::BitXor { dst, lhs, rhs, ty } => write!(f, "bit_xor.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Eq { dst, lhs, rhs, ty } => write!(f, "eq.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Ne { dst, lhs, rhs, ty } => write!(f, "ne.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Lt { dst, lhs, rhs, ty } => write!(f, "lt.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Le { dst, lhs, rhs, ty } => write!(f, "le.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Gt { dst, lhs, rhs, ty } => write!(f, "gt.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Ge { dst, lhs, rhs, ty } => write!(f, "ge.{} r{}, r{}, r{}", ty, dst, lhs, rhs),
+            Inst::Neg { dst, src, ty } => write!(f, "neg.{} r{}, r{}", ty, dst, src),
+            Inst::Not { dst, src, ty } => write!(f, "not.{} r{}, r{}", ty, dst, src),
+            Inst::Deref { dst, src } => write!(f, "deref r{}, r{}", dst, src),
+            Inst::Jmp { target } => write!(f, "jmp @{}", target),
+            Inst::Jmpf { cond, target } => write!(f, "jmpf r{}, @{}", cond, target),
+            Inst::JmpfEq { lhs, rhs, ty, target } => write!(f, "jmpf_eq.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::JmpfNe { lhs, rhs, ty, target } => write!(f, "jmpf_ne.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::JmpfLt { lhs, rhs, ty, target } => write!(f, "jmpf_lt.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::JmpfLe { lhs, rhs, ty, target } => write!(f, "jmpf_le.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::JmpfGt { lhs, rhs, ty, target } => write!(f, "jmpf_gt.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::JmpfGe { lhs, rhs, ty, target } => write!(f, "jmpf_ge.{} r{}, r{}, @{}", ty, lhs, rhs, target),
+            Inst::Call {
+                dst,
+                callee,
+                args,
+                arg_count,
+            } => {
+                if *arg_count == 0 {
+                    write!(f, "call r{}, r{}()", dst, callee)
+                } else {
+                    write!(f, "call r{}, r{}(r{}..r{})", dst, callee, args, args + arg_count - 1)
+                }
+            }
+            Inst::Return { src } => write!(f, "return r{}", src),
+            Inst::Offset { dst, src, offset } => write!(f, "offset r{}, r{}, r{}", dst, src, offset),
+            Inst::ConstIndex { dst, src, index } => write!(f, "const_index r{}, r{}, {}", dst, src, index),
+            Inst::ConstIndexPtr { dst, src, index } => write!(f, "const_index_ptr r{}, r{}, {}", dst, src, index),
+            Inst::CheckIndexOutOfBounds { index, len } => write!(f, "check_index_out_of_bounds r{}, r{}", index, len),
+            Inst::CheckSliceEndBeforeStart { low, high } => {
+                write!(f, "check_slice_end_before_start r{}, r{}", low, high)
+            }
+            Inst::CheckSliceRangeOutOfBounds { low, high, len } => {
+                write!(f, "check_slice_range_out_of_bounds r{}, r{}, r{}", low, high, len)
+            }
+            Inst::Assign { ptr, src } => write!(f, "assign r{}, r{}", ptr, src),
+            Inst::Cast { dst, src, ty } => write!(f, "cast r{}, r{}, %{}", dst, src, ty),
+            Inst::BufferAlloc { dst, ty, size } => write!(f, "buffer_alloc r{}, %{}, {}", dst, ty, size),
+            Inst::BufferPut { buf, offset, src } => write!(f, "buffer_put r{}, {}, r{}", buf, offset, src),
+            Inst::BufferFill { buf, src, count } => write!(f, "buffer_fill r{}, r{}, {}", buf, src, count),
         }
     }
 }
-
-#[derive(PartialEq, Debug, Clone, Copy)]
-pub enum Inst {
-    Pop,
-    LoadConst(u32),
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Rem,
-    Neg,
-    Not,
-    Deref,
-    Eq,
-    Ne,
-    Lt,
-    Le,
-    Gt,
-    Ge,
-    And,
-    Or,
-    Shl,
-    Shr,
-    Xor,
-    Jmp(i32),
-    Jmpf(i32),
-    Return,
-    Call(u32),
-    LoadGlobal(u32),
-    LoadGlobalPtr(u32),
-    StoreGlobal(u32),
-    Peek(i32),
-    PeekPtr(i32),
-    StoreLocal(i32),
-    Offset,
-    ConstIndex(u32),
-    ConstIndexPtr(u32),
-    Assign,
-    Cast,
-    BufferAlloc(u32),
-    BufferPut(u32),
-    BufferFill(u32),
-    Copy(u32),
-    Swap(u32),
-    Halt,
-}
@@ -1,11 +1,8 @@
-use std::ffi::c_void;
-
 use crate::types::{FloatType, InferType, IntType, Type, UintType};
 
 use super::{
-    super::ffi::RawPointer,
+    super::{ffi::RawPointer, is_64bit, wrap_int, wrap_uint},
     value::{Pointer, Value},
-    VM,
 };
 
 macro_rules! cast_to_int {
@@ -24,6 +21,7 @@ macro_rules! cast_to_int {
             Value::F32(v) => Value::$name(v as $to),
             Value::F64(v) => Value::$name(v as $to),
             Value::Bool(v) => Value::$name(v as $to),
+            Value::Pointer(v) => Value::$name(v.raw as usize as $to),
             _ => panic!("invalid value {}", $value.to_string()),
         }
     };
@@ -50,53 +48,42 @@ macro_rules! cast_to_float {
     };
 }
 
-impl<'vm> VM<'vm> {
-    #[inline]
-    pub fn cast_op(&mut self) {
-        let ty = self.stack.pop().into_type();
-        let value = self.stack.pop();
-
-        let new_value = match ty {
-            Type::Int(IntType::I8) => cast_to_int!(value => I8, i8),
-            Type::Int(IntType::I16) => cast_to_int!(value => I16, i16),
-            Type::Int(IntType::I32) => cast_to_int!(value => I32, i32),
-            Type::Int(IntType::I64) => cast_to_int!(value => I64, i64),
-            Type::Int(IntType::Int) | Type::Infer(_, InferType::AnyInt) => {
-                cast_to_int!(value => Int, isize)
-            }
-            Type::Uint(UintType::U8) => cast_to_int!(value => U8, u8),
-            Type::Uint(UintType::U16) => cast_to_int!(value => U16, u16),
-            Type::Uint(UintType::U32) => cast_to_int!(value => U32, u32),
-            Type::Uint(UintType::U64) => cast_to_int!(value => U64, u64),
-            Type::Uint(UintType::Uint) => cast_to_int!(value => Uint, usize),
-            Type::Float(FloatType::F32) => cast_to_float!(value => F32, f32),
-            Type::Float(FloatType::F64) => cast_to_float!(value => F64, f64),
-            Type::Float(FloatType::Float) | Type::Infer(_, InferType::AnyFloat) => {
+#[inline]
+pub fn cast_value(value: Value, ty: &Type) -> Value {
+    match ty {
+        Type::Int(IntType::I8) => cast_to_int!(value => I8, i8),
+        Type::Int(IntType::I16) => cast_to_int!(value => I16, i16),
+        Type::Int(IntType::I32) => cast_to_int!(value => I32, i32),
+        Type::Int(IntType::I64) => cast_to_int!(value => I64, i64),
+        Type::Int(IntType::Int) | Type::Infer(_, InferType::AnyInt) => {
+            Value::Int(wrap_int(cast_to_int!(value => Int, isize).into_int()))
+        }
+        Type::Uint(UintType::U8) => cast_to_int!(value => U8, u8),
+        Type::Uint(UintType::U16) => cast_to_int!(value => U16, u16),
+        Type::Uint(UintType::U32) => cast_to_int!(value => U32, u32),
+        Type::Uint(UintType::U64) => cast_to_int!(value => U64, u64),
+        Type::Uint(UintType::Uint) => Value::Uint(wrap_uint(cast_to_int!(value => Uint, usize).into_uint())),
+        Type::Float(FloatType::F32) => cast_to_float!(value => F32, f32),
+        Type::Float(FloatType::F64) => cast_to_float!(value => F64, f64),
+        // `float` is as wide as the target's word
+        Type::Float(FloatType::Float) | Type::Infer(_, InferType::AnyFloat) => {
+            if is_64bit() {
                 cast_to_float!(value => F64, f64)
+            } else {
+                cast_to_float!(value => F32, f32)
             }
-            Type::Pointer(inner, _) => {
-                let raw_ptr = match value {
-                    Value::Int(value) => value as RawPointer,
-                    Value::Uint(value) => value as RawPointer,
-                    Value::Pointer(ptr) => match ptr {
-                        Pointer::Buffer(buf) => {
-                            if buf.is_null() {
-                                std::ptr::null_mut::<c_void>()
-                            } else {
-                                unsafe { &mut *buf }.bytes.as_mut_ptr() as _
-                            }
-                        }
-                        _ => ptr.as_inner_raw(),
-                    },
-                    _ => panic!("invalid value {}", value.to_string()),
-                };
-
-                let new_ptr = Pointer::from_type_and_ptr(&inner, raw_ptr);
-                Value::Pointer(new_ptr)
-            }
-            _ => panic!("{:?}", ty),
-        };
+        }
+        // pointer casts reinterpret the same memory as another type
+        Type::Pointer(inner, _) => {
+            let raw_ptr = match value {
+                Value::Int(value) => value as RawPointer,
+                Value::Uint(value) => value as RawPointer,
+                Value::Pointer(ptr) => ptr.raw,
+                _ => panic!("invalid value {}", value.to_string()),
+            };
 
-        self.stack.push(new_value);
+            Value::Pointer(Pointer::new(raw_ptr, inner.as_ref().clone()))
+        }
+        _ => panic!("{:?}", ty),
     }
 }
@@ -0,0 +1,344 @@
+use super::VM;
+use crate::{
+    error::Diagnostics,
+    span::{FileId, Span},
+};
+use colored::Colorize;
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+use ustr::Ustr;
+
+// The amount of lines printed around the current line by `list`
+const LIST_CONTEXT_LINES: u32 = 3;
+
+const HELP: &str = "\
+commands:
+  s, step               run until the next line, stepping into calls
+  n, next               run until the next line in the current function, stepping over calls
+  f, finish             run until the current function returns
+  c, continue           run until the next breakpoint
+  b, break [FILE:]LINE  set a breakpoint at a line, in the current file if FILE is omitted
+  d, delete N           delete the breakpoint with index N
+  breakpoints           list all breakpoints
+  l, locals             print the locals of the current function
+  p, print NAME         print the value of a local
+  bt, backtrace         print the call stack
+  list                  print the source around the current line
+  q, quit               abort the build
+  h, help               print this message
+an empty line repeats the last command";
+
+/// A line-oriented debugger for compile-time code.
+/// The VM notifies it before every instruction, and it pauses when a new source line is reached
+/// while stepping, or when a breakpoint is hit.
+#[derive(Debug)]
+pub struct Debugger {
+    // The workspace's source files, indexed by their `FileId`
+    files: Vec<SourceFile>,
+    breakpoints: Vec<Breakpoint>,
+    mode: StepMode,
+    last_location: Option<LineLocation>,
+    last_command: String,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    source: String,
+}
+
+#[derive(Debug)]
+struct Breakpoint {
+    file: String,
+    line: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineLocation {
+    file_id: FileId,
+    line: u32,
+    // The number of frames on the VM's stack
+    depth: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StepMode {
+    Continue,
+    Step,
+    Next { depth: usize },
+    Finish { depth: usize },
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        println!(
+            "{}",
+            "comptime debugger - pausing at the first line of compile-time code. type `help` for a list of commands."
+                .bright_black()
+        );
+
+        Self {
+            files: vec![],
+            breakpoints: vec![],
+            mode: StepMode::Step,
+            last_location: None,
+            last_command: String::new(),
+        }
+    }
+
+    /// Copies source files added since the last call, so that they can be listed
+    pub fn sync_files(&mut self, diagnostics: &Diagnostics) {
+        while let Some(file) = diagnostics.get_file(self.files.len()) {
+            self.files.push(SourceFile {
+                name: file.name().clone(),
+                source: file.source().clone(),
+            });
+        }
+    }
+
+    pub fn begin_block(&mut self) {
+        self.last_location = None;
+    }
+
+    pub(super) fn on_instruction(&mut self, vm: &VM) {
+        let frame = vm.frame();
+        let span = frame.func().code.spans[frame.pc];
+
+        if span.is_unknown() {
+            return;
+        }
+
+        let location = LineLocation {
+            file_id: span.file_id,
+            line: span.start.line,
+            depth: vm.frames.len(),
+        };
+
+        if self.last_location == Some(location) {
+            return;
+        }
+
+        self.last_location = Some(location);
+
+        let should_pause = match self.mode {
+            StepMode::Continue => false,
+            StepMode::Step => true,
+            StepMode::Next { depth } => location.depth <= depth,
+            StepMode::Finish { depth } => location.depth < depth,
+        };
+
+        if should_pause || self.is_breakpoint(location) {
+            self.pause(vm, span, location);
+        }
+    }
+
+    fn is_breakpoint(&self, location: LineLocation) -> bool {
+        self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.line == location.line
+                && self
+                    .files
+                    .get(location.file_id)
+                    .map_or(false, |file| Path::new(&file.name).ends_with(&breakpoint.file))
+        })
+    }
+
+    fn pause(&mut self, vm: &VM, span: Span, location: LineLocation) {
+        println!(
+            "{} at {}",
+            vm.frame().func().name.as_str().bold(),
+            self.format_location(span).bright_black()
+        );
+
+        self.print_line(location.file_id, location.line, true);
+
+        let stdin = io::stdin();
+
+        loop {
+            print!("{} ", "(debug)".bright_black());
+            io::stdout().flush().unwrap();
+
+            let mut input = String::new();
+
+            match stdin.lock().read_line(&mut input) {
+                Ok(0) | Err(_) => {
+                    // stdin was closed, so there's no way to resume from a breakpoint
+                    self.breakpoints.clear();
+                    self.mode = StepMode::Continue;
+                    return;
+                }
+                Ok(_) => (),
+            }
+
+            let input = match input.trim() {
+                "" => self.last_command.clone(),
+                input => input.to_string(),
+            };
+
+            self.last_command = input.clone();
+
+            let mut parts = input.split_whitespace();
+            let command = parts.next().unwrap_or_default();
+            let arg = parts.next();
+
+            match command {
+                "" => (),
+                "s" | "step" => {
+                    self.mode = StepMode::Step;
+                    return;
+                }
+                "n" | "next" => {
+                    self.mode = StepMode::Next { depth: location.depth };
+                    return;
+                }
+                "f" | "finish" => {
+                    self.mode = StepMode::Finish { depth: location.depth };
+                    return;
+                }
+                "c" | "continue" => {
+                    self.mode = StepMode::Continue;
+                    return;
+                }
+                "b" | "break" => self.add_breakpoint(arg, location),
+                "d" | "delete" => match arg.and_then(|arg| arg.parse::<usize>().ok()) {
+                    Some(index) if index < self.breakpoints.len() => {
+                        self.breakpoints.remove(index);
+                    }
+                    _ => print_error("expected the index of an existing breakpoint"),
+                },
+                "breakpoints" => {
+                    if self.breakpoints.is_empty() {
+                        println!("no breakpoints");
+                    }
+
+                    for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {}:{}", index, breakpoint.file, breakpoint.line);
+                    }
+                }
+                "l" | "locals" => {
+                    let locals = locals(vm);
+
+                    if locals.is_empty() {
+                        println!("no locals");
+                    }
+
+                    for (name, value) in locals {
+                        println!("{} = {}", name, value);
+                    }
+                }
+                "p" | "print" => match arg {
+                    Some(name) => match locals(vm).into_iter().find(|(local, _)| local.as_str() == name) {
+                        Some((_, value)) => println!("{} = {}", name, value),
+                        None => print_error(&format!("no local named `{}` in the current scope", name)),
+                    },
+                    None => print_error("expected the name of a local"),
+                },
+                "bt" | "backtrace" => self.print_backtrace(vm),
+                "list" => {
+                    let first = location.line.saturating_sub(LIST_CONTEXT_LINES).max(1);
+
+                    for line in first..=location.line + LIST_CONTEXT_LINES {
+                        self.print_line(location.file_id, line, line == location.line);
+                    }
+                }
+                "q" | "quit" => {
+                    println!("aborting build");
+                    std::process::exit(1);
+                }
+                "h" | "help" => println!("{}", HELP),
+                command => print_error(&format!(
+                    "unknown command `{}`. type `help` for a list of commands",
+                    command
+                )),
+            }
+        }
+    }
+
+    fn add_breakpoint(&mut self, arg: Option<&str>, location: LineLocation) {
+        let arg = match arg {
+            Some(arg) => arg,
+            None => return print_error("expected a line, or a file and a line separated by `:`"),
+        };
+
+        let (file, line) = match arg.rsplit_once(':') {
+            Some((file, line)) => (file.to_string(), line),
+            None => (self.files[location.file_id].name.clone(), arg),
+        };
+
+        match line.parse::<u32>() {
+            Ok(line) => {
+                println!("breakpoint {} at {}:{}", self.breakpoints.len(), file, line);
+                self.breakpoints.push(Breakpoint { file, line });
+            }
+            Err(_) => print_error(&format!("invalid line `{}`", line)),
+        }
+    }
+
+    fn print_backtrace(&self, vm: &VM) {
+        let depth = vm.frames.len();
+
+        for (index, frame) in vm.frames.iter().rev().enumerate() {
+            // the current frame is paused before its next instruction, callers are paused at their call
+            let pc = if index == 0 { frame.pc } else { frame.pc - 1 };
+            let span = frame.func().code.spans[pc];
+
+            println!(
+                "#{:<3} {} at {}",
+                depth - index - 1,
+                frame.func().name.as_str().bold(),
+                self.format_location(span).bright_black()
+            );
+        }
+    }
+
+    fn print_line(&self, file_id: FileId, line: u32, is_current: bool) {
+        let text = self
+            .files
+            .get(file_id)
+            .and_then(|file| file.source.lines().nth(line as usize - 1));
+
+        if let Some(text) = text {
+            let marker = if is_current { ">" } else { " " };
+            println!("{} {}", format!("{} {:>5} |", marker, line).bright_black(), text);
+        }
+    }
+
+    fn format_location(&self, span: Span) -> String {
+        match self.files.get(span.file_id) {
+            Some(file) if !span.is_unknown() => format!("{}:{}:{}", file.name, span.start.line, span.start.column),
+            _ => "<unknown>".to_string(),
+        }
+    }
+}
+
+// The named locals alive in the current frame, and their values.
+// Locals in inner scopes shadow outer locals with the same name.
+fn locals(vm: &VM) -> Vec<(Ustr, String)> {
+    let frame = vm.frame();
+    let mut locals: Vec<(Ustr, String)> = vec![];
+
+    for local in frame.func().code.locals.iter().rev() {
+        if local.is_alive_at(frame.pc) && !locals.iter().any(|(name, _)| *name == local.name) {
+            let value = vm.stack.get(frame.base + local.register as usize);
+            locals.push((local.name, value.to_string()));
+        }
+    }
+
+    locals.reverse();
+    locals
+}
+
+fn print_error(msg: &str) {
+    println!("{} {}", "error:".red().bold(), msg);
+}
+
+impl<'vm> VM<'vm> {
+    #[inline]
+    pub(super) fn debug_hook(&mut self) {
+        if let Some(mut debugger) = self.interp.debugger.take() {
+            debugger.on_instruction(self);
+            self.interp.debugger = Some(debugger);
+        }
+    }
+}
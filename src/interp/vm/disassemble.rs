@@ -1,7 +1,7 @@
 use crate::interp::interp::Interp;
 
 use super::{
-    bytecode::{Bytecode, BytecodeReader, Op},
+    bytecode::Bytecode,
     value::{FunctionValue, Value},
 };
 use std::{
@@ -10,18 +10,18 @@ use std::{
     path::Path,
 };
 
-pub fn dump_bytecode_to_file(interp: &Interp, code: &Bytecode) {
+pub fn dump_bytecode_to_file(interp: &Interp, code: &Bytecode, path: &str) {
     if let Ok(file) = &OpenOptions::new()
         .read(false)
         .write(true)
         .create(true)
         .truncate(true)
         .append(false)
-        .open(Path::new("vm.out"))
+        .open(Path::new(path))
     {
         let mut w = BufWriter::new(file);
 
-        code.reader().disassemble(&mut w, interp);
+        code.disassemble(&mut w, interp);
 
         write!(&mut w, "\nglobals:\n").unwrap();
 
@@ -46,7 +46,7 @@ pub fn dump_bytecode_to_file(interp: &Interp, code: &Bytecode) {
             )
             .unwrap();
 
-            function.code.reader().disassemble(&mut w, interp);
+            function.code.disassemble(&mut w, interp);
 
             if index < last_function_index {
                 write!(&mut w, "\n").unwrap();
@@ -81,40 +81,12 @@ impl<W: Write> Disassemble<W> for Value {
     }
 }
 
-impl<'a, W: Write> Disassemble<W> for BytecodeReader<'a> {
+impl<W: Write> Disassemble<W> for Bytecode {
     fn disassemble(&self, w: &mut W, _: &Interp) {
-        let mut reader = self.clone();
+        writeln!(w, "registers: {}", self.registers).unwrap();
 
-        while reader.has_remaining() {
-            bytecode_reader_write_single_inst(&mut reader, w);
-            write!(w, "\n").unwrap();
-        }
-    }
-}
-
-pub(super) fn bytecode_reader_write_single_inst<'a, W: Write>(reader: &mut BytecodeReader<'a>, w: &mut W) {
-    if let Some(op) = reader.try_read_op() {
-        write!(w, "{:06}\t{}", reader.cursor() - 1, op).unwrap();
-
-        match op {
-            Op::LoadConst => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::Jmp => write!(w, " {}", reader.read_i32()).unwrap(),
-            Op::Jmpf => write!(w, " {}", reader.read_i32()).unwrap(),
-            Op::Call => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::LoadGlobal => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::LoadGlobalPtr => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::StoreGlobal => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::Peek => write!(w, " {}", reader.read_i32()).unwrap(),
-            Op::PeekPtr => write!(w, " {}", reader.read_i32()).unwrap(),
-            Op::StoreLocal => write!(w, " {}", reader.read_i32()).unwrap(),
-            Op::ConstIndex => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::ConstIndexPtr => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::BufferAlloc => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::BufferPut => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::BufferFill => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::Copy => write!(w, " {}", reader.read_u32()).unwrap(),
-            Op::Swap => write!(w, " {}", reader.read_u32()).unwrap(),
-            _ => (),
+        for (index, inst) in self.instructions.iter().enumerate() {
+            writeln!(w, "{:06}\t{}", index, inst).unwrap();
         }
     }
 }
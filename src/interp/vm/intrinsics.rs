@@ -1,55 +1,112 @@
-use super::{value::IntrinsicFunction, VM};
+use super::{value::IntrinsicFunction, VmError, VmResult, VM};
 use crate::{
     common::{
-        build_options::{BuildOptions, CodegenOptions, OptimizationLevel},
+        build_options::{BuildOptions, CodegenOptions, OptimizationLevel, OutputKind, RuntimeChecks},
         target::TargetPlatform,
     },
+    error::diagnostic::{Diagnostic, Label},
     interp::{
-        vm::value::{Buffer, Value},
-        workspace::{BuildTargetValue, OptimizationLevelValue, WorkspaceValue},
+        interp::InjectedBindings,
+        vm::value::{Buffer, Pointer, Value},
+        workspace::{BuildTargetValue, OptimizationLevelValue, OutputKindValue, WorkspaceValue},
     },
-    types::Type,
+    span::Span,
+    types::{StructType, StructTypeField, StructTypeKind, Type},
 };
 use path_absolutize::Absolutize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use ustr::ustr;
 
 impl<'vm> VM<'vm> {
-    pub fn dispatch_intrinsic(&mut self, intrinsic: IntrinsicFunction) {
+    pub fn dispatch_intrinsic(&mut self, intrinsic: IntrinsicFunction, args: &[Value]) -> VmResult<Value> {
         match intrinsic {
             IntrinsicFunction::StartWorkspace => {
-                let value = self.stack.pop();
-                let workspace_value = WorkspaceValue::from(&value);
+                let workspace_value = WorkspaceValue::from(&args[0]);
 
-                let source_file = PathBuf::from(workspace_value.build_options.input_file.as_str())
-                    .absolutize_from(self.interp.build_options.root_dir())
-                    .unwrap()
-                    .to_path_buf();
+                let root_dir = self.interp.build_options.root_dir().to_path_buf();
 
-                let output_file = PathBuf::from(workspace_value.build_options.output_file.as_str())
-                    .absolutize_from(self.interp.build_options.root_dir())
-                    .unwrap()
-                    .to_path_buf();
+                let absolutize =
+                    |path: &str| -> PathBuf { Path::new(path).absolutize_from(&root_dir).unwrap().to_path_buf() };
 
-                let build_options = BuildOptions {
-                    source_file,
-                    output_file: Some(output_file),
-                    target_platform: match &workspace_value.build_options.target {
-                        BuildTargetValue::Auto => TargetPlatform::current().unwrap(),
-                        BuildTargetValue::Linux => TargetPlatform::LinuxAmd64,
-                        BuildTargetValue::Windows => TargetPlatform::WindowsAmd64,
-                    },
-                    optimization_level: match &workspace_value.build_options.optimization_level {
-                        OptimizationLevelValue::Debug => OptimizationLevel::Debug,
-                        OptimizationLevelValue::Release => OptimizationLevel::Release,
+                let build_options_value = &workspace_value.build_options;
+                let emit = &build_options_value.emit;
+
+                let optimization_level = match &build_options_value.optimization_level {
+                    OptimizationLevelValue::O0 => OptimizationLevel::O0,
+                    OptimizationLevelValue::O1 => OptimizationLevel::O1,
+                    OptimizationLevelValue::O2 => OptimizationLevel::O2,
+                    OptimizationLevelValue::O3 => OptimizationLevel::O3,
+                    OptimizationLevelValue::Os => OptimizationLevel::Os,
+                    OptimizationLevelValue::Oz => OptimizationLevel::Oz,
+                };
+
+                let target_platform = match &build_options_value.target {
+                    // the target passed with `--target` to the build file, or the current platform
+                    BuildTargetValue::Auto => match &self.interp.build_options.auto_target_platform {
+                        Some(target_platform) => target_platform.clone(),
+                        None => match TargetPlatform::current() {
+                            Ok(target_platform) => target_platform,
+                            Err(platform) => {
+                                return Err(VmError::Diagnostic(
+                                    Diagnostic::error()
+                                        .with_message(format!("targeting unsupported platform: {}", platform))
+                                        .with_label(Label::primary(self.current_span(), "workspace started here"))
+                                        .with_note("set the workspace's `target` build option to a supported platform"),
+                                ));
+                            }
+                        },
                     },
-                    emit_times: self.interp.build_options.emit_times,
-                    emit_hir: self.interp.build_options.emit_hir,
-                    emit_bytecode: self.interp.build_options.emit_bytecode,
+                    BuildTargetValue::Platform(platform) => platform.clone(),
+                };
+
+                let build_options = BuildOptions {
+                    source_file: absolutize(&build_options_value.input_file),
+                    output_file: Some(absolutize(&build_options_value.output_file)),
+                    target_platform,
+                    auto_target_platform: self.interp.build_options.auto_target_platform.clone(),
+                    optimization_level,
+                    runtime_checks: build_options_value
+                        .runtime_checks
+                        .resolve(RuntimeChecks::default_for(optimization_level)),
+                    emit_times: emit.times || self.interp.build_options.emit_times,
+                    emit_hir: emit.hir || self.interp.build_options.emit_hir,
+                    emit_bytecode: emit.bytecode || self.interp.build_options.emit_bytecode,
+                    // nested workspaces report their own profile, but don't overwrite the parent's folded stacks file
+                    profile_comptime: self.interp.build_options.profile_comptime,
+                    profile_comptime_folded: None,
+                    debug_comptime: self.interp.build_options.debug_comptime,
                     diagnostic_options: self.interp.build_options.diagnostic_options.clone(),
                     codegen_options: CodegenOptions::Codegen {
-                        emit_llvm_ir: self.interp.build_options.codegen_options.emit_llvm_ir(),
+                        emit_llvm_ir: emit.llvm_ir || self.interp.build_options.codegen_options.emit_llvm_ir(),
+                    },
+                    output_kind: match &build_options_value.output_kind {
+                        OutputKindValue::Executable => OutputKind::Executable,
+                        OutputKindValue::Object => OutputKind::Object,
+                        OutputKindValue::StaticLibrary => OutputKind::StaticLibrary,
+                        OutputKindValue::SharedLibrary => OutputKind::SharedLibrary,
+                        OutputKindValue::Assembly => OutputKind::Assembly,
+                        OutputKindValue::Bitcode => OutputKind::Bitcode,
                     },
-                    include_paths: vec![],
+                    keep_intermediates: build_options_value.keep_intermediates
+                        || self.interp.build_options.keep_intermediates,
+                    include_paths: build_options_value
+                        .include_paths
+                        .iter()
+                        .map(|path| absolutize(path))
+                        .collect(),
+                    libraries: build_options_value.libraries.clone(),
+                    library_search_paths: build_options_value
+                        .library_search_paths
+                        .iter()
+                        .map(|path| absolutize(path))
+                        .collect(),
+                    sysroot: build_options_value
+                        .sysroot
+                        .as_ref()
+                        .map(|path| absolutize(path))
+                        .or_else(|| self.interp.build_options.sysroot.clone()),
+                    defines: build_options_value.defines.iter().cloned().collect(),
+                    comptime_cache: self.interp.build_options.comptime_cache,
                     check_mode: false,
                 };
 
@@ -66,15 +123,96 @@ impl<'vm> VM<'vm> {
                     (self.bump.alloc_slice_copy(b""), false)
                 };
 
-                let result_type = Type::Tuple(vec![Type::str_pointer(), Type::Bool]);
+                let diagnostics = &result.workspace.diagnostics;
+
+                Ok(Value::Buffer(Buffer::from_values(
+                    [
+                        Value::Buffer(Buffer::from_str_bytes(output_file)),
+                        Value::Bool(ok),
+                        Value::Uint(diagnostics.error_count()),
+                        Value::Uint(diagnostics.warning_count()),
+                    ],
+                    build_result_type(),
+                )))
+            }
+            IntrinsicFunction::InjectBindings => {
+                let source = args[0].as_buffer().as_str().to_string();
+
+                let span = self.current_span();
 
-                let result_value = Value::Buffer(Buffer::from_values(
-                    [Value::Buffer(Buffer::from_str_bytes(output_file)), Value::Bool(ok)],
-                    result_type,
-                ));
+                self.interp.injected_bindings.push(InjectedBindings { source, span });
 
-                self.stack.push(result_value);
+                Ok(Value::unit())
+            }
+            IntrinsicFunction::SymbolTable => {
+                // there is no machine code to symbolize at compile-time, so the table is always empty
+                Ok(Value::Buffer(Buffer::from_values(
+                    [
+                        Value::Pointer(Pointer::new(std::ptr::null_mut(), symbol_type())),
+                        Value::Uint(0),
+                    ],
+                    Type::slice_pointer(symbol_type(), false),
+                )))
             }
         }
     }
 }
+
+// Mirrors `std.build.BuildResult`
+fn build_result_type() -> Type {
+    let field = |name: &str, ty: Type| StructTypeField {
+        name: ustr(name),
+        ty,
+        span: Span::unknown(),
+    };
+
+    Type::Struct(StructType {
+        name: ustr("BuildResult"),
+        binding_id: None,
+        fields: vec![
+            field("output_file", Type::str_pointer()),
+            field("ok", Type::Bool),
+            field("error_count", Type::uint()),
+            field("warning_count", Type::uint()),
+        ],
+        kind: StructTypeKind::Struct,
+    })
+}
+
+// Mirrors `std.intrinsics.Symbol`
+fn symbol_type() -> Type {
+    let field = |name: &str, ty: Type| StructTypeField {
+        name: ustr(name),
+        ty,
+        span: Span::unknown(),
+    };
+
+    Type::Struct(StructType {
+        name: ustr("Symbol"),
+        binding_id: None,
+        fields: vec![
+            field("address", Type::uint()),
+            field("name", Type::str_pointer()),
+            field("file", Type::str_pointer()),
+            field("line", Type::u32()),
+            field("call_sites", Type::slice_pointer(call_site_type(), false)),
+        ],
+        kind: StructTypeKind::Struct,
+    })
+}
+
+// Mirrors `std.intrinsics.CallSite`
+fn call_site_type() -> Type {
+    let field = |name: &str, ty: Type| StructTypeField {
+        name: ustr(name),
+        ty,
+        span: Span::unknown(),
+    };
+
+    Type::Struct(StructType {
+        name: ustr("CallSite"),
+        binding_id: None,
+        fields: vec![field("address", Type::uint()), field("line", Type::u32())],
+        kind: StructTypeKind::Struct,
+    })
+}
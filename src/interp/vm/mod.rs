@@ -1,57 +1,88 @@
 use self::{
-    bytecode::{BytecodeReader, Op},
+    bytecode::{Inst, Register},
+    cast::cast_value,
     value::FunctionValue,
 };
 use super::{
-    ffi::RawPointer,
     interp::Interp,
     vm::{
         byte_seq::{ByteSeq, PutValue},
-        disassemble::bytecode_reader_write_single_inst,
         stack::Stack,
-        value::{Buffer, Function, Value},
+        value::{Buffer, Function, Pointer, Value, ValueKind},
     },
+    wrap_int, wrap_uint,
+};
+use crate::{
+    error::diagnostic::{Diagnostic, Label},
+    span::Span,
+    workspace::Workspace,
 };
 use bumpalo::Bump;
 use colored::Colorize;
-use std::{fmt::Display, ptr};
-use ustr::ustr;
+use paste::paste;
+use std::{fmt::Display, mem, ptr};
 
 pub mod byte_seq;
 pub mod bytecode;
 mod cast;
+pub mod debugger;
 pub mod disassemble;
 mod index;
 mod intrinsics;
+pub mod optimize;
+pub mod profiler;
+mod runtime_check;
 mod stack;
 pub mod value;
 
-const FRAMES_MAX: usize = 64;
-const STACK_MAX: usize = FRAMES_MAX * (std::u8::MAX as usize) + 1;
+const FRAMES_MAX: usize = 1024;
+const STACK_MAX: usize = FRAMES_MAX * 256;
 
 pub type Constants = Vec<Value>;
 pub type Globals = Vec<Value>;
 
+pub type VmResult<T> = Result<T, VmError>;
+
+/// An error which stops the VM. It is reported as a diagnostic at the evaluation site.
+#[derive(Debug, Clone)]
+pub enum VmError {
+    /// Compile-time code panicked, i.e: a runtime check failed
+    Panic { message: String, span: Span },
+    /// An intrinsic couldn't carry out its operation
+    Diagnostic(Diagnostic),
+}
+
+impl VmError {
+    pub fn into_diagnostic(self, eval_span: Span) -> Diagnostic {
+        match self {
+            VmError::Panic { message, span } => Diagnostic::error()
+                .with_message(format!("compile-time code panicked: {}", message))
+                .with_label(Label::primary(span, "panicked here"))
+                .with_label(Label::secondary(eval_span, "while evaluating this")),
+            VmError::Diagnostic(diagnostic) => diagnostic,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct StackFrame<'a> {
+pub struct StackFrame {
     func: *const Function,
-    reader: BytecodeReader<'a>,
-    stack_slot: usize,
+    pc: usize,
+    // The index of the frame's first register in the VM's stack
+    base: usize,
+    // The caller's register that receives the return value
+    dst: Register,
 }
 
-impl<'a> Display for StackFrame<'a> {
+impl Display for StackFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<{:06}\t{}>", self.reader.cursor(), self.func().name)
+        write!(f, "<{:06}\t{}>", self.pc, self.func().name)
     }
 }
 
-impl<'a> StackFrame<'a> {
-    pub fn new(func: *const Function, slot: usize) -> Self {
-        Self {
-            func,
-            reader: unsafe { &*func }.code.reader(),
-            stack_slot: slot,
-        }
+impl StackFrame {
+    pub fn new(func: *const Function, base: usize, dst: Register) -> Self {
+        Self { func, pc: 0, base, dst }
     }
 
     #[inline]
@@ -61,514 +92,564 @@ impl<'a> StackFrame<'a> {
     }
 }
 
-macro_rules! binary_op_int_only {
-    ($vm:expr, $op:tt) => {{
-        let b = $vm.stack.pop();
-        let a = $vm.stack.pop();
-
-        match (&a, &b) {
-            (Value::I8(a), Value::I8(b)) => $vm.stack.push(Value::I8(a $op b)),
-            (Value::I16(a), Value::I16(b)) => $vm.stack.push(Value::I16(a $op b)),
-            (Value::I32(a), Value::I32(b)) => $vm.stack.push(Value::I32(a $op b)),
-            (Value::I64(a), Value::I64(b)) => $vm.stack.push(Value::I64(a $op b)),
-            (Value::Int(a), Value::Int(b)) => $vm.stack.push(Value::Int(a $op b)),
-            (Value::U8(a), Value::U8(b)) => $vm.stack.push(Value::U8(a $op b)),
-            (Value::U16(a), Value::U16(b)) => $vm.stack.push(Value::U16(a $op b)),
-            (Value::U32(a), Value::U32(b)) => $vm.stack.push(Value::U32(a $op b)),
-            (Value::U64(a), Value::U64(b)) => $vm.stack.push(Value::U64(a $op b)),
-            (Value::Uint(a), Value::Uint(b)) => $vm.stack.push(Value::Uint(a $op b)),
-            _=> panic!("invalid types in binary operation `{}` : `{}` and `{}`", stringify!($op), a.to_string() ,b.to_string())
+// Reads the operands of a binary instruction, whose type is known when lowering
+macro_rules! operands {
+    ($vm:expr, $lhs:expr, $rhs:expr, $variant:ident) => {
+        paste! { ($vm.register($lhs).[<as_ $variant:snake>](), $vm.register($rhs).[<as_ $variant:snake>]()) }
+    };
+}
+
+macro_rules! apply_op {
+    ($operands:expr, $op:tt) => {{
+        let (a, b) = $operands;
+        a $op b
+    }};
+}
+
+// Integer operations that overflow wrap around, and panic when overflow checks are enabled
+macro_rules! overflowing_op {
+    ($vm:expr, $dst:expr, $lhs:expr, $rhs:expr, $ty:expr, $op:tt, $overflowing_fn:ident, $op_name:literal $(, $extra_pat:pat => $extra_value:expr)*) => {{
+        let (result, overflow) = match $ty {
+            ValueKind::I8 => overflowing!(I8, operands!($vm, $lhs, $rhs, I8), $overflowing_fn),
+            ValueKind::I16 => overflowing!(I16, operands!($vm, $lhs, $rhs, I16), $overflowing_fn),
+            ValueKind::I32 => overflowing!(I32, operands!($vm, $lhs, $rhs, I32), $overflowing_fn),
+            ValueKind::I64 => overflowing!(I64, operands!($vm, $lhs, $rhs, I64), $overflowing_fn),
+            ValueKind::Int => overflowing!(Int, operands!($vm, $lhs, $rhs, Int), $overflowing_fn, wrap_int),
+            ValueKind::U8 => overflowing!(U8, operands!($vm, $lhs, $rhs, U8), $overflowing_fn),
+            ValueKind::U16 => overflowing!(U16, operands!($vm, $lhs, $rhs, U16), $overflowing_fn),
+            ValueKind::U32 => overflowing!(U32, operands!($vm, $lhs, $rhs, U32), $overflowing_fn),
+            ValueKind::U64 => overflowing!(U64, operands!($vm, $lhs, $rhs, U64), $overflowing_fn),
+            ValueKind::Uint => overflowing!(Uint, operands!($vm, $lhs, $rhs, Uint), $overflowing_fn, wrap_uint),
+            ValueKind::F32 => (Value::F32(apply_op!(operands!($vm, $lhs, $rhs, F32), $op)), false),
+            ValueKind::F64 => (Value::F64(apply_op!(operands!($vm, $lhs, $rhs, F64), $op)), false),
+            $($extra_pat => ($extra_value, false),)*
+            ty => panic!("invalid type in binary operation `{}` : `{}`", stringify!($op), ty),
+        };
+
+        if overflow && $vm.overflow_checks_enabled() {
+            return Err($vm.panic(concat!("attempt to ", $op_name, " with overflow")));
         }
+
+        $vm.set_register($dst, result);
     }};
 }
 
-macro_rules! compare_op {
-    ($vm:expr, $op:tt) => {
-        let b = $vm.stack.pop();
-        let a = $vm.stack.pop();
-
-        match (&a, &b) {
-            (Value::Bool(a), Value::Bool(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::I8(a), Value::I8(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::I16(a), Value::I16(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::I32(a), Value::I32(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::I64(a), Value::I64(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::Int(a), Value::Int(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::U8(a), Value::U8(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::U16(a), Value::U16(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::U32(a), Value::U32(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::U64(a), Value::U64(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::Uint(a), Value::Uint(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::F32(a), Value::F32(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::F64(a), Value::F64(b)) => $vm.stack.push(Value::Bool(a $op b)),
-            (Value::Pointer(a), Value::Pointer(b)) => $vm.stack.push(Value::Bool(a.as_inner_raw() $op b.as_inner_raw())),
-            _ => panic!("invalid types in compare operation `{}` and `{}`", a.to_string() ,b.to_string())
+macro_rules! overflowing {
+    ($variant:ident, $operands:expr, $overflowing_fn:ident) => {{
+        let (a, b) = $operands;
+        let (value, overflow) = a.$overflowing_fn(*b);
+        (Value::$variant(value), overflow)
+    }};
+    // `int` and `uint` are wrapped to the target's word size, which may be narrower than the host's
+    ($variant:ident, $operands:expr, $overflowing_fn:ident, $wrap:ident) => {{
+        let (a, b) = $operands;
+        let (value, overflow) = a.$overflowing_fn(*b);
+        let wrapped = $wrap(value);
+        (Value::$variant(wrapped), overflow || wrapped != value)
+    }};
+}
+
+macro_rules! int_op {
+    ($vm:expr, $dst:expr, $lhs:expr, $rhs:expr, $ty:expr, $op:tt $(, $extra_pat:pat => $extra_value:expr)*) => {{
+        let result = match $ty {
+            ValueKind::I8 => Value::I8(apply_op!(operands!($vm, $lhs, $rhs, I8), $op)),
+            ValueKind::I16 => Value::I16(apply_op!(operands!($vm, $lhs, $rhs, I16), $op)),
+            ValueKind::I32 => Value::I32(apply_op!(operands!($vm, $lhs, $rhs, I32), $op)),
+            ValueKind::I64 => Value::I64(apply_op!(operands!($vm, $lhs, $rhs, I64), $op)),
+            ValueKind::Int => Value::Int(wrap_int(apply_op!(operands!($vm, $lhs, $rhs, Int), $op))),
+            ValueKind::U8 => Value::U8(apply_op!(operands!($vm, $lhs, $rhs, U8), $op)),
+            ValueKind::U16 => Value::U16(apply_op!(operands!($vm, $lhs, $rhs, U16), $op)),
+            ValueKind::U32 => Value::U32(apply_op!(operands!($vm, $lhs, $rhs, U32), $op)),
+            ValueKind::U64 => Value::U64(apply_op!(operands!($vm, $lhs, $rhs, U64), $op)),
+            ValueKind::Uint => Value::Uint(wrap_uint(apply_op!(operands!($vm, $lhs, $rhs, Uint), $op))),
+            $($extra_pat => $extra_value,)*
+            ty => panic!("invalid type in binary operation `{}` : `{}`", stringify!($op), ty),
+        };
+
+        $vm.set_register($dst, result);
+    }};
+}
+
+macro_rules! compare_values {
+    ($vm:expr, $lhs:expr, $rhs:expr, $ty:expr, $op:tt) => {
+        match $ty {
+            ValueKind::Bool => apply_op!(operands!($vm, $lhs, $rhs, Bool), $op),
+            ValueKind::I8 => apply_op!(operands!($vm, $lhs, $rhs, I8), $op),
+            ValueKind::I16 => apply_op!(operands!($vm, $lhs, $rhs, I16), $op),
+            ValueKind::I32 => apply_op!(operands!($vm, $lhs, $rhs, I32), $op),
+            ValueKind::I64 => apply_op!(operands!($vm, $lhs, $rhs, I64), $op),
+            ValueKind::Int => apply_op!(operands!($vm, $lhs, $rhs, Int), $op),
+            ValueKind::U8 => apply_op!(operands!($vm, $lhs, $rhs, U8), $op),
+            ValueKind::U16 => apply_op!(operands!($vm, $lhs, $rhs, U16), $op),
+            ValueKind::U32 => apply_op!(operands!($vm, $lhs, $rhs, U32), $op),
+            ValueKind::U64 => apply_op!(operands!($vm, $lhs, $rhs, U64), $op),
+            ValueKind::Uint => apply_op!(operands!($vm, $lhs, $rhs, Uint), $op),
+            ValueKind::F32 => apply_op!(operands!($vm, $lhs, $rhs, F32), $op),
+            ValueKind::F64 => apply_op!(operands!($vm, $lhs, $rhs, F64), $op),
+            ValueKind::Pointer => {
+                let (a, b) = operands!($vm, $lhs, $rhs, Pointer);
+                a.raw $op b.raw
+            }
+            ty => panic!("invalid type in compare operation `{}`", ty),
         }
     };
 }
 
-macro_rules! logic_op {
-    ($vm:expr, $op:tt) => {
-        let b = $vm.stack.pop();
-        let a = $vm.stack.pop();
+macro_rules! compare_op {
+    ($vm:expr, $dst:expr, $lhs:expr, $rhs:expr, $ty:expr, $op:tt) => {{
+        let result = compare_values!($vm, $lhs, $rhs, $ty, $op);
+        $vm.set_register($dst, Value::Bool(result));
+    }};
+}
 
-        $vm.stack.push(Value::Bool(a.into_bool() $op b.into_bool()));
-    };
+macro_rules! compare_jmpf {
+    ($vm:expr, $lhs:expr, $rhs:expr, $ty:expr, $target:expr, $op:tt) => {{
+        if !compare_values!($vm, $lhs, $rhs, $ty, $op) {
+            $vm.frame_mut().pc = $target as usize;
+        }
+    }};
 }
 
 pub struct VM<'vm> {
     pub interp: &'vm mut Interp,
+    pub workspace: &'vm Workspace,
     pub bump: &'vm mut Bump,
     pub stack: Stack<Value, STACK_MAX>,
-    pub frames: Stack<StackFrame<'vm>, FRAMES_MAX>,
-    pub frame: *mut StackFrame<'vm>,
+    pub frames: Stack<StackFrame, FRAMES_MAX>,
+    pub frame: *mut StackFrame,
+    // An error raised by a function which was called back from foreign code.
+    // It is returned once the foreign call returns, since it can't unwind through foreign frames.
+    pub foreign_error: Option<VmError>,
 }
 
 impl<'vm> VM<'vm> {
-    pub fn new(interp: &'vm mut Interp, bump: &'vm mut Bump) -> Self {
+    pub fn new(interp: &'vm mut Interp, workspace: &'vm Workspace, bump: &'vm mut Bump) -> Self {
         Self {
             interp,
+            workspace,
             bump,
             stack: Stack::new(),
             frames: Stack::new(),
             frame: ptr::null_mut(),
+            foreign_error: None,
         }
     }
 
-    pub fn run_function(&mut self, function: Function) -> Value {
-        self.push_frame(&function);
-        self.run_inner()
+    pub fn run_function(&mut self, function: Function) -> VmResult<Value> {
+        self.run_nested_function(&function, vec![])
+    }
+
+    /// Runs a function on top of the current frames, returning when it returns.
+    /// This is also used for re-entrant calls, i.e: foreign code calling back into a compile-time function.
+    pub fn run_nested_function(&mut self, function: *const Function, args: Vec<Value>) -> VmResult<Value> {
+        let base = self.stack.len();
+        let base_frame_count = self.frames.len();
+
+        self.push_frame(function, base, 0)?;
+
+        for (index, arg) in args.into_iter().enumerate() {
+            self.stack.set(base + index, arg);
+        }
+
+        let result = self.run_inner(base_frame_count);
+
+        if result.is_err() {
+            // unwind the frames of this run, so that the caller's frame can be resumed
+            while self.frames.len() > base_frame_count {
+                self.frames.pop();
+
+                if let Some(profiler) = &mut self.interp.profiler {
+                    profiler.leave_function();
+                }
+            }
+
+            self.stack.truncate(base);
+
+            if !self.frames.is_empty() {
+                self.frame = self.frames.last_mut() as _;
+            }
+        }
+
+        result
     }
 
-    fn run_inner(&mut self) -> Value {
+    fn run_inner(&mut self, base_frame_count: usize) -> VmResult<Value> {
         loop {
             // self.trace(TraceLevel::Full);
 
-            let reader = &mut self.frame_mut().reader;
+            if self.interp.debugger.is_some() {
+                self.debug_hook();
+            }
 
-            match reader.read_op() {
-                Op::Pop => {
-                    self.stack.pop();
-                }
-                Op::LoadConst => {
-                    let addr = reader.read_u32();
-                    let const_ = self.interp.constants.get(addr as usize).unwrap();
+            let frame = self.frame_mut();
+            let inst = frame.func().code.instructions[frame.pc];
+            frame.pc += 1;
 
-                    let value = match const_ {
-                        Value::ExternVariable(variable) => {
-                            let symbol =
-                                unsafe { self.interp.ffi.load_symbol(ustr(&variable.lib.path()), variable.name) };
+            if let Some(profiler) = &mut self.interp.profiler {
+                profiler.count_instruction();
+            }
 
-                            unsafe { Value::from_type_and_ptr(&variable.ty, *symbol as RawPointer) }
-                        }
-                        value => value.clone(),
-                    };
+            match inst {
+                Inst::LoadConst { dst, slot } => {
+                    let value = self.interp.constants[slot as usize].clone();
 
-                    self.stack.push(value);
-                }
-                Op::Add => {
-                    let b = self.stack.pop();
-                    let a = self.stack.pop();
-
-                    match (&a, &b) {
-                        (Value::I8(a), Value::I8(b)) => self.stack.push(Value::I8(a + b)),
-                        (Value::I16(a), Value::I16(b)) => self.stack.push(Value::I16(a + b)),
-                        (Value::I32(a), Value::I32(b)) => self.stack.push(Value::I32(a + b)),
-                        (Value::I64(a), Value::I64(b)) => self.stack.push(Value::I64(a + b)),
-                        (Value::Int(a), Value::Int(b)) => self.stack.push(Value::Int(a + b)),
-                        (Value::U8(a), Value::U8(b)) => self.stack.push(Value::U8(a + b)),
-                        (Value::U16(a), Value::U16(b)) => self.stack.push(Value::U16(a + b)),
-                        (Value::U32(a), Value::U32(b)) => self.stack.push(Value::U32(a + b)),
-                        (Value::U64(a), Value::U64(b)) => self.stack.push(Value::U64(a + b)),
-                        (Value::Uint(a), Value::Uint(b)) => self.stack.push(Value::Uint(a + b)),
-                        (Value::F32(a), Value::F32(b)) => self.stack.push(Value::F32(a + b)),
-                        (Value::F64(a), Value::F64(b)) => self.stack.push(Value::F64(a + b)),
-                        (Value::Pointer(a), Value::Int(b)) => self.stack.push(Value::Pointer(unsafe { a.offset(*b) })),
-                        _ => panic!(
-                            "invalid types in binary operation `{}` : `{}` and `{}`",
-                            stringify!(+),
-                            a.to_string(),
-                            b.to_string()
-                        ),
-                    }
-                }
-                Op::Sub => {
-                    let b = self.stack.pop();
-                    let a = self.stack.pop();
-
-                    match (&a, &b) {
-                        (Value::I8(a), Value::I8(b)) => self.stack.push(Value::I8(a - b)),
-                        (Value::I16(a), Value::I16(b)) => self.stack.push(Value::I16(a - b)),
-                        (Value::I32(a), Value::I32(b)) => self.stack.push(Value::I32(a - b)),
-                        (Value::I64(a), Value::I64(b)) => self.stack.push(Value::I64(a - b)),
-                        (Value::Int(a), Value::Int(b)) => self.stack.push(Value::Int(a - b)),
-                        (Value::U8(a), Value::U8(b)) => self.stack.push(Value::U8(a - b)),
-                        (Value::U16(a), Value::U16(b)) => self.stack.push(Value::U16(a - b)),
-                        (Value::U32(a), Value::U32(b)) => self.stack.push(Value::U32(a - b)),
-                        (Value::U64(a), Value::U64(b)) => self.stack.push(Value::U64(a - b)),
-                        (Value::Uint(a), Value::Uint(b)) => self.stack.push(Value::Uint(a - b)),
-                        (Value::F32(a), Value::F32(b)) => self.stack.push(Value::F32(a - b)),
-                        (Value::F64(a), Value::F64(b)) => self.stack.push(Value::F64(a - b)),
-                        (Value::Pointer(a), Value::Int(b)) => self.stack.push(Value::Pointer(unsafe { a.offset(-*b) })),
-                        _ => panic!(
-                            "invalid types in binary operation `{}` : `{}` and `{}`",
-                            stringify!(-),
-                            a.to_string(),
-                            b.to_string()
-                        ),
-                    }
-                }
-                Op::Mul => {
-                    let b = self.stack.pop();
-                    let a = self.stack.pop();
-
-                    match (&a, &b) {
-                        (Value::I8(a), Value::I8(b)) => self.stack.push(Value::I8(a * b)),
-                        (Value::I16(a), Value::I16(b)) => self.stack.push(Value::I16(a * b)),
-                        (Value::I32(a), Value::I32(b)) => self.stack.push(Value::I32(a * b)),
-                        (Value::I64(a), Value::I64(b)) => self.stack.push(Value::I64(a * b)),
-                        (Value::Int(a), Value::Int(b)) => self.stack.push(Value::Int(a * b)),
-                        (Value::U8(a), Value::U8(b)) => self.stack.push(Value::U8(a * b)),
-                        (Value::U16(a), Value::U16(b)) => self.stack.push(Value::U16(a * b)),
-                        (Value::U32(a), Value::U32(b)) => self.stack.push(Value::U32(a * b)),
-                        (Value::U64(a), Value::U64(b)) => self.stack.push(Value::U64(a * b)),
-                        (Value::Uint(a), Value::Uint(b)) => self.stack.push(Value::Uint(a * b)),
-                        (Value::F32(a), Value::F32(b)) => self.stack.push(Value::F32(a * b)),
-                        (Value::F64(a), Value::F64(b)) => self.stack.push(Value::F64(a * b)),
-                        _ => panic!(
-                            "invalid types in binary operation `{}` : `{}` and `{}`",
-                            stringify!(*),
-                            a.to_string(),
-                            b.to_string()
-                        ),
-                    }
-                }
-                Op::Div => {
-                    let b = self.stack.pop();
-                    let a = self.stack.pop();
-
-                    match (&a, &b) {
-                        (Value::I8(a), Value::I8(b)) => self.stack.push(Value::I8(a % b)),
-                        (Value::I16(a), Value::I16(b)) => self.stack.push(Value::I16(a % b)),
-                        (Value::I32(a), Value::I32(b)) => self.stack.push(Value::I32(a % b)),
-                        (Value::I64(a), Value::I64(b)) => self.stack.push(Value::I64(a % b)),
-                        (Value::Int(a), Value::Int(b)) => self.stack.push(Value::Int(a % b)),
-                        (Value::U8(a), Value::U8(b)) => self.stack.push(Value::U8(a % b)),
-                        (Value::U16(a), Value::U16(b)) => self.stack.push(Value::U16(a % b)),
-                        (Value::U32(a), Value::U32(b)) => self.stack.push(Value::U32(a % b)),
-                        (Value::U64(a), Value::U64(b)) => self.stack.push(Value::U64(a % b)),
-                        (Value::Uint(a), Value::Uint(b)) => self.stack.push(Value::Uint(a % b)),
-                        (Value::F32(a), Value::F32(b)) => self.stack.push(Value::F32(a % b)),
-                        (Value::F64(a), Value::F64(b)) => self.stack.push(Value::F64(a % b)),
-                        _ => panic!(
-                            "invalid types in binary operation `{}` : `{}` and `{}`",
-                            stringify!(%),
-                            a.to_string(),
-                            b.to_string()
-                        ),
+                    // pointer constants are the addresses of extern variables
+                    if value.is_pointer() {
+                        self.interp.has_side_effects = true;
                     }
+
+                    self.set_register(dst, value);
                 }
-                Op::Rem => {
-                    let b = self.stack.pop();
-                    let a = self.stack.pop();
-
-                    match (&a, &b) {
-                        (Value::I8(a), Value::I8(b)) => self.stack.push(Value::I8(a % b)),
-                        (Value::I16(a), Value::I16(b)) => self.stack.push(Value::I16(a % b)),
-                        (Value::I32(a), Value::I32(b)) => self.stack.push(Value::I32(a % b)),
-                        (Value::I64(a), Value::I64(b)) => self.stack.push(Value::I64(a % b)),
-                        (Value::Int(a), Value::Int(b)) => self.stack.push(Value::Int(a % b)),
-                        (Value::U8(a), Value::U8(b)) => self.stack.push(Value::U8(a % b)),
-                        (Value::U16(a), Value::U16(b)) => self.stack.push(Value::U16(a % b)),
-                        (Value::U32(a), Value::U32(b)) => self.stack.push(Value::U32(a % b)),
-                        (Value::U64(a), Value::U64(b)) => self.stack.push(Value::U64(a % b)),
-                        (Value::Uint(a), Value::Uint(b)) => self.stack.push(Value::Uint(a % b)),
-                        (Value::F32(a), Value::F32(b)) => self.stack.push(Value::F32(a % b)),
-                        (Value::F64(a), Value::F64(b)) => self.stack.push(Value::F64(a % b)),
-                        _ => panic!(
-                            "invalid types in binary operation `{}` : `{}` and `{}`",
-                            stringify!(%),
-                            a.to_string(),
-                            b.to_string()
-                        ),
+                Inst::LoadGlobal { dst, slot } => match self.interp.globals.get(slot as usize) {
+                    Some(value) => {
+                        let value = value.clone();
+                        self.interp.has_side_effects = true;
+                        self.set_register(dst, value);
                     }
-                }
-                Op::Neg => match self.stack.pop() {
-                    Value::Int(v) => self.stack.push(Value::Int(-v)),
-                    value => panic!("invalid value {}", value.to_string()),
+                    None => panic!("undefined global `{}`", slot),
                 },
-                Op::Not => {
-                    let result = match self.stack.pop() {
-                        Value::I8(v) => Value::I8(!v),
-                        Value::I16(v) => Value::I16(!v),
-                        Value::I32(v) => Value::I32(!v),
-                        Value::I64(v) => Value::I64(!v),
-                        Value::Int(v) => Value::Int(!v),
-                        Value::U8(v) => Value::U8(!v),
-                        Value::U16(v) => Value::U16(!v),
-                        Value::U32(v) => Value::U32(!v),
-                        Value::U64(v) => Value::U64(!v),
-                        Value::Uint(v) => Value::Uint(!v),
-                        Value::Bool(v) => Value::Bool(!v),
-                        v => panic!("invalid value {}", v.to_string()),
-                    };
-                    self.stack.push(result);
-                }
-                Op::Deref => match self.stack.pop() {
-                    Value::Pointer(ptr) => {
-                        let value = unsafe { ptr.deref_value() };
-                        self.stack.push(value);
+                Inst::LoadGlobalPtr { dst, slot } => match self.interp.globals.get(slot as usize) {
+                    Some(value) => {
+                        self.interp.has_side_effects = true;
+                        let ty = value.get_type(self.interp);
+                        let value = &mut self.interp.globals[slot as usize];
+                        let value = Value::Pointer(Pointer::from_value(value, ty));
+                        self.set_register(dst, value);
                     }
-                    value => panic!("invalid value {}", value.to_string()),
+                    None => panic!("undefined global `{}`", slot),
                 },
-                Op::Eq => {
-                    compare_op!(self, ==);
-                }
-                Op::Ne => {
-                    compare_op!(self, !=);
-                }
-                Op::Lt => {
-                    compare_op!(self, <);
-                }
-                Op::Le => {
-                    compare_op!(self, <=);
-                }
-                Op::Gt => {
-                    compare_op!(self, >);
-                }
-                Op::Ge => {
-                    compare_op!(self, >=);
-                }
-                Op::And => {
-                    logic_op!(self, &&);
-                }
-                Op::Or => {
-                    logic_op!(self, ||);
-                }
-                Op::Shl => {
-                    binary_op_int_only!(self, <<)
-                }
-                Op::Shr => {
-                    binary_op_int_only!(self, >>);
-                }
-                Op::Xor => {
-                    binary_op_int_only!(self, ^);
-                }
-                Op::Jmp => {
-                    let offset = reader.read_i32();
-                    self.jmp(offset);
-                }
-                Op::Jmpf => {
-                    let offset = reader.read_i32();
+                Inst::StoreGlobal { slot, src } => {
+                    self.interp.has_side_effects = true;
+                    let value = self.register(src).clone();
+                    self.interp.globals[slot as usize].overwrite(value);
+                }
+                Inst::Move { dst, src } => {
+                    let value = self.register(src).clone();
+                    self.set_register(dst, value);
+                }
+                Inst::RegisterPtr { dst, src } => {
+                    let ty = self.register(src).get_type(self.interp);
+                    let value = Value::Pointer(Pointer::from_value(self.register_mut(src), ty));
+                    self.set_register(dst, value);
+                }
+                Inst::Add { dst, lhs, rhs, ty } => {
+                    overflowing_op!(self, dst, lhs, rhs, ty, ty, +, overflowing_add, "add", ValueKind::Pointer => Value::Pointer(unsafe { self.register(lhs).as_pointer().offset(*self.register(rhs).as_int()) }))
+                }
+                Inst::Sub { dst, lhs, rhs, ty } => {
+                    overflowing_op!(self, dst, lhs, rhs, ty, ty, -, overflowing_sub, "subtract", ValueKind::Pointer => Value::Pointer(unsafe { self.register(lhs).as_pointer().offset(-*self.register(rhs).as_int()) }))
+                }
+                Inst::Mul { dst, lhs, rhs, ty } => {
+                    overflowing_op!(self, dst, lhs, rhs, ty, *, overflowing_mul, "multiply")
+                }
+                Inst::Div { dst, lhs, rhs, ty } => {
+                    self.check_division_by_zero(rhs)?;
+                    overflowing_op!(self, dst, lhs, rhs, ty, /, overflowing_div, "divide")
+                }
+                Inst::Rem { dst, lhs, rhs, ty } => {
+                    self.check_division_by_zero(rhs)?;
+                    overflowing_op!(self, dst, lhs, rhs, ty, %, overflowing_rem, "calculate the remainder")
+                }
+                Inst::Shl { dst, lhs, rhs, ty } => int_op!(self, dst, lhs, rhs, ty, <<),
+                Inst::Shr { dst, lhs, rhs, ty } => int_op!(self, dst, lhs, rhs, ty, >>),
+                Inst::BitAnd { dst, lhs, rhs, ty } => {
+                    int_op!(self, dst, lhs, rhs, ty, &, ValueKind::Bool => Value::Bool(apply_op!(operands!(self, lhs, rhs, Bool), &)))
+                }
+                Inst::BitOr { dst, lhs, rhs, ty } => {
+                    int_op!(self, dst, lhs, rhs, ty, |, ValueKind::Bool => Value::Bool(apply_op!(operands!(self, lhs, rhs, Bool), |)))
+                }
+                Inst::BitXor { dst, lhs, rhs, ty } => {
+                    int_op!(self, dst, lhs, rhs, ty, ^, ValueKind::Bool => Value::Bool(apply_op!(operands!(self, lhs, rhs, Bool), ^)))
+                }
+                Inst::Eq { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, ==),
+                Inst::Ne { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, !=),
+                Inst::Lt { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, <),
+                Inst::Le { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, <=),
+                Inst::Gt { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, >),
+                Inst::Ge { dst, lhs, rhs, ty } => compare_op!(self, dst, lhs, rhs, ty, >=),
+                Inst::Neg { dst, src, ty } => {
+                    let value = self.register(src);
+                    let result = match ty {
+                        ValueKind::I8 => Value::I8(-value.as_i8()),
+                        ValueKind::I16 => Value::I16(-value.as_i16()),
+                        ValueKind::I32 => Value::I32(-value.as_i32()),
+                        ValueKind::I64 => Value::I64(-value.as_i64()),
+                        ValueKind::Int => Value::Int(wrap_int(-value.as_int())),
+                        ValueKind::F32 => Value::F32(-value.as_f32()),
+                        ValueKind::F64 => Value::F64(-value.as_f64()),
+                        ty => panic!("invalid type in negation `{}`", ty),
+                    };
 
-                    if !self.stack.pop().into_bool() {
-                        self.jmp(offset);
-                    }
+                    self.set_register(dst, result);
+                }
+                Inst::Not { dst, src, ty } => {
+                    let value = self.register(src);
+                    let result = match ty {
+                        ValueKind::I8 => Value::I8(!value.as_i8()),
+                        ValueKind::I16 => Value::I16(!value.as_i16()),
+                        ValueKind::I32 => Value::I32(!value.as_i32()),
+                        ValueKind::I64 => Value::I64(!value.as_i64()),
+                        ValueKind::Int => Value::Int(wrap_int(!value.as_int())),
+                        ValueKind::U8 => Value::U8(!value.as_u8()),
+                        ValueKind::U16 => Value::U16(!value.as_u16()),
+                        ValueKind::U32 => Value::U32(!value.as_u32()),
+                        ValueKind::U64 => Value::U64(!value.as_u64()),
+                        ValueKind::Uint => Value::Uint(wrap_uint(!value.as_uint())),
+                        ValueKind::Bool => Value::Bool(!value.as_bool()),
+                        ty => panic!("invalid type in not operation `{}`", ty),
+                    };
+
+                    self.set_register(dst, result);
                 }
-                Op::Return => {
-                    let frame = self.frames.pop();
-                    let return_value = self.stack.pop();
+                Inst::Deref { dst, src } => {
+                    self.check_null_pointer_deref(src)?;
 
-                    if self.frames.is_empty() {
-                        break return_value;
-                    } else {
-                        self.stack.truncate(frame.stack_slot - frame.func().ty.params.len());
-                        self.frame = self.frames.last_mut() as _;
-                        self.stack.push(return_value);
+                    let value = match self.register(src) {
+                        Value::Pointer(ptr) => unsafe { ptr.deref_value() },
+                        value => panic!("invalid value {}", value.to_string()),
+                    };
+
+                    self.set_register(dst, value);
+                }
+                Inst::Jmp { target } => {
+                    self.frame_mut().pc = target as usize;
+                }
+                Inst::Jmpf { cond, target } => {
+                    if !*self.register(cond).as_bool() {
+                        self.frame_mut().pc = target as usize;
                     }
                 }
-                Op::Call => {
-                    let arg_count = reader.read_u32();
-
-                    match self.stack.pop() {
-                        Value::Function(addr) => {
-                            let function = self
-                                .interp
-                                .get_function(addr.id)
-                                .unwrap_or_else(|| panic!("couldn't find '{}' {:?}", addr.name, addr.id));
-
-                            match function {
-                                FunctionValue::Orphan(function) => {
-                                    self.push_frame(function);
+                Inst::JmpfEq { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, ==),
+                Inst::JmpfNe { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, !=),
+                Inst::JmpfLt { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, <),
+                Inst::JmpfLe { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, <=),
+                Inst::JmpfGt { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, >),
+                Inst::JmpfGe { lhs, rhs, ty, target } => compare_jmpf!(self, lhs, rhs, ty, target, >=),
+                Inst::Call {
+                    dst,
+                    callee,
+                    args,
+                    arg_count,
+                } => match self.register(callee).clone() {
+                    Value::Function(addr) => {
+                        let function = self
+                            .interp
+                            .get_function(addr.id)
+                            .unwrap_or_else(|| panic!("couldn't find '{}' {:?}", addr.name, addr.id));
+
+                        match function {
+                            FunctionValue::Orphan(function) => {
+                                let function = function as *const Function;
+
+                                // the callee's frame starts right after the caller's registers,
+                                // with the arguments moved into its first registers
+                                let base = self.stack.len();
+                                let args_start = self.frame().base + args as usize;
+
+                                self.push_frame(function, base, dst)?;
+
+                                for index in 0..arg_count as usize {
+                                    let arg = mem::take(self.stack.get_mut(args_start + index));
+                                    self.stack.set(base + index, arg);
                                 }
-                                FunctionValue::Extern(function) => {
-                                    let mut values = (0..arg_count)
-                                        .into_iter()
-                                        .map(|_| self.stack.pop())
-                                        .collect::<Vec<Value>>();
+                            }
+                            FunctionValue::Extern(function) => {
+                                self.interp.has_side_effects = true;
 
-                                    values.reverse();
+                                let function = function.clone();
+                                let values = self.collect_args(args, arg_count);
 
-                                    let function = function.clone();
-                                    let vm_ptr = self as *mut _;
-                                    let interp_ptr = self.interp as *const _;
+                                let vm_ptr = self as *mut _;
+                                let interp_ptr = self.interp as *const _;
 
-                                    let result = unsafe { self.interp.ffi.call(function, values, vm_ptr, interp_ptr) };
+                                let result = unsafe { self.interp.ffi.call(function, values, vm_ptr, interp_ptr) };
 
-                                    self.stack.push(result);
+                                if let Some(error) = self.foreign_error.take() {
+                                    return Err(error);
                                 }
+
+                                self.set_register(dst, result);
                             }
                         }
-                        Value::Intrinsic(intrinsic) => self.dispatch_intrinsic(intrinsic),
-                        value => panic!("tried to call uncallable value `{}`", value.to_string()),
                     }
-                }
-                Op::LoadGlobal => {
-                    let slot = reader.read_u32();
+                    Value::Intrinsic(intrinsic) => {
+                        self.interp.has_side_effects = true;
 
-                    match self.interp.globals.get(slot as usize) {
-                        Some(value) => self.stack.push(value.clone()),
-                        None => panic!("undefined global `{}`", slot),
+                        let values = self.collect_args(args, arg_count);
+                        let result = self.dispatch_intrinsic(intrinsic, &values)?;
+                        self.set_register(dst, result);
                     }
-                }
-                Op::LoadGlobalPtr => {
-                    let slot = reader.read_u32();
+                    value => panic!("tried to call uncallable value `{}`", value.to_string()),
+                },
+                Inst::Return { src } => {
+                    let return_value = mem::take(self.register_mut(src));
+                    let frame = self.frames.pop();
 
-                    match self.interp.globals.get_mut(slot as usize) {
-                        Some(value) => self.stack.push(Value::Pointer(value.into())),
-                        None => panic!("undefined global `{}`", slot),
+                    if let Some(profiler) = &mut self.interp.profiler {
+                        profiler.leave_function();
                     }
-                }
-                Op::StoreGlobal => {
-                    let slot = reader.read_u32();
-                    self.interp.globals[slot as usize] = self.stack.pop();
-                }
-                Op::Peek => {
-                    let offset = reader.read_i32();
-                    let slot = self.frame().stack_slot as isize + offset as isize;
 
-                    let value = self.stack.get(slot as usize).clone();
-                    self.stack.push(value);
-                }
-                Op::PeekPtr => {
-                    let offset = reader.read_i32();
-                    let slot = self.frame().stack_slot as isize + offset as isize;
+                    if self.frames.len() == base_frame_count {
+                        // clean up after the function, and resume the caller's frame if this was a nested run
+                        self.stack.truncate(frame.base);
 
-                    let value = self.stack.get_mut(slot as usize);
-                    let value = Value::Pointer(value.into());
-                    self.stack.push(value);
-                }
-                Op::StoreLocal => {
-                    let offset = reader.read_i32();
-                    let slot = self.frame().stack_slot as isize + offset as isize;
+                        if !self.frames.is_empty() {
+                            self.frame = self.frames.last_mut() as _;
+                        }
 
-                    let value = self.stack.pop();
-                    self.stack.set(slot as usize, value);
+                        break Ok(return_value);
+                    } else {
+                        self.stack.truncate(frame.base);
+                        self.frame = self.frames.last_mut() as _;
+                        self.set_register(frame.dst, return_value);
+                    }
                 }
-                Op::Offset => {
-                    let index = self.stack.pop().into_uint();
-                    let value = self.stack.pop();
-                    self.offset(value, index);
+                Inst::Offset { dst, src, offset } => {
+                    let offset = *self.register(offset).as_uint();
+                    let value = self.offset(src, offset);
+                    self.set_register(dst, value);
                 }
-                Op::ConstIndex => {
-                    let index = reader.read_u32();
-
-                    let value = self.stack.pop();
-                    self.index(value, index as usize);
+                Inst::ConstIndex { dst, src, index } => {
+                    let value = self.index(src, index as usize);
+                    self.set_register(dst, value);
                 }
-                Op::ConstIndexPtr => {
-                    let index = reader.read_u32();
+                Inst::ConstIndexPtr { dst, src, index } => {
+                    let value = self.index_ptr(src, index as usize);
+                    self.set_register(dst, value);
+                }
+                Inst::Assign { ptr, src } => {
+                    self.check_null_pointer_deref(ptr)?;
 
-                    let value = self.stack.pop();
-                    self.index_ptr(value, index as usize);
+                    let value = self.register(src).clone();
+                    let ptr = self.register(ptr).as_pointer().clone();
+                    unsafe { ptr.write_value(value) }
+                }
+                Inst::CheckIndexOutOfBounds { index, len } => {
+                    self.check_index_out_of_bounds(index, len)?;
                 }
-                Op::Assign => {
-                    let lhs = self.stack.pop().into_pointer();
-                    let rhs = self.stack.pop();
-                    unsafe { lhs.write_value(rhs) }
+                Inst::CheckSliceEndBeforeStart { low, high } => {
+                    self.check_slice_end_before_start(low, high)?;
                 }
-                Op::Cast => {
-                    self.cast_op();
+                Inst::CheckSliceRangeOutOfBounds { low, high, len } => {
+                    self.check_slice_range_out_of_bounds(low, high, len)?;
                 }
-                Op::BufferAlloc => {
-                    let size = reader.read_u32();
-
-                    let ty = self.stack.pop().into_type();
-                    self.stack.push(Value::Buffer(Buffer {
-                        bytes: ByteSeq::new(size as usize),
-                        ty,
-                    }));
+                Inst::Cast { dst, src, ty } => {
+                    let value = self.register(src).clone();
+                    let value = cast_value(value, self.interp.constants[ty as usize].as_type());
+                    self.set_register(dst, value);
                 }
-                Op::BufferPut => {
-                    let offset = reader.read_u32();
+                Inst::BufferAlloc { dst, ty, size } => {
+                    let ty = self.interp.constants[ty as usize].as_type().clone();
 
-                    let value = self.stack.pop();
+                    self.set_register(
+                        dst,
+                        Value::Buffer(Buffer {
+                            bytes: ByteSeq::new(size as usize),
+                            ty,
+                        }),
+                    );
+                }
+                Inst::BufferPut { buf, offset, src } => {
+                    let value = self.register(src).clone();
 
-                    let buf = self.stack.peek_mut(0).as_buffer_mut();
+                    let buf = self.register_mut(buf).as_buffer_mut();
                     buf.bytes.offset_mut(offset as usize).put_value(&value);
                 }
-                Op::BufferFill => {
-                    let size = reader.read_u32();
+                Inst::BufferFill { buf, src, count } => {
+                    let value = self.register(src).clone();
 
-                    let value = self.stack.pop();
-                    let buf = self.stack.peek_mut(0).as_buffer_mut();
+                    let buf = self.register_mut(buf).as_buffer_mut();
 
-                    for _ in 0..size {
-                        buf.bytes.put_value(&value);
+                    if count > 0 {
+                        let stride = buf.bytes.len() / count as usize;
+
+                        for index in 0..count as usize {
+                            buf.bytes.offset_mut(index * stride).put_value(&value);
+                        }
                     }
                 }
-                Op::Copy => {
-                    let offset = reader.read_u32();
-                    let value = self.stack.peek(offset as usize).clone();
-                    self.stack.push(value);
-                }
-                Op::Swap => {
-                    let offset = reader.read_u32();
-                    let last_index = self.stack.len() - 1;
-                    self.stack.swap(last_index, last_index - offset as usize);
-                }
-                Op::Halt => {
-                    let result = self.stack.pop();
-                    break result;
-                }
             }
         }
     }
 
     #[inline]
-    pub fn push_frame(&mut self, function: *const Function) {
-        let stack_slot = self.stack.len();
+    pub fn push_frame(&mut self, function: *const Function, base: usize, dst: Register) -> VmResult<()> {
+        let function = unsafe { &*function };
+        let registers = function.code.registers as usize;
+
+        // the stacks can't grow past their capacity, so deep recursion is reported at the overflowing call
+        if self.frames.len() >= FRAMES_MAX || base + registers > STACK_MAX {
+            return Err(VmError::Diagnostic(
+                Diagnostic::error()
+                    .with_message(format!(
+                        "stack overflow while calling `{}` at compile-time",
+                        function.name
+                    ))
+                    .with_label(Label::primary(self.current_span(), "this call overflowed the stack"))
+                    .with_note(format!(
+                        "compile-time code is limited to {} nested calls and {} registers",
+                        FRAMES_MAX, STACK_MAX
+                    )),
+            ));
+        }
 
-        for _ in 0..unsafe { &*function }.code.locals {
-            self.stack.push(Value::default());
+        if let Some(profiler) = &mut self.interp.profiler {
+            profiler.enter_function(function.name);
         }
 
-        self.frames.push(StackFrame::<'vm>::new(function, stack_slot));
+        self.stack.resize(base + registers);
+
+        self.frames.push(StackFrame::new(function, base, dst));
 
         self.frame = self.frames.last_mut() as _;
+
+        Ok(())
     }
 
     #[inline]
-    pub fn frame(&self) -> &StackFrame<'vm> {
+    pub fn frame(&self) -> &StackFrame {
         debug_assert!(!self.frame.is_null());
         unsafe { &*self.frame }
     }
 
+    /// The span of the instruction which is currently executing
+    pub fn current_span(&self) -> Span {
+        let frame = self.frame();
+        frame.func().code.spans[frame.pc - 1]
+    }
+
     #[inline]
-    pub fn frame_mut(&mut self) -> &mut StackFrame<'vm> {
+    pub fn frame_mut(&mut self) -> &mut StackFrame {
         debug_assert!(!self.frame.is_null());
         unsafe { &mut *self.frame }
     }
 
     #[inline]
-    pub fn jmp(&mut self, offset: i32) {
-        // The offset is calculated from the op-code's position.
-        // The current actual offset the op-code's position + the amount of bytes,
-        // The the jmp instruction's operand takes (which is the size of `i32`)
-        const COMPENSATION: usize = std::mem::size_of::<i32>();
+    pub fn register(&self, register: Register) -> &Value {
+        self.stack.get(self.frame().base + register as usize)
+    }
 
-        let reader = &mut self.frame_mut().reader;
-        let cursor = reader.cursor() - 1 - COMPENSATION;
-        let new_cursor = cursor as isize + offset as isize;
+    #[inline]
+    pub fn register_mut(&mut self, register: Register) -> &mut Value {
+        let index = self.frame().base + register as usize;
+        self.stack.get_mut(index)
+    }
 
-        reader.set_cursor(new_cursor as usize);
+    #[inline]
+    pub fn set_register(&mut self, register: Register, value: Value) {
+        self.register_mut(register).overwrite(value);
+    }
+
+    fn collect_args(&self, args: Register, arg_count: u32) -> Vec<Value> {
+        (args..args + arg_count)
+            .map(|register| self.register(register).clone())
+            .collect()
     }
 
     #[allow(unused)]
     pub fn trace(&self, level: TraceLevel) {
         let frame = self.frame();
 
-        bytecode_reader_write_single_inst(&mut frame.reader.clone(), &mut std::io::stdout());
+        print!("{:06}\t{}", frame.pc, frame.func().code.instructions[frame.pc]);
 
         match level {
             TraceLevel::Minimal => {
@@ -576,28 +657,29 @@ impl<'vm> VM<'vm> {
             }
             TraceLevel::Full => {
                 print!("\n\t[");
-                let frame_slot = frame.stack_slot;
-                for (index, value) in self.stack.iter().enumerate() {
+
+                let params = frame.func().ty.params.len();
+                let frame_end = frame.base + frame.func().code.registers as usize;
+
+                for index in frame.base..frame_end {
+                    let value = self.stack.get(index);
+
                     print!(
                         "{}",
-                        if index == frame_slot {
-                            // frame slot
+                        if index < frame.base + params {
+                            // parameter
                             value.to_string().bright_yellow()
-                        } else if index > frame_slot - frame.func().ty.params.len()
-                            && index <= frame_slot + frame.func().code.locals as usize
-                        {
-                            // local value
-                            value.to_string().bright_magenta()
                         } else {
-                            // any other value
+                            // any other register
                             value.to_string().white()
                         }
                     );
 
-                    if index < self.stack.len() - 1 {
+                    if index < frame_end - 1 {
                         print!(", ");
                     }
                 }
+
                 println!("] ({})\n", self.stack.len());
             }
         }
@@ -0,0 +1,550 @@
+use super::{
+    bytecode::{Bytecode, ConstSlot, Inst, JmpTarget, Register},
+    cast::cast_value,
+    value::Value,
+    Constants,
+};
+use crate::types::{InferType, Type};
+use std::collections::{HashMap, HashSet};
+
+/// Runs peephole and constant folding passes over lowered code, before it's executed by the VM.
+/// Each pass can expose more work for the others, so they're repeated until the code stops changing.
+pub fn optimize(code: &mut Bytecode, constants: &mut Constants) {
+    loop {
+        let mut changed = false;
+
+        changed |= fold_constants(code, constants);
+        changed |= thread_jmps(code);
+        changed |= fuse_compare_and_jmp(code);
+        changed |= remove_dead_stores(code);
+        changed |= remove_unreachable_code(code);
+
+        if !changed {
+            break;
+        }
+    }
+}
+
+// Replaces casts and moves of values that are known constants with a single `LoadConst`.
+// Values are only tracked within a basic block.
+fn fold_constants(code: &mut Bytecode, constants: &mut Constants) -> bool {
+    let leaders = block_leaders(code);
+    let escaped = escaped_registers(code);
+
+    let mut known: HashMap<Register, ConstSlot> = HashMap::new();
+    let mut changed = false;
+
+    for (index, inst) in code.instructions.iter_mut().enumerate() {
+        if leaders[index] {
+            known.clear();
+        }
+
+        match *inst {
+            Inst::Move { dst, src } => {
+                if let Some(&slot) = known.get(&src) {
+                    *inst = Inst::LoadConst { dst, slot };
+                    changed = true;
+                }
+            }
+            Inst::Cast { dst, src, ty } => {
+                if let Some(&slot) = known.get(&src) {
+                    let ty = constants[ty as usize].as_type().clone();
+
+                    if is_foldable_cast(&constants[slot as usize], &ty) {
+                        let value = cast_value(constants[slot as usize].clone(), &ty);
+
+                        let slot = constants.len() as ConstSlot;
+                        constants.push(value);
+
+                        *inst = Inst::LoadConst { dst, slot };
+                        changed = true;
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        // any write invalidates what we know about the written registers
+        for_each_written_register(inst, |register| {
+            known.remove(&register);
+        });
+
+        if let Inst::LoadConst { dst, slot } = *inst {
+            if !escaped.contains(&dst) && is_scalar(&constants[slot as usize]) {
+                known.insert(dst, slot);
+            }
+        }
+    }
+
+    changed
+}
+
+// Redirects jmps that land on other jmps to their final target,
+// turns jmps to a `Return` into a `Return`, and removes jmps to the next instruction.
+fn thread_jmps(code: &mut Bytecode) -> bool {
+    let len = code.len();
+    let mut changed = false;
+
+    for index in 0..len {
+        let mut inst = code.instructions[index];
+
+        if let Some(target) = inst.jmp_target_mut() {
+            let mut final_target = *target;
+
+            // bounded, in case jmps form a cycle
+            for _ in 0..len {
+                match code.instructions.get(final_target as usize) {
+                    Some(Inst::Jmp { target: next }) if *next != final_target => final_target = *next,
+                    _ => break,
+                }
+            }
+
+            if final_target != *target {
+                *target = final_target;
+                changed = true;
+            }
+        }
+
+        if let Inst::Jmp { target } = inst {
+            if let Some(Inst::Return { src }) = code.instructions.get(target as usize) {
+                inst = Inst::Return { src: *src };
+                changed = true;
+            }
+        }
+
+        code.instructions[index] = inst;
+    }
+
+    let removed: Vec<bool> = code
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(index, inst)| inst.jmp_target() == Some(index as JmpTarget + 1))
+        .collect();
+
+    changed | compact(code, &removed)
+}
+
+// Fuses a comparison followed by a `Jmpf` on its result into a single compare-and-branch instruction
+fn fuse_compare_and_jmp(code: &mut Bytecode) -> bool {
+    let live_out = liveness(code);
+    let targets = jmp_targets(code);
+    let escaped = escaped_registers(code);
+
+    let mut removed = vec![false; code.len()];
+
+    for index in 1..code.len() {
+        let (cond, target) = match code.instructions[index] {
+            Inst::Jmpf { cond, target } => (cond, target),
+            _ => continue,
+        };
+
+        // the comparison's result must not be observed by anyone else
+        if targets.contains(&index) || escaped.contains(&cond) || live_out[index].contains(cond) {
+            continue;
+        }
+
+        let fused = match code.instructions[index - 1] {
+            Inst::Eq { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfEq { lhs, rhs, ty, target },
+            Inst::Ne { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfNe { lhs, rhs, ty, target },
+            Inst::Lt { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfLt { lhs, rhs, ty, target },
+            Inst::Le { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfLe { lhs, rhs, ty, target },
+            Inst::Gt { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfGt { lhs, rhs, ty, target },
+            Inst::Ge { dst, lhs, rhs, ty } if dst == cond => Inst::JmpfGe { lhs, rhs, ty, target },
+            _ => continue,
+        };
+
+        code.instructions[index - 1] = fused;
+        removed[index] = true;
+    }
+
+    compact(code, &removed)
+}
+
+// Removes side-effect free instructions whose result is never read
+fn remove_dead_stores(code: &mut Bytecode) -> bool {
+    let live_out = liveness(code);
+    let escaped = escaped_registers(code);
+
+    let removed: Vec<bool> = code
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(index, inst)| match (inst, pure_dst(inst)) {
+            (Inst::Move { dst, src }, _) if dst == src => true,
+            (_, Some(dst)) => !escaped.contains(&dst) && !live_out[index].contains(dst),
+            _ => false,
+        })
+        .collect();
+
+    compact(code, &removed)
+}
+
+fn remove_unreachable_code(code: &mut Bytecode) -> bool {
+    let mut reachable = vec![false; code.len()];
+    let mut worklist = vec![0];
+
+    while let Some(index) = worklist.pop() {
+        if index >= code.len() || reachable[index] {
+            continue;
+        }
+
+        reachable[index] = true;
+        worklist.extend(successors(code, index));
+    }
+
+    let removed: Vec<bool> = reachable.iter().map(|reachable| !reachable).collect();
+
+    compact(code, &removed)
+}
+
+// Removes the given instructions, relocating jmp targets.
+// Jmps to a removed instruction land on the instruction that follows it.
+fn compact(code: &mut Bytecode, removed: &[bool]) -> bool {
+    if !removed.iter().any(|removed| *removed) {
+        return false;
+    }
+
+    let mut new_indices = Vec::with_capacity(removed.len() + 1);
+    let mut kept = 0;
+
+    for removed in removed {
+        new_indices.push(kept);
+
+        if !removed {
+            kept += 1;
+        }
+    }
+
+    new_indices.push(kept);
+
+    let instructions = std::mem::take(&mut code.instructions);
+
+    code.instructions = instructions
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !**removed)
+        .map(|(mut inst, _)| {
+            if let Some(target) = inst.jmp_target_mut() {
+                *target = new_indices[*target as usize];
+            }
+
+            inst
+        })
+        .collect();
+
+    let spans = std::mem::take(&mut code.spans);
+
+    code.spans = spans
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !**removed)
+        .map(|(span, _)| span)
+        .collect();
+
+    for local in code.locals.iter_mut() {
+        local.start = new_indices[local.start];
+
+        if local.end != usize::MAX {
+            local.end = new_indices[local.end];
+        }
+    }
+
+    true
+}
+
+fn successors(code: &Bytecode, index: usize) -> Vec<usize> {
+    match code.instructions[index] {
+        Inst::Jmp { target } => vec![target as usize],
+        Inst::Return { .. } => vec![],
+        inst => match inst.jmp_target() {
+            Some(target) => vec![index + 1, target as usize],
+            None => vec![index + 1],
+        },
+    }
+}
+
+fn jmp_targets(code: &Bytecode) -> HashSet<usize> {
+    code.instructions
+        .iter()
+        .filter_map(|inst| inst.jmp_target().map(|target| target as usize))
+        .collect()
+}
+
+// The first instruction of every basic block
+fn block_leaders(code: &Bytecode) -> Vec<bool> {
+    let mut leaders = vec![false; code.len() + 1];
+
+    leaders[0] = true;
+
+    for (index, inst) in code.instructions.iter().enumerate() {
+        if let Some(target) = inst.jmp_target() {
+            leaders[target as usize] = true;
+            leaders[index + 1] = true;
+        }
+    }
+
+    leaders
+}
+
+// Registers that may be read or written through a pointer, which we can't track
+fn escaped_registers(code: &Bytecode) -> HashSet<Register> {
+    code.instructions
+        .iter()
+        .filter_map(|inst| match *inst {
+            Inst::RegisterPtr { src, .. } | Inst::Offset { src, .. } | Inst::ConstIndexPtr { src, .. } => Some(src),
+            _ => None,
+        })
+        .collect()
+}
+
+// Computes the registers that are live after each instruction
+fn liveness(code: &Bytecode) -> Vec<RegisterSet> {
+    let len = code.len();
+    let registers = code.registers as usize;
+
+    let mut live_in = vec![RegisterSet::new(registers); len];
+    let mut live_out = vec![RegisterSet::new(registers); len];
+
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for index in (0..len).rev() {
+            let mut out = RegisterSet::new(registers);
+
+            for successor in successors(code, index) {
+                if successor < len {
+                    out.union_with(&live_in[successor]);
+                }
+            }
+
+            let inst = &code.instructions[index];
+            let mut live = out.clone();
+
+            if let Some(dst) = dst(inst) {
+                live.remove(dst);
+            }
+
+            for_each_read_register(inst, |register| live.insert(register));
+
+            if live != live_in[index] {
+                live_in[index] = live;
+                changed = true;
+            }
+
+            live_out[index] = out;
+        }
+    }
+
+    live_out
+}
+
+// The register an instruction fully overwrites
+fn dst(inst: &Inst) -> Option<Register> {
+    match *inst {
+        Inst::LoadConst { dst, .. }
+        | Inst::LoadGlobal { dst, .. }
+        | Inst::LoadGlobalPtr { dst, .. }
+        | Inst::Move { dst, .. }
+        | Inst::RegisterPtr { dst, .. }
+        | Inst::Add { dst, .. }
+        | Inst::Sub { dst, .. }
+        | Inst::Mul { dst, .. }
+        | Inst::Div { dst, .. }
+        | Inst::Rem { dst, .. }
+        | Inst::Shl { dst, .. }
+        | Inst::Shr { dst, .. }
+        | Inst::BitAnd { dst, .. }
+        | Inst::BitOr { dst, .. }
+        | Inst::BitXor { dst, .. }
+        | Inst::Eq { dst, .. }
+        | Inst::Ne { dst, .. }
+        | Inst::Lt { dst, .. }
+        | Inst::Le { dst, .. }
+        | Inst::Gt { dst, .. }
+        | Inst::Ge { dst, .. }
+        | Inst::Neg { dst, .. }
+        | Inst::Not { dst, .. }
+        | Inst::Deref { dst, .. }
+        | Inst::Call { dst, .. }
+        | Inst::Offset { dst, .. }
+        | Inst::ConstIndex { dst, .. }
+        | Inst::ConstIndexPtr { dst, .. }
+        | Inst::Cast { dst, .. }
+        | Inst::BufferAlloc { dst, .. } => Some(dst),
+        _ => None,
+    }
+}
+
+// The register written by an instruction that has no side effects, and can't fail at runtime.
+// Arithmetic may fail on overflow.
+fn pure_dst(inst: &Inst) -> Option<Register> {
+    match inst {
+        Inst::Add { .. }
+        | Inst::Sub { .. }
+        | Inst::Mul { .. }
+        | Inst::Div { .. }
+        | Inst::Rem { .. }
+        | Inst::Deref { .. }
+        | Inst::Call { .. } => None,
+        inst => dst(inst),
+    }
+}
+
+fn for_each_written_register(inst: &Inst, mut f: impl FnMut(Register)) {
+    if let Some(dst) = dst(inst) {
+        f(dst);
+    }
+
+    match *inst {
+        Inst::BufferPut { buf, .. } | Inst::BufferFill { buf, .. } => f(buf),
+        // arguments are moved out of the caller's registers
+        Inst::Call { args, arg_count, .. } => (args..args + arg_count).for_each(f),
+        _ => (),
+    }
+}
+
+fn for_each_read_register(inst: &Inst, mut f: impl FnMut(Register)) {
+    match *inst {
+        Inst::LoadConst { .. }
+        | Inst::LoadGlobal { .. }
+        | Inst::LoadGlobalPtr { .. }
+        | Inst::Jmp { .. }
+        | Inst::BufferAlloc { .. } => (),
+        Inst::StoreGlobal { src, .. }
+        | Inst::Move { src, .. }
+        | Inst::RegisterPtr { src, .. }
+        | Inst::Neg { src, .. }
+        | Inst::Not { src, .. }
+        | Inst::Deref { src, .. }
+        | Inst::Return { src }
+        | Inst::ConstIndex { src, .. }
+        | Inst::ConstIndexPtr { src, .. }
+        | Inst::Cast { src, .. } => f(src),
+        Inst::Add { lhs, rhs, .. }
+        | Inst::Sub { lhs, rhs, .. }
+        | Inst::Mul { lhs, rhs, .. }
+        | Inst::Div { lhs, rhs, .. }
+        | Inst::Rem { lhs, rhs, .. }
+        | Inst::Shl { lhs, rhs, .. }
+        | Inst::Shr { lhs, rhs, .. }
+        | Inst::BitAnd { lhs, rhs, .. }
+        | Inst::BitOr { lhs, rhs, .. }
+        | Inst::BitXor { lhs, rhs, .. }
+        | Inst::Eq { lhs, rhs, .. }
+        | Inst::Ne { lhs, rhs, .. }
+        | Inst::Lt { lhs, rhs, .. }
+        | Inst::Le { lhs, rhs, .. }
+        | Inst::Gt { lhs, rhs, .. }
+        | Inst::Ge { lhs, rhs, .. }
+        | Inst::JmpfEq { lhs, rhs, .. }
+        | Inst::JmpfNe { lhs, rhs, .. }
+        | Inst::JmpfLt { lhs, rhs, .. }
+        | Inst::JmpfLe { lhs, rhs, .. }
+        | Inst::JmpfGt { lhs, rhs, .. }
+        | Inst::JmpfGe { lhs, rhs, .. } => {
+            f(lhs);
+            f(rhs);
+        }
+        Inst::Jmpf { cond, .. } => f(cond),
+        Inst::Call {
+            callee,
+            args,
+            arg_count,
+            ..
+        } => {
+            f(callee);
+            (args..args + arg_count).for_each(f);
+        }
+        Inst::Offset { src, offset, .. } => {
+            f(src);
+            f(offset);
+        }
+        Inst::Assign { ptr, src } => {
+            f(ptr);
+            f(src);
+        }
+        Inst::CheckIndexOutOfBounds { index, len } => {
+            f(index);
+            f(len);
+        }
+        Inst::CheckSliceEndBeforeStart { low, high } => {
+            f(low);
+            f(high);
+        }
+        Inst::CheckSliceRangeOutOfBounds { low, high, len } => {
+            f(low);
+            f(high);
+            f(len);
+        }
+        // buffers are only partially written, so their previous value is read
+        Inst::BufferPut { buf, src, .. } | Inst::BufferFill { buf, src, .. } => {
+            f(buf);
+            f(src);
+        }
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::I8(_)
+            | Value::I16(_)
+            | Value::I32(_)
+            | Value::I64(_)
+            | Value::Int(_)
+            | Value::U8(_)
+            | Value::U16(_)
+            | Value::U32(_)
+            | Value::U64(_)
+            | Value::Uint(_)
+            | Value::F32(_)
+            | Value::F64(_)
+            | Value::Bool(_)
+    )
+}
+
+fn is_foldable_cast(value: &Value, ty: &Type) -> bool {
+    match ty {
+        Type::Int(_) | Type::Uint(_) | Type::Infer(_, InferType::AnyInt) => is_scalar(value),
+        // bools can't be cast to floats
+        Type::Float(_) | Type::Infer(_, InferType::AnyFloat) => is_scalar(value) && !matches!(value, Value::Bool(_)),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RegisterSet {
+    bits: Vec<u64>,
+}
+
+impl RegisterSet {
+    fn new(registers: usize) -> Self {
+        Self {
+            bits: vec![0; (registers + 63) / 64],
+        }
+    }
+
+    fn contains(&self, register: Register) -> bool {
+        let register = register as usize;
+        self.bits[register / 64] & (1 << (register % 64)) != 0
+    }
+
+    fn insert(&mut self, register: Register) {
+        let register = register as usize;
+        self.bits[register / 64] |= 1 << (register % 64);
+    }
+
+    fn remove(&mut self, register: Register) {
+        let register = register as usize;
+        self.bits[register / 64] &= !(1 << (register % 64));
+    }
+
+    fn union_with(&mut self, other: &RegisterSet) {
+        for (bits, other_bits) in self.bits.iter_mut().zip(&other.bits) {
+            *bits |= other_bits;
+        }
+    }
+}
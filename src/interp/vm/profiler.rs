@@ -0,0 +1,230 @@
+use colored::Colorize;
+use num_format::{Locale, ToFormattedString};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+use ustr::Ustr;
+
+// The maximum number of functions printed in the report
+const REPORT_FUNCTIONS_MAX: usize = 25;
+
+/// Counts the instructions, calls and wall time spent in compile-time code,
+/// per function and per comptime block.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    functions: HashMap<Ustr, ProfileEntry>,
+    blocks: Vec<(String, ProfileEntry)>,
+
+    // Call stacks, joined by `;`, mapped to the number of instructions executed by their top frame.
+    // This is the folded stack format that flamegraph tools accept.
+    folded_stacks: HashMap<String, u64>,
+
+    // Mirrors the VM's frames while it's running
+    frames: Vec<ProfiledFrame>,
+    block: Option<ProfiledBlock>,
+
+    total_instructions: u64,
+    total_calls: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProfileEntry {
+    pub calls: u64,
+    pub instructions: u64,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+#[derive(Debug)]
+struct ProfiledFrame {
+    name: Ustr,
+    start: Instant,
+    instructions: u64,
+    child_time: Duration,
+}
+
+#[derive(Debug)]
+struct ProfiledBlock {
+    name: String,
+    start: Instant,
+    instructions: u64,
+    calls: u64,
+}
+
+impl Profiler {
+    pub fn begin_block(&mut self, name: String) {
+        self.block = Some(ProfiledBlock {
+            name,
+            start: Instant::now(),
+            instructions: self.total_instructions,
+            calls: self.total_calls,
+        });
+    }
+
+    pub fn end_block(&mut self) {
+        if let Some(block) = self.block.take() {
+            let elapsed = block.start.elapsed();
+
+            self.blocks.push((
+                block.name,
+                ProfileEntry {
+                    calls: self.total_calls - block.calls,
+                    instructions: self.total_instructions - block.instructions,
+                    self_time: elapsed,
+                    total_time: elapsed,
+                },
+            ));
+        }
+    }
+
+    #[inline]
+    pub fn count_instruction(&mut self) {
+        self.total_instructions += 1;
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.instructions += 1;
+        }
+    }
+
+    pub fn enter_function(&mut self, name: Ustr) {
+        self.total_calls += 1;
+        self.functions.entry(name).or_default().calls += 1;
+
+        self.frames.push(ProfiledFrame {
+            name,
+            start: Instant::now(),
+            instructions: 0,
+            child_time: Duration::ZERO,
+        });
+    }
+
+    pub fn leave_function(&mut self) {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let elapsed = frame.start.elapsed();
+
+        if frame.instructions > 0 {
+            let stack = self.folded_stack(frame.name);
+            *self.folded_stacks.entry(stack).or_default() += frame.instructions;
+        }
+
+        // recursive calls are already accounted for in the outermost call's total time
+        let is_recursive = self.frames.iter().any(|f| f.name == frame.name);
+
+        if let Some(parent) = self.frames.last_mut() {
+            parent.child_time += elapsed;
+        }
+
+        let entry = self.functions.entry(frame.name).or_default();
+
+        entry.instructions += frame.instructions;
+        entry.self_time += elapsed.saturating_sub(frame.child_time);
+
+        if !is_recursive {
+            entry.total_time += elapsed;
+        }
+    }
+
+    fn folded_stack(&self, top: Ustr) -> String {
+        let mut stack = String::new();
+
+        if let Some(block) = &self.block {
+            stack.push_str(&block.name);
+            stack.push(';');
+        }
+
+        for frame in self.frames.iter() {
+            stack.push_str(frame.name.as_str());
+            stack.push(';');
+        }
+
+        stack.push_str(top.as_str());
+
+        stack
+    }
+
+    pub fn print_report(&self) {
+        let mut blocks = self.blocks.iter().collect::<Vec<_>>();
+        blocks.sort_by(|(_, a), (_, b)| b.total_time.cmp(&a.total_time));
+
+        let mut functions = self.functions.iter().collect::<Vec<_>>();
+        functions.sort_by(|(_, a), (_, b)| b.self_time.cmp(&a.self_time));
+
+        println!("------------------------");
+        println!(
+            "{}\t{} instructions, {} calls",
+            "comptime:".cyan().bold(),
+            self.total_instructions.to_formatted_string(&Locale::en),
+            self.total_calls.to_formatted_string(&Locale::en)
+        );
+
+        println!();
+        println!("{}", "blocks (by total time)".bold());
+        print_header("block");
+
+        for (name, entry) in blocks {
+            print_entry(name, entry);
+        }
+
+        println!();
+        println!("{}", "functions (by self time)".bold());
+        print_header("function");
+
+        for (name, entry) in functions.iter().take(REPORT_FUNCTIONS_MAX) {
+            print_entry(name, entry);
+        }
+
+        if functions.len() > REPORT_FUNCTIONS_MAX {
+            println!(
+                "{}",
+                format!("... and {} more", functions.len() - REPORT_FUNCTIONS_MAX).bright_black()
+            );
+        }
+    }
+
+    pub fn write_folded_stacks(&self, path: &Path) -> io::Result<()> {
+        let mut stacks = self.folded_stacks.iter().collect::<Vec<_>>();
+        stacks.sort();
+
+        let mut file = BufWriter::new(File::create(path)?);
+
+        for (stack, count) in stacks {
+            writeln!(file, "{} {}", stack, count)?;
+        }
+
+        file.flush()
+    }
+}
+
+fn print_header(name: &str) {
+    println!(
+        "{}",
+        format!(
+            "{:>12} {:>10} {:>12} {:>12}  {}",
+            "instructions", "calls", "self", "total", name
+        )
+        .bright_black()
+    );
+}
+
+fn print_entry(name: &str, entry: &ProfileEntry) {
+    println!(
+        "{:>12} {:>10} {:>12} {:>12}  {}",
+        entry.instructions.to_formatted_string(&Locale::en),
+        entry.calls.to_formatted_string(&Locale::en),
+        format_duration(entry.self_time),
+        format_duration(entry.total_time),
+        name
+    );
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
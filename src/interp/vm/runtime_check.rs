@@ -0,0 +1,98 @@
+use super::{bytecode::Register, value::Value, VmError, VmResult, VM};
+
+// These checks mirror the ones emitted by the llvm backend in `backend/llvm/runtime_check.rs`,
+// so that a program fails the same way at compile-time and at runtime.
+// Unlike the backend, checks that guard the compiler's own memory are performed in release builds too.
+
+impl<'vm> VM<'vm> {
+    /// Returns a panic at the location of the current instruction, which stops the VM
+    pub(super) fn panic(&self, message: &str) -> VmError {
+        VmError::Panic {
+            message: message.to_string(),
+            span: self.current_span(),
+        }
+    }
+
+    // Overflow wraps when runtime checks are disabled, like it does at runtime
+    #[inline]
+    pub(super) fn overflow_checks_enabled(&self) -> bool {
+        self.workspace.build_options.runtime_checks.overflow && self.frame().func().runtime_checks
+    }
+
+    #[inline]
+    pub(super) fn check_division_by_zero(&self, divisor: Register) -> VmResult<()> {
+        let is_zero = match self.register(divisor) {
+            Value::I8(v) => *v == 0,
+            Value::I16(v) => *v == 0,
+            Value::I32(v) => *v == 0,
+            Value::I64(v) => *v == 0,
+            Value::Int(v) => *v == 0,
+            Value::U8(v) => *v == 0,
+            Value::U16(v) => *v == 0,
+            Value::U32(v) => *v == 0,
+            Value::U64(v) => *v == 0,
+            Value::Uint(v) => *v == 0,
+            _ => false,
+        };
+
+        if is_zero {
+            return Err(self.panic("attempt to divide by zero"));
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(super) fn check_null_pointer_deref(&self, ptr: Register) -> VmResult<()> {
+        if let Value::Pointer(ptr) = self.register(ptr) {
+            if ptr.is_null() {
+                return Err(self.panic("attempt to dereference a null pointer"));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(super) fn check_index_out_of_bounds(&self, index: Register, len: Register) -> VmResult<()> {
+        let index = self.register(index).as_uint();
+        let len = self.register(len).as_uint();
+
+        if index >= len {
+            return Err(self.panic(&format!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            )));
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(super) fn check_slice_end_before_start(&self, low: Register, high: Register) -> VmResult<()> {
+        let low = self.register(low).as_uint();
+        let high = self.register(high).as_uint();
+
+        if high < low {
+            return Err(self.panic(&format!("slice index starts at {} but ends at {}", low, high)));
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(super) fn check_slice_range_out_of_bounds(&self, low: Register, high: Register, len: Register) -> VmResult<()> {
+        let low = self.register(low).as_uint();
+        let high = self.register(high).as_uint();
+        let len = self.register(len).as_uint();
+
+        if high > len {
+            return Err(self.panic(&format!(
+                "slice range {}..{} is out of range for slice of length {}",
+                low, high, len
+            )));
+        }
+
+        Ok(())
+    }
+}
@@ -11,7 +11,8 @@ impl<T: ToString, const CAPACITY: usize> Stack<T, CAPACITY> {
     }
 
     pub fn push(&mut self, value: T) {
-        debug_assert!(self.inner.len() <= self.inner.capacity(), "stack overflow");
+        // Note: growing past the capacity would move the stack, invalidating pointers into it
+        assert!(self.inner.len() < CAPACITY, "stack overflow");
         self.inner.push(value);
     }
 
@@ -20,17 +21,6 @@ impl<T: ToString, const CAPACITY: usize> Stack<T, CAPACITY> {
         self.inner.pop().unwrap()
     }
 
-    #[inline]
-    pub fn peek(&self, offset: usize) -> &T {
-        &self.inner[self.len() - 1 - offset]
-    }
-
-    #[inline]
-    pub fn peek_mut(&mut self, offset: usize) -> &mut T {
-        let len = self.len();
-        &mut self.inner[len - 1 - offset]
-    }
-
     #[allow(unused)]
     #[inline]
     pub fn last(&self) -> &T {
@@ -42,11 +32,6 @@ impl<T: ToString, const CAPACITY: usize> Stack<T, CAPACITY> {
         self.inner.last_mut().unwrap()
     }
 
-    #[inline]
-    pub fn swap(&mut self, a: usize, b: usize) {
-        self.inner.swap(a, b)
-    }
-
     #[inline]
     pub fn get(&self, index: usize) -> &T {
         &self.inner[index]
@@ -67,6 +52,15 @@ impl<T: ToString, const CAPACITY: usize> Stack<T, CAPACITY> {
         self.inner.truncate(len)
     }
 
+    #[inline]
+    pub fn resize(&mut self, len: usize)
+    where
+        T: Default,
+    {
+        assert!(len <= CAPACITY, "stack overflow");
+        self.inner.resize_with(len, T::default)
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.inner.len()
@@ -77,8 +71,7 @@ impl<T: ToString, const CAPACITY: usize> Stack<T, CAPACITY> {
         self.inner.is_empty()
     }
 
-    #[inline]
-    pub fn iter(&self) -> std::slice::Iter<T> {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
         self.inner.iter()
     }
 }
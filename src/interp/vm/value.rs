@@ -1,6 +1,6 @@
 use super::{
     super::ffi::RawPointer,
-    super::{IS_64BIT, WORD_SIZE},
+    super::{decode_ptr, is_64bit, word_size},
     byte_seq::{ByteSeq, GetValue, PutValue},
     bytecode::Bytecode,
 };
@@ -13,20 +13,18 @@ use crate::{
     infer::type_ctx::TypeCtx,
     interp::interp::Interp,
     span::Span,
-    types::{
-        align_of::AlignOf, offset_of::OffsetOf, size_of::SizeOf, FloatType, FunctionType, InferType, IntType, Type,
-        UintType,
-    },
+    types::{offset_of::OffsetOf, size_of::SizeOf, FloatType, FunctionType, InferType, IntType, Type, UintType},
 };
-use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{NativeEndian, ReadBytesExt};
 use indexmap::IndexMap;
 use paste::paste;
-use std::{fmt::Display, mem, slice, str};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, slice};
 use ustr::{ustr, Ustr};
 
 macro_rules! impl_value {
     ($($variant:ident($ty:ty)) , + $(,)?) => {
-        #[derive(PartialEq, Debug, Clone, Copy)]
+        #[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
         pub enum ValueKind {
             $(
                 $variant
@@ -114,128 +112,6 @@ macro_rules! impl_value {
                 )+
             }
         }
-
-        #[derive(Debug, Clone)]
-        pub enum Pointer {
-            $(
-                $variant(*mut $ty)
-            ),+
-        }
-
-        impl From<&mut Value> for Pointer {
-            fn from(value: &mut Value) -> Self {
-                match value {
-                    $(
-                        Value::$variant(v) => Pointer::$variant(v as _)
-                    ),+
-                }
-            }
-        }
-
-        #[allow(unused)]
-        impl Pointer {
-            pub fn kind(&self) -> ValueKind {
-                match self {
-                    $(
-                        Self::$variant(_) => ValueKind::$variant
-                    ),+
-                }
-            }
-
-            pub unsafe fn as_raw(&mut self) -> *mut RawPointer {
-                match self {
-                    $(
-                        Self::$variant(ref mut v) => mem::transmute::<&mut *mut _, *mut RawPointer>(v)
-                    ),+
-                }
-            }
-
-            pub fn as_inner_raw(&self) -> RawPointer {
-                match self {
-                    $(
-                        Self::$variant(v) => *v as RawPointer
-                    ),+
-                }
-            }
-
-            pub unsafe fn offset(&self, offset: isize) -> Self {
-                match self {
-                    $(
-                        Self::$variant(v) => Self::$variant((*v).offset(offset))
-                    ),+
-                }
-            }
-
-            pub fn is_null(&self) -> bool {
-                match self {
-                    $(
-                        Self::$variant(v) => (*v).is_null()
-                    ),+
-                }
-            }
-
-            pub unsafe fn deref_value(&self) -> Value {
-                match self {
-                    $(
-                        Self::$variant(v) => Value::$variant((**v).clone())
-                    ),+
-                }
-            }
-
-            pub fn from_kind_and_ptr(kind: ValueKind, ptr: RawPointer) -> Self {
-                match kind {
-                    $(
-                        ValueKind::$variant => Pointer::$variant(ptr as _)
-                    ),+
-                }
-            }
-
-            pub unsafe fn print(&self) {
-                match self {
-                    $(
-                        Self::$variant(v) => println!("{:?}", **v)
-                    ),+
-                }
-            }
-
-            paste! {
-                $(
-                    pub fn [<is_ $variant:snake>](&self) -> bool {
-                        match self {
-                            Self::$variant(_) => true,
-                            _ => false
-                        }
-                    }
-                )+
-
-                $(
-                    pub fn [<into_ $variant:snake>](self) -> *mut $ty {
-                        match self {
-                            Self::$variant(v) => v,
-                            _ => panic!("got {}, expected {}", self.to_string(), stringify!($variant))
-                        }
-                    }
-                )+
-
-                $(
-                    pub fn [<as_ $variant:snake>](&self) -> &*mut $ty {
-                        match self {
-                            Self::$variant(v) => v,
-                            _ => panic!("got {}, expected {}", self.to_string(), stringify!($variant))
-                        }
-                    }
-                )+
-
-                $(
-                    pub fn [<as_ $variant:snake _mut>](&mut self) -> &mut *mut $ty {
-                        match self {
-                            Self::$variant(v) => v,
-                            _ => panic!("got {}, expected {}", self.to_string(), stringify!($variant))
-                        }
-                    }
-                )+
-            }
-        }
     };
 }
 
@@ -273,14 +149,25 @@ pub struct Buffer {
     pub ty: Type,
 }
 
+/// A pointer to memory which is laid out exactly like the target lays out `ty`, the pointee's type.
+/// `raw` is a real address, so pointers can be passed to foreign code, and memory written by it can be read back.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Pointer {
+    // Note: this must be the first field, so that a pointer to a register holding a pointer
+    // points to its address, just like a pointer to a pointer in memory does
+    pub raw: RawPointer,
+    pub ty: Type,
+}
+
 impl Buffer {
     pub fn as_slice<T>(&self) -> &[T] {
-        let ptr = self.bytes.offset(0).read_uint::<NativeEndian>(WORD_SIZE).unwrap() as *mut T;
+        let ptr = decode_ptr::<T>(self.bytes.offset(0).read_uint::<NativeEndian>(word_size()).unwrap());
 
         let len = self
             .bytes
-            .offset(self.ty.offset_of(1, WORD_SIZE))
-            .read_uint::<NativeEndian>(WORD_SIZE)
+            .offset(self.ty.offset_of(1, word_size()))
+            .read_uint::<NativeEndian>(word_size())
             .unwrap() as usize;
 
         unsafe { std::slice::from_raw_parts(ptr, len) }
@@ -302,16 +189,16 @@ impl Buffer {
     #[allow(unused)]
     pub fn from_ustr(s: Ustr) -> Self {
         let ty = Type::str_pointer();
-        let size = ty.size_of(WORD_SIZE);
+        let size = ty.size_of(word_size());
 
         let mut bytes = ByteSeq::new(size);
 
         bytes
             .offset_mut(0)
-            .put_value(&Value::Pointer(Pointer::U8(s.as_char_ptr() as *mut u8)));
+            .put_value(&Value::Pointer(Pointer::new(s.as_char_ptr() as RawPointer, Type::u8())));
 
         bytes
-            .offset_mut(ty.offset_of(1, WORD_SIZE))
+            .offset_mut(ty.offset_of(1, word_size()))
             .put_value(&Value::Uint(s.len()));
 
         Buffer { bytes, ty }
@@ -320,16 +207,16 @@ impl Buffer {
     #[allow(unused)]
     pub fn from_str(s: &mut str) -> Self {
         let ty = Type::str_pointer();
-        let size = ty.size_of(WORD_SIZE);
+        let size = ty.size_of(word_size());
 
         let mut bytes = ByteSeq::new(size);
 
         bytes
             .offset_mut(0)
-            .put_value(&Value::Pointer(Pointer::U8(s.as_mut_ptr())));
+            .put_value(&Value::Pointer(Pointer::new(s.as_mut_ptr() as RawPointer, Type::u8())));
 
         bytes
-            .offset_mut(ty.offset_of(1, WORD_SIZE))
+            .offset_mut(ty.offset_of(1, word_size()))
             .put_value(&Value::Uint(s.len()));
 
         Buffer { bytes, ty }
@@ -337,28 +224,28 @@ impl Buffer {
 
     pub fn from_str_bytes(s: &mut [u8]) -> Self {
         let ty = Type::str_pointer();
-        let size = ty.size_of(WORD_SIZE);
+        let size = ty.size_of(word_size());
 
         let mut bytes = ByteSeq::new(size);
 
         bytes
             .offset_mut(0)
-            .put_value(&Value::Pointer(Pointer::U8(s.as_mut_ptr())));
+            .put_value(&Value::Pointer(Pointer::new(s.as_mut_ptr() as RawPointer, Type::u8())));
 
         bytes
-            .offset_mut(ty.offset_of(1, WORD_SIZE))
+            .offset_mut(ty.offset_of(1, word_size()))
             .put_value(&Value::Uint(s.len()));
 
         Buffer { bytes, ty }
     }
 
     pub fn from_values<I: IntoIterator<Item = Value>>(values: I, ty: Type) -> Self {
-        let size = ty.size_of(WORD_SIZE);
+        let size = ty.size_of(word_size());
 
         let mut bytes = ByteSeq::new(size);
 
         for (index, value) in values.into_iter().enumerate() {
-            let offset = ty.offset_of(index, WORD_SIZE);
+            let offset = ty.offset_of(index, word_size());
             bytes.offset_mut(offset).put_value(&value);
         }
 
@@ -403,23 +290,47 @@ impl Buffer {
     }
 
     pub fn get_value_at_index(&self, index: usize) -> Value {
-        let offset = self.ty.offset_of(index, WORD_SIZE);
+        let offset = self.ty.offset_of(index, word_size());
+        self.bytes.offset(offset).get_value(&member_type(&self.ty, index))
+    }
 
-        match &self.ty {
-            Type::Unit => panic!("{}", index),
-            Type::Struct(struct_type) => self.bytes.offset(offset).get_value(&struct_type.fields[index].ty),
-            Type::Tuple(elements) => self.bytes.offset(offset).get_value(&elements[index]),
-            Type::Array(ty, _) => self.bytes.offset(offset).get_value(ty),
-            Type::Pointer(inner, _) => match inner.as_ref() {
-                Type::Slice(ty) | Type::Str(ty) => match index {
-                    0 => self.bytes.offset(offset).get_value(&Type::Pointer(ty.clone(), false)),
-                    1 => self.bytes.offset(offset).get_value(&Type::uint()),
-                    _ => panic!("{}", index),
-                },
-                _ => panic!("{:?}", &self.ty),
+    /// Returns a pointer to the member at `index`, which points into this buffer's bytes
+    pub fn member_ptr(&mut self, index: usize) -> Pointer {
+        let offset = self.ty.offset_of(index, word_size());
+        let raw = self.bytes.offset_mut(offset).as_mut_ptr() as RawPointer;
+        Pointer::new(raw, member_type(&self.ty, index))
+    }
+
+    /// Returns a pointer `offset` bytes into this buffer. Arrays decay to a pointer to their elements.
+    pub fn element_ptr(&mut self, offset: usize) -> Pointer {
+        let raw = self.bytes.offset_mut(offset).as_mut_ptr() as RawPointer;
+        Pointer::new(raw, decayed_type(&self.ty).clone())
+    }
+}
+
+// The type of the member at `index` in an aggregate of type `ty`
+fn member_type(ty: &Type, index: usize) -> Type {
+    match ty {
+        Type::Struct(struct_type) => struct_type.fields[index].ty.clone(),
+        Type::Tuple(elements) => elements[index].clone(),
+        Type::Array(inner, _) => inner.as_ref().clone(),
+        Type::Pointer(inner, is_mutable) => match inner.as_ref() {
+            Type::Slice(inner) | Type::Str(inner) => match index {
+                0 => Type::Pointer(inner.clone(), *is_mutable),
+                1 => Type::uint(),
+                _ => panic!("{}", index),
             },
-            _ => panic!("{:?}", &self.ty),
-        }
+            _ => panic!("{:?}", ty),
+        },
+        _ => panic!("{:?}", ty),
+    }
+}
+
+// Pointers to arrays are offset by their elements, like they are at runtime
+fn decayed_type(ty: &Type) -> &Type {
+    match ty {
+        Type::Array(inner, _) => inner,
+        ty => ty,
     }
 }
 
@@ -429,6 +340,8 @@ pub struct Function {
     pub name: Ustr,
     pub ty: FunctionType,
     pub code: Bytecode,
+    // false for functions annotated with `no_runtime_checks`
+    pub runtime_checks: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -452,19 +365,25 @@ pub struct ExternVariable {
     pub ty: Type,
 }
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum IntrinsicFunction {
     StartWorkspace,
+    InjectBindings,
+    SymbolTable,
 }
 
 impl From<hir::Intrinsic> for IntrinsicFunction {
     fn from(intrinsic: hir::Intrinsic) -> Self {
         match intrinsic {
             hir::Intrinsic::StartWorkspace => IntrinsicFunction::StartWorkspace,
+            hir::Intrinsic::InjectBindings => IntrinsicFunction::InjectBindings,
+            hir::Intrinsic::SymbolTable => IntrinsicFunction::SymbolTable,
             hir::Intrinsic::Location
             | hir::Intrinsic::CallerLocation
             | hir::Intrinsic::CompilerError
-            | hir::Intrinsic::CompilerWarning => panic!(
+            | hir::Intrinsic::CompilerWarning
+            | hir::Intrinsic::IsDefined
+            | hir::Intrinsic::GetDefine => panic!(
                 "intrinsic function '{}' should have been evaluated at compile-time",
                 intrinsic
             ),
@@ -486,6 +405,8 @@ impl Display for IntrinsicFunction {
             "{}",
             match self {
                 IntrinsicFunction::StartWorkspace => "start_workspace",
+                IntrinsicFunction::InjectBindings => "inject_bindings",
+                IntrinsicFunction::SymbolTable => "symbol_table",
             }
         )
     }
@@ -514,7 +435,7 @@ impl From<&Type> for ValueKind {
                 FloatType::F16 | FloatType::F32 => Self::F32,
                 FloatType::F64 => Self::F64,
                 FloatType::Float => {
-                    if IS_64BIT {
+                    if is_64bit() {
                         Self::F64
                     } else {
                         Self::F32
@@ -531,7 +452,7 @@ impl From<&Type> for ValueKind {
             Type::Type(_) => Self::Type,
             Type::Infer(_, InferType::AnyInt) => Self::Int,
             Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
+                if is_64bit() {
                     Self::F64
                 } else {
                     Self::F32
@@ -550,68 +471,26 @@ impl Value {
         })
     }
 
-    pub unsafe fn from_type_and_ptr(ty: &Type, ptr: RawPointer) -> Self {
-        match ty {
-            Type::Never | Type::Unit => Self::unit(),
-            Type::Bool => Self::Bool(*(ptr as *mut bool)),
-            Type::Int(ty) => match ty {
-                IntType::I8 => Self::I8(*(ptr as *mut i8)),
-                IntType::I16 => Self::I16(*(ptr as *mut i16)),
-                IntType::I32 => Self::I32(*(ptr as *mut i32)),
-                IntType::I64 => Self::I64(*(ptr as *mut i64)),
-                IntType::Int => Self::Int(*(ptr as *mut isize)),
-            },
-            Type::Uint(ty) => match ty {
-                UintType::U8 => Self::U8(*(ptr as *mut u8)),
-                UintType::U16 => Self::U16(*(ptr as *mut u16)),
-                UintType::U32 => Self::U32(*(ptr as *mut u32)),
-                UintType::U64 => Self::U64(*(ptr as *mut u64)),
-                UintType::Uint => Self::Uint(*(ptr as *mut usize)),
-            },
-            Type::Float(ty) => match ty {
-                FloatType::F16 | FloatType::F32 => Self::F32(*(ptr as *mut f32)),
-                FloatType::F64 => Self::F64(*(ptr as *mut f64)),
-                FloatType::Float => {
-                    if IS_64BIT {
-                        Self::F64(*(ptr as *mut f64))
-                    } else {
-                        Self::F32(*(ptr as *mut f32))
-                    }
-                }
-            },
-            Type::Pointer(ty, _) => Self::Pointer(Pointer::from_type_and_ptr(ty, *(ptr as *mut RawPointer))),
-            Type::Function(_) => todo!(),
-            Type::Array(inner, size) => Self::Buffer(Buffer {
-                bytes: ByteSeq::copy_from_raw_parts(ptr as _, *size * inner.size_of(WORD_SIZE)),
-                ty: ty.clone(),
-            }),
-            Type::Tuple(_) => {
-                let size = ty.size_of(WORD_SIZE);
-                let slice = slice::from_raw_parts(ptr as *const u8, size);
-
-                Self::Buffer(Buffer {
-                    bytes: ByteSeq::from(slice),
-                    ty: ty.clone(),
-                })
+    /// Overwrites this value with `value`.
+    /// Buffers are overwritten in place, since pointers to them point directly to their bytes.
+    pub fn overwrite(&mut self, value: Value) {
+        match (self, value) {
+            (Value::Buffer(dst), Value::Buffer(src)) if dst.bytes.len() == src.bytes.len() => {
+                dst.bytes.as_mut().copy_from_slice(src.bytes.as_ref());
+                dst.ty = src.ty;
             }
-            Type::Struct(struct_ty) => {
-                let size = struct_ty.size_of(WORD_SIZE);
-                let slice = slice::from_raw_parts(ptr as *const u8, size);
+            (dst, value) => *dst = value,
+        }
+    }
 
-                Self::Buffer(Buffer {
-                    bytes: ByteSeq::from(slice),
-                    ty: ty.clone(),
-                })
-            }
-            Type::Infer(_, InferType::AnyInt) => Self::Int(*(ptr as *mut isize)),
-            Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
-                    Self::F64(*(ptr as *mut f64))
-                } else {
-                    Self::F32(*(ptr as *mut f32))
-                }
-            }
-            _ => panic!("invalid type {:?}", ty),
+    /// Reads a value of type `ty` from memory, which is laid out for the target
+    pub unsafe fn from_type_and_ptr(ty: &Type, ptr: RawPointer) -> Self {
+        let size = ty.size_of(word_size());
+
+        if size == 0 {
+            Self::unit()
+        } else {
+            slice::from_raw_parts(ptr as *const u8, size).get_value(ty)
         }
     }
 
@@ -631,14 +510,15 @@ impl Value {
             Self::F64(_) => Type::f64(),
             Self::Bool(_) => Type::Bool,
             Self::Buffer(arr) => arr.ty.clone(),
-            Self::Pointer(p) => Type::Pointer(Box::new(p.get_type()), true),
+            Self::Pointer(p) => Type::Pointer(Box::new(p.ty.clone()), true),
             Self::Function(f) => Type::Function(if f.is_extern {
                 interp.extern_functions.get(&f.id).unwrap().ty.clone()
             } else {
                 interp.functions.get(&f.id).unwrap().ty.clone()
             }),
             Self::ExternVariable(v) => v.ty.clone(),
-            Self::Intrinsic(_) | Self::Type(_) => todo!(),
+            Self::Type(ty) => Type::Type(Box::new(ty.clone())),
+            Self::Intrinsic(_) => todo!(),
         }
     }
 
@@ -665,25 +545,18 @@ impl Value {
             Self::Type(t) => Ok(ConstValue::Type(tcx.bound(t, eval_span))),
             Self::Buffer(buf) => match ty {
                 Type::Unit => Ok(ConstValue::Unit(())),
-                Type::Array(_, _) => {
-                    let (el_ty, array_len) = if let Type::Array(el_ty, len) = buf.ty {
-                        (el_ty, len)
-                    } else {
-                        panic!()
-                    };
+                Type::Array(el_ty, array_len) => {
+                    let mut values = Vec::with_capacity(*array_len);
 
-                    let mut values = Vec::with_capacity(array_len);
-                    let el_size = el_ty.size_of(WORD_SIZE);
-
-                    for i in 0..array_len {
-                        let value = buf.bytes.offset(i * el_size).get_value(&el_ty);
-                        let const_value = value.try_into_const_value(tcx, &el_ty, eval_span)?;
+                    for index in 0..*array_len {
+                        let value = buf.get_value_at_index(index);
+                        let const_value = value.try_into_const_value(tcx, el_ty, eval_span)?;
                         values.push(const_value);
                     }
 
                     Ok(ConstValue::Array(ConstArray {
                         values,
-                        element_type: tcx.bound(*el_ty, eval_span),
+                        element_type: tcx.bound(el_ty.as_ref().clone(), eval_span),
                     }))
                 }
                 Type::Pointer(inner, _) => match inner.as_ref() {
@@ -698,12 +571,11 @@ impl Value {
                     _ => panic!("value type mismatch. expected an aggregate type, got {:?}", ty),
                 },
                 Type::Tuple(elements) => {
-                    let align = ty.align_of(WORD_SIZE);
                     let mut values = Vec::with_capacity(elements.len());
 
                     for (index, elem_type) in elements.iter().enumerate() {
-                        let value = buf.bytes.offset(index * align).get_value(elem_type);
-                        let const_value = value.try_into_const_value(tcx, ty, eval_span)?;
+                        let value = buf.get_value_at_index(index);
+                        let const_value = value.try_into_const_value(tcx, elem_type, eval_span)?;
                         values.push(ConstElement {
                             value: const_value,
                             ty: tcx.bound(elem_type.clone(), eval_span),
@@ -713,12 +585,11 @@ impl Value {
                     Ok(ConstValue::Tuple(values))
                 }
                 Type::Struct(struct_type) => {
-                    let align = ty.align_of(WORD_SIZE);
                     let mut fields = IndexMap::<Ustr, ConstElement>::new();
 
                     for (index, field) in struct_type.fields.iter().enumerate() {
-                        let value = buf.bytes.offset(index * align).get_value(&field.ty);
-                        let const_value = value.try_into_const_value(tcx, ty, eval_span)?;
+                        let value = buf.get_value_at_index(index);
+                        let const_value = value.try_into_const_value(tcx, &field.ty, eval_span)?;
 
                         fields.insert(
                             field.name,
@@ -747,148 +618,71 @@ impl Value {
 }
 
 impl Pointer {
-    #[allow(unused)]
-    pub fn unit() -> Self {
-        Pointer::Buffer(&mut Buffer {
-            bytes: ByteSeq::new(0),
-            ty: Type::Unit,
-        })
+    pub fn new(raw: RawPointer, ty: Type) -> Self {
+        Self { raw, ty }
     }
 
-    pub fn from_type_and_ptr(ty: &Type, ptr: RawPointer) -> Self {
-        match ty {
-            Type::Never | Type::Unit => Self::U8(ptr as _),
-            Type::Bool => Self::Bool(ptr as _),
-            Type::Int(ty) => match ty {
-                IntType::I8 => Self::I8(ptr as _),
-                IntType::I16 => Self::I16(ptr as _),
-                IntType::I32 => Self::I32(ptr as _),
-                IntType::I64 => Self::I64(ptr as _),
-                IntType::Int => Self::Int(ptr as _),
-            },
-            Type::Uint(ty) => match ty {
-                UintType::U8 => Self::U8(ptr as _),
-                UintType::U16 => Self::U16(ptr as _),
-                UintType::U32 => Self::U32(ptr as _),
-                UintType::U64 => Self::U64(ptr as _),
-                UintType::Uint => Self::Uint(ptr as _),
-            },
-            Type::Float(ty) => match ty {
-                FloatType::F16 | FloatType::F32 => Self::F32(ptr as _),
-                FloatType::F64 => Self::F64(ptr as _),
-                FloatType::Float => {
-                    if IS_64BIT {
-                        Self::F64(ptr as _)
-                    } else {
-                        Self::F32(ptr as _)
-                    }
-                }
-            },
-            Type::Pointer(inner, _) => match inner.as_ref() {
-                Type::Slice(_) | Type::Str(_) => {
-                    if ptr.is_null() {
-                        Self::Buffer(std::ptr::null_mut())
-                    } else {
-                        let bytes = ByteSeq::copy_from_raw_parts(ptr as _, ty.size_of(WORD_SIZE));
+    /// Returns a pointer to a value owned by the VM, such as a register or a global.
+    /// Buffers are pointed to by their bytes, other values by their payload.
+    pub fn from_value(value: &mut Value, ty: Type) -> Self {
+        let raw = match value {
+            Value::Buffer(buf) => buf.bytes.as_mut_ptr() as RawPointer,
+            value => value.as_c_ref(),
+        };
 
-                        let buf = Box::new(Buffer { bytes, ty: ty.clone() });
+        Self::new(raw, ty)
+    }
 
-                        // Note (Ron): Leak
-                        Self::Buffer(Box::leak(buf) as *mut Buffer)
-                    }
-                }
-                _ => Self::from_type_and_ptr(inner, ptr),
-            },
-            Type::Function(_) => todo!(),
-            Type::Array(inner, size) => {
-                if ptr.is_null() {
-                    Self::Buffer(std::ptr::null_mut())
-                } else {
-                    let bytes = ByteSeq::copy_from_raw_parts(ptr as _, *size * inner.size_of(WORD_SIZE));
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.raw.is_null()
+    }
 
-                    let buf = Box::new(Buffer { bytes, ty: ty.clone() });
+    /// Offsets the pointer by `count` pointees, like pointer arithmetic does at runtime
+    pub unsafe fn offset(&self, count: isize) -> Self {
+        let size = self.ty.size_of(word_size()) as isize;
+        Self::new(
+            (self.raw as *mut u8).offset(count * size) as RawPointer,
+            self.ty.clone(),
+        )
+    }
 
-                    // Note (Ron): Leak
-                    Self::Buffer(Box::leak(buf) as *mut Buffer)
-                }
-            }
-            Type::Tuple(_) | Type::Struct(_) => {
-                if ptr.is_null() {
-                    Self::Buffer(std::ptr::null_mut())
-                } else {
-                    let bytes = ByteSeq::copy_from_raw_parts(ptr as _, ty.size_of(WORD_SIZE));
+    /// Returns a pointer `offset` bytes into the pointee. Pointers to arrays decay to a pointer to their elements.
+    pub unsafe fn element(&self, offset: usize) -> Self {
+        Self::new(
+            (self.raw as *mut u8).add(offset) as RawPointer,
+            decayed_type(&self.ty).clone(),
+        )
+    }
 
-                    let buf = Box::new(Buffer { bytes, ty: ty.clone() });
+    /// Returns a pointer to the pointee's member at `index`, which is found using the target's layout
+    pub unsafe fn member(&self, index: usize) -> Self {
+        let offset = self.ty.offset_of(index, word_size());
 
-                    // Note (Ron): Leak
-                    Self::Buffer(Box::leak(buf) as *mut Buffer)
-                }
-            }
-            Type::Infer(_, InferType::AnyInt) => Self::Int(ptr as _),
-            Type::Infer(_, InferType::AnyFloat) => {
-                if IS_64BIT {
-                    Self::F32(ptr as _)
-                } else {
-                    Self::F64(ptr as _)
-                }
-            }
-            _ => panic!("invalid type {:?}", ty),
-        }
+        Self::new(
+            (self.raw as *mut u8).add(offset) as RawPointer,
+            member_type(&self.ty, index),
+        )
     }
 
-    pub fn get_type(&self) -> Type {
-        match self {
-            Self::I8(_) => Type::i8(),
-            Self::I16(_) => Type::i16(),
-            Self::I32(_) => Type::i32(),
-            Self::I64(_) => Type::i64(),
-            Self::Int(_) => Type::int(),
-            Self::U8(_) => Type::u8(),
-            Self::U16(_) => Type::u16(),
-            Self::U32(_) => Type::u32(),
-            Self::U64(_) => Type::u64(),
-            Self::Uint(_) => Type::uint(),
-            Self::F32(_) => Type::f32(),
-            Self::F64(_) => Type::f64(),
-            Self::Bool(_) => Type::Bool,
-            Self::Buffer(buf) => unsafe { &**buf }.ty.clone(),
-            Self::Pointer(p) => Type::Pointer(
-                if p.is_null() {
-                    Box::new(Type::u8())
-                } else {
-                    Box::new(unsafe { &**p }.get_type())
-                },
-                true,
-            ),
-            Self::Function(_) => todo!(),
-            value => panic!("{:?}", value),
+    pub unsafe fn deref_value(&self) -> Value {
+        match &self.ty {
+            // functions and types have no memory representation at compile-time,
+            // so pointers to them always point to a register or a global
+            Type::Function(_) => Value::Function((*(self.raw as *mut FunctionAddress)).clone()),
+            Type::Type(_) => Value::Type((*(self.raw as *mut Type)).clone()),
+            ty => Value::from_type_and_ptr(ty, self.raw),
         }
     }
 
     pub unsafe fn write_value(&self, value: Value) {
-        unsafe fn slice<'a, T>(p: &*mut T) -> &'a mut [u8] {
-            slice::from_raw_parts_mut(*p as *mut u8, mem::size_of::<T>())
-        }
-
-        match (self, value) {
-            (Self::I8(p), Value::I8(v)) => slice(p).write_i8(v).unwrap(),
-            (Self::I16(p), Value::I16(v)) => slice(p).write_i16::<NativeEndian>(v).unwrap(),
-            (Self::I32(p), Value::I32(v)) => slice(p).write_i32::<NativeEndian>(v).unwrap(),
-            (Self::I64(p), Value::I64(v)) => slice(p).write_i64::<NativeEndian>(v).unwrap(),
-            (Self::Int(p), Value::Int(v)) => slice(p).write_int::<NativeEndian>(v as i64, WORD_SIZE).unwrap(),
-            (Self::U8(p), Value::U8(v)) => slice(p).write_u8(v).unwrap(),
-            (Self::U16(p), Value::U16(v)) => slice(p).write_u16::<NativeEndian>(v).unwrap(),
-            (Self::U32(p), Value::U32(v)) => slice(p).write_u32::<NativeEndian>(v).unwrap(),
-            (Self::U64(p), Value::U64(v)) => slice(p).write_u64::<NativeEndian>(v).unwrap(),
-            (Self::Uint(p), Value::Uint(v)) => slice(p).write_uint::<NativeEndian>(v as u64, WORD_SIZE).unwrap(),
-            (Self::F32(p), Value::F32(v)) => slice(p).write_f32::<NativeEndian>(v).unwrap(),
-            (Self::F64(p), Value::F64(v)) => slice(p).write_f64::<NativeEndian>(v).unwrap(),
-            (Self::Bool(p), Value::Bool(v)) => slice(p).write_u8(v as u8).unwrap(),
-            (Self::Buffer(p), Value::Buffer(v)) => **p = v,
-            (Self::Pointer(p), Value::Pointer(v)) => **p = v,
-            (Self::Function(p), Value::Function(v)) => **p = v,
-            (Self::Type(p), Value::Type(v)) => **p = v,
-            (p, v) => panic!("invalid pair {:?} , {}", p, v.to_string()),
+        match (&self.ty, value) {
+            (Type::Function(_), Value::Function(v)) => *(self.raw as *mut FunctionAddress) = v,
+            (Type::Type(_), Value::Type(v)) => *(self.raw as *mut Type) = v,
+            (ty, value) => {
+                let size = ty.size_of(word_size());
+                slice::from_raw_parts_mut(self.raw as *mut u8, size).put_value(&value)
+            }
         }
     }
 }
@@ -1002,34 +796,10 @@ impl Display for ExternVariable {
 
 impl Display for Pointer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let value = if self.as_inner_raw().is_null() {
-            "null".to_string()
+        if self.is_null() {
+            write!(f, "ptr null")
         } else {
-            unsafe {
-                match self {
-                    Pointer::I8(v) => format!("i8 {}", **v),
-                    Pointer::I16(v) => format!("i16 {}", **v),
-                    Pointer::I32(v) => format!("i32 {}", **v),
-                    Pointer::I64(v) => format!("i64 {}", **v),
-                    Pointer::Int(v) => format!("int {}", **v),
-                    Pointer::U8(v) => format!("u8 {}", **v),
-                    Pointer::U16(v) => format!("u16 {}", **v),
-                    Pointer::U32(v) => format!("u32 {}", **v),
-                    Pointer::U64(v) => format!("u64 {}", **v),
-                    Pointer::Uint(v) => format!("uint {}", **v),
-                    Pointer::F32(v) => format!("f32 {:.2}", **v),
-                    Pointer::F64(v) => format!("f64 {:.2}", **v),
-                    Pointer::Bool(v) => format!("bool {}", **v),
-                    Pointer::Buffer(v) => (**v).to_string(),
-                    Pointer::Pointer(p) => (**p).to_string(),
-                    Pointer::Function(f) => (**f).to_string(),
-                    Pointer::ExternVariable(v) => (**v).to_string(),
-                    Pointer::Intrinsic(v) => (**v).to_string(),
-                    Pointer::Type(ty) => format!("type {:?}", (**ty)),
-                }
-            }
-        };
-
-        write!(f, "ptr {}", value)
+            write!(f, "ptr {}", unsafe { self.deref_value() })
+        }
     }
 }
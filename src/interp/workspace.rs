@@ -1,8 +1,13 @@
 use super::{
+    decode_ptr,
     vm::{byte_seq::GetValue, value::Value},
-    WORD_SIZE,
+    word_size,
 };
-use crate::types::offset_of::OffsetOf;
+use crate::{
+    common::{build_options::RuntimeChecks, target::TargetPlatform},
+    types::{offset_of::OffsetOf, size_of::SizeOf},
+};
+use byteorder::{NativeEndian, ReadBytesExt};
 
 #[derive(Debug, Clone)]
 pub struct WorkspaceValue {
@@ -19,7 +24,7 @@ impl From<&Value> for WorkspaceValue {
 
         let build_options = BuildOptionsValue::from(
             &buf.bytes
-                .offset(buf.ty.offset_of(1, WORD_SIZE))
+                .offset(buf.ty.offset_of(1, word_size()))
                 .get_value(&struct_field_types[1].ty),
         );
 
@@ -36,63 +41,111 @@ pub struct BuildOptionsValue {
     pub output_file: String,
     pub target: BuildTargetValue,
     pub optimization_level: OptimizationLevelValue,
+    pub output_kind: OutputKindValue,
+    pub include_paths: Vec<String>,
+    pub libraries: Vec<String>,
+    pub library_search_paths: Vec<String>,
+    pub defines: Vec<(String, String)>,
+    pub emit: EmitOptionsValue,
+    pub keep_intermediates: bool,
+    pub runtime_checks: RuntimeChecksValue,
+    // None if the build file left it empty
+    pub sysroot: Option<String>,
 }
 
 impl From<&Value> for BuildOptionsValue {
     fn from(value: &Value) -> Self {
         let buf = value.as_buffer();
-        let field_types = &buf.ty.as_struct().fields;
-
-        let input_file = buf
-            .bytes
-            .offset(0)
-            .get_value(&field_types[0].ty)
-            .as_buffer()
-            .as_str()
-            .to_string();
-
-        let output_file = buf
-            .bytes
-            .offset(buf.ty.offset_of(1, WORD_SIZE))
-            .get_value(&field_types[1].ty)
-            .as_buffer()
-            .as_str()
-            .to_string();
-
-        let target = BuildTargetValue::from(
-            &buf.bytes
-                .offset(buf.ty.offset_of(2, WORD_SIZE))
-                .get_value(&field_types[2].ty),
-        );
-        let optimization_level = OptimizationLevelValue::from(
-            &buf.bytes
-                .offset(buf.ty.offset_of(3, WORD_SIZE))
-                .get_value(&field_types[3].ty),
-        );
+
+        let input_file = buf.get_value_at_index(0).as_buffer().as_str().to_string();
+        let output_file = buf.get_value_at_index(1).as_buffer().as_str().to_string();
+        let target = BuildTargetValue::from(&buf.get_value_at_index(2));
+        let optimization_level = OptimizationLevelValue::from(&buf.get_value_at_index(3));
+        let output_kind = OutputKindValue::from(&buf.get_value_at_index(4));
+        let include_paths = get_str_slice(&buf.get_value_at_index(5));
+        let libraries = get_str_slice(&buf.get_value_at_index(6));
+        let library_search_paths = get_str_slice(&buf.get_value_at_index(7));
+
+        let defines = get_slice_values(&buf.get_value_at_index(8))
+            .iter()
+            .map(|define| {
+                let define = define.as_buffer();
+
+                (
+                    define.get_value_at_index(0).as_buffer().as_str().to_string(),
+                    define.get_value_at_index(1).as_buffer().as_str().to_string(),
+                )
+            })
+            .collect();
+
+        let emit = EmitOptionsValue::from(&buf.get_value_at_index(9));
+        let keep_intermediates = *buf.get_value_at_index(10).as_bool();
+        let runtime_checks = RuntimeChecksValue::from(&buf.get_value_at_index(11));
+        let sysroot = buf.get_value_at_index(12).as_buffer().as_str().to_string();
 
         Self {
             input_file,
             output_file,
             target,
             optimization_level,
+            output_kind,
+            include_paths,
+            libraries,
+            library_search_paths,
+            defines,
+            emit,
+            keep_intermediates,
+            runtime_checks,
+            sysroot: (!sysroot.is_empty()).then(|| sysroot),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmitOptionsValue {
+    pub times: bool,
+    pub hir: bool,
+    pub bytecode: bool,
+    pub llvm_ir: bool,
+}
+
+impl From<&Value> for EmitOptionsValue {
+    fn from(value: &Value) -> Self {
+        let buf = value.as_buffer();
+
+        Self {
+            times: *buf.get_value_at_index(0).as_bool(),
+            hir: *buf.get_value_at_index(1).as_bool(),
+            bytecode: *buf.get_value_at_index(2).as_bool(),
+            llvm_ir: *buf.get_value_at_index(3).as_bool(),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-#[repr(usize)]
 pub enum BuildTargetValue {
-    Auto = 0,
-    Linux = 1,
-    Windows = 2,
+    Auto,
+    Platform(TargetPlatform),
 }
 
 impl From<&Value> for BuildTargetValue {
     fn from(value: &Value) -> Self {
+        // Keep in sync with `BuildTarget_*` in std/build.chl
         match value.as_uint() {
             0 => Self::Auto,
-            1 => Self::Linux,
-            2 => Self::Windows,
+            1 => Self::Platform(TargetPlatform::LinuxAmd64),
+            2 => Self::Platform(TargetPlatform::WindowsAmd64),
+            3 => Self::Platform(TargetPlatform::Windows386),
+            4 => Self::Platform(TargetPlatform::Linux386),
+            5 => Self::Platform(TargetPlatform::LinuxArm64),
+            6 => Self::Platform(TargetPlatform::DarwinAmd64),
+            7 => Self::Platform(TargetPlatform::DarwinArm64),
+            8 => Self::Platform(TargetPlatform::FreeBSD386),
+            9 => Self::Platform(TargetPlatform::FreeBSDAmd64),
+            10 => Self::Platform(TargetPlatform::EssenceAmd64),
+            11 => Self::Platform(TargetPlatform::FreestandingWasm32),
+            12 => Self::Platform(TargetPlatform::JsWasm32),
+            13 => Self::Platform(TargetPlatform::WasiWasm32),
             x => panic!("{}", x),
         }
     }
@@ -101,16 +154,148 @@ impl From<&Value> for BuildTargetValue {
 #[derive(Debug, Clone)]
 #[repr(usize)]
 pub enum OptimizationLevelValue {
-    Debug = 0,
-    Release = 1,
+    O0 = 0,
+    O2 = 1,
+    O1 = 2,
+    O3 = 3,
+    Os = 4,
+    Oz = 5,
 }
 
 impl From<&Value> for OptimizationLevelValue {
     fn from(value: &Value) -> Self {
+        // Keep in sync with `OptimizationLevel_*` in std/build.chl
         match value.as_uint() {
-            0 => Self::Debug,
-            1 => Self::Release,
+            0 => Self::O0,
+            1 => Self::O2,
+            2 => Self::O1,
+            3 => Self::O3,
+            4 => Self::Os,
+            5 => Self::Oz,
             x => panic!("{}", x),
         }
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct RuntimeChecksValue {
+    pub division_by_zero: RuntimeCheckValue,
+    pub null_pointer_dereference: RuntimeCheckValue,
+    pub overflow: RuntimeCheckValue,
+    pub index_out_of_bounds: RuntimeCheckValue,
+    pub slice_range: RuntimeCheckValue,
+}
+
+impl From<&Value> for RuntimeChecksValue {
+    fn from(value: &Value) -> Self {
+        let buf = value.as_buffer();
+
+        Self {
+            division_by_zero: RuntimeCheckValue::from(&buf.get_value_at_index(0)),
+            null_pointer_dereference: RuntimeCheckValue::from(&buf.get_value_at_index(1)),
+            overflow: RuntimeCheckValue::from(&buf.get_value_at_index(2)),
+            index_out_of_bounds: RuntimeCheckValue::from(&buf.get_value_at_index(3)),
+            slice_range: RuntimeCheckValue::from(&buf.get_value_at_index(4)),
+        }
+    }
+}
+
+impl RuntimeChecksValue {
+    /// Resolves checks that are left on `auto`, according to `default`
+    pub fn resolve(&self, default: RuntimeChecks) -> RuntimeChecks {
+        RuntimeChecks {
+            division_by_zero: self.division_by_zero.resolve(default.division_by_zero),
+            null_pointer_dereference: self.null_pointer_dereference.resolve(default.null_pointer_dereference),
+            overflow: self.overflow.resolve(default.overflow),
+            index_out_of_bounds: self.index_out_of_bounds.resolve(default.index_out_of_bounds),
+            slice_range: self.slice_range.resolve(default.slice_range),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(usize)]
+pub enum RuntimeCheckValue {
+    Auto = 0,
+    Enabled = 1,
+    Disabled = 2,
+}
+
+impl From<&Value> for RuntimeCheckValue {
+    fn from(value: &Value) -> Self {
+        match value.as_uint() {
+            0 => Self::Auto,
+            1 => Self::Enabled,
+            2 => Self::Disabled,
+            x => panic!("{}", x),
+        }
+    }
+}
+
+impl RuntimeCheckValue {
+    fn resolve(&self, default: bool) -> bool {
+        match self {
+            RuntimeCheckValue::Auto => default,
+            RuntimeCheckValue::Enabled => true,
+            RuntimeCheckValue::Disabled => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[repr(usize)]
+pub enum OutputKindValue {
+    Executable = 0,
+    Object = 1,
+    StaticLibrary = 2,
+    SharedLibrary = 3,
+    Assembly = 4,
+    Bitcode = 5,
+}
+
+impl From<&Value> for OutputKindValue {
+    fn from(value: &Value) -> Self {
+        match value.as_uint() {
+            0 => Self::Executable,
+            1 => Self::Object,
+            2 => Self::StaticLibrary,
+            3 => Self::SharedLibrary,
+            4 => Self::Assembly,
+            5 => Self::Bitcode,
+            x => panic!("{}", x),
+        }
+    }
+}
+
+fn get_str_slice(value: &Value) -> Vec<String> {
+    get_slice_values(value)
+        .iter()
+        .map(|value| value.as_buffer().as_str().to_string())
+        .collect()
+}
+
+// Reads all elements of a slice, which point to memory outside of the slice's own buffer
+fn get_slice_values(value: &Value) -> Vec<Value> {
+    let buf = value.as_buffer();
+    let element_type = buf.ty.as_inner().as_inner();
+    let element_size = element_type.size_of(word_size());
+
+    let data = decode_ptr::<u8>(buf.bytes.offset(0).read_uint::<NativeEndian>(word_size()).unwrap()) as *const u8;
+
+    let len = buf
+        .bytes
+        .offset(buf.ty.offset_of(1, word_size()))
+        .read_uint::<NativeEndian>(word_size())
+        .unwrap() as usize;
+
+    if data.is_null() {
+        return vec![];
+    }
+
+    (0..len)
+        .map(|index| {
+            let bytes = unsafe { std::slice::from_raw_parts(data.add(index * element_size), element_size) };
+            bytes.get_value(element_type)
+        })
+        .collect()
+}
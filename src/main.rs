@@ -12,19 +12,21 @@ mod infer;
 mod interp;
 mod lint;
 mod parse;
+mod repl;
 mod span;
 mod token;
 mod types;
 mod workspace;
 
 use crate::common::{
-    build_options::{BuildOptions, CodegenOptions, DiagnosticOptions, OptimizationLevel},
+    build_options::{BuildOptions, CodegenOptions, DiagnosticOptions, OptimizationLevel, OutputKind, RuntimeChecks},
     target::TargetPlatform,
 };
 use clap::*;
 use colored::Colorize;
 use path_absolutize::Absolutize;
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     process::Command,
 };
@@ -39,6 +41,7 @@ use std::{
 )]
 struct Args {
     /// The main action the compiler should take.
+    /// Either a source file, or `repl` to start an interactive session.
     input: String,
 
     // Modes
@@ -53,6 +56,62 @@ struct Args {
     #[clap(long, short)]
     check: bool,
 
+    /// Enables Build mode - which compiles the input file directly to the given kind of artifact,
+    /// instead of evaluating it as a build file.
+    #[clap(long, arg_enum)]
+    output_kind: Option<OutputKindArg>,
+
+    // Build options
+    //
+    //
+    /// The path of the artifact produced in Build mode.
+    /// Its extension is replaced with the one of the output kind.
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+
+    /// Keep intermediate files, such as the object file of a linked executable.
+    #[clap(long)]
+    keep_intermediates: bool,
+
+    /// The platform to compile for, such as `linux-arm64`.
+    /// In Build mode, this is the target of the input file. Otherwise, it is the target of the workspaces
+    /// which the build file starts with `BuildTarget_auto`.
+    /// Defaults to the current platform.
+    #[clap(long, conflicts_with = "run")]
+    target: Option<String>,
+
+    /// The root directory of the target's headers and libraries, used when cross-compiling.
+    /// Defaults to the sysroot of the target's installed GNU cross toolchain.
+    #[clap(long)]
+    sysroot: Option<PathBuf>,
+
+    /// The optimization level of Run and Build modes.
+    /// Defaults to 2 in Run mode, and to 0 in Build mode.
+    #[clap(long = "opt-level", short = 'O', arg_enum)]
+    opt_level: Option<OptLevelArg>,
+
+    /// Emit runtime checks, such as bounds and overflow checks.
+    /// By default, they are only emitted when optimizations are disabled.
+    #[clap(long, conflicts_with = "no-runtime-checks")]
+    runtime_checks: bool,
+
+    /// Don't emit runtime checks, such as bounds and overflow checks.
+    #[clap(long)]
+    no_runtime_checks: bool,
+
+    /// Emit the given runtime check, on top of the ones enabled by the other options. Can be repeated.
+    #[clap(long, arg_enum, multiple_occurrences = true)]
+    enable_check: Vec<RuntimeCheckArg>,
+
+    /// Don't emit the given runtime check, even if it is enabled by the other options. Can be repeated.
+    #[clap(long, arg_enum, multiple_occurrences = true)]
+    disable_check: Vec<RuntimeCheckArg>,
+
+    /// Optimize like -O 2, while keeping all runtime checks.
+    /// Specific checks can still be disabled with --disable-check.
+    #[clap(long, conflicts_with_all = &["opt-level", "no-runtime-checks"])]
+    checked_release: bool,
+
     // Verbosity/Dump options
     //
     //
@@ -64,7 +123,7 @@ struct Args {
     #[clap(long)]
     emit_hir: bool,
 
-    /// Print trace information verbosely.
+    /// Print the compile-time bytecode, before and after optimization.
     #[clap(long)]
     emit_bytecode: bool,
 
@@ -72,6 +131,24 @@ struct Args {
     #[clap(long)]
     emit_llvm_ir: bool,
 
+    /// Print the instructions, calls and time spent in compile-time code, per function and per comptime block.
+    #[clap(long)]
+    profile_comptime: bool,
+
+    /// Write the compile-time profile as folded stacks to the given file, for use with flamegraph tools.
+    /// Implies --profile-comptime.
+    #[clap(long)]
+    profile_comptime_folded: Option<PathBuf>,
+
+    /// Step through compile-time code with an interactive debugger.
+    /// Execution pauses at the first line of compile-time code.
+    #[clap(long)]
+    debug_comptime: bool,
+
+    /// Don't reuse the results of compile-time evaluations, or the lowered functions, from previous builds.
+    #[clap(long)]
+    no_comptime_cache: bool,
+
     // Misc options
     //
     //
@@ -100,51 +177,192 @@ struct Args {
     /// Return the hover info for a given index, in the given input file.
     #[clap(long)]
     goto_def: Option<usize>,
+
+    /// Only available in Run mode.
+    /// Arguments passed to the compiled program, given after `--`.
+    #[clap(last = true)]
+    program_args: Vec<String>,
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy)]
+enum OutputKindArg {
+    Executable,
+    Object,
+    Assembly,
+    Bitcode,
+    StaticLibrary,
+    SharedLibrary,
+}
+
+impl From<OutputKindArg> for OutputKind {
+    fn from(kind: OutputKindArg) -> Self {
+        match kind {
+            OutputKindArg::Executable => OutputKind::Executable,
+            OutputKindArg::Object => OutputKind::Object,
+            OutputKindArg::Assembly => OutputKind::Assembly,
+            OutputKindArg::Bitcode => OutputKind::Bitcode,
+            OutputKindArg::StaticLibrary => OutputKind::StaticLibrary,
+            OutputKindArg::SharedLibrary => OutputKind::SharedLibrary,
+        }
+    }
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy)]
+enum OptLevelArg {
+    #[clap(name = "0")]
+    O0,
+    #[clap(name = "1")]
+    O1,
+    #[clap(name = "2")]
+    O2,
+    #[clap(name = "3")]
+    O3,
+    #[clap(name = "s")]
+    Os,
+    #[clap(name = "z")]
+    Oz,
+}
+
+impl From<OptLevelArg> for OptimizationLevel {
+    fn from(level: OptLevelArg) -> Self {
+        match level {
+            OptLevelArg::O0 => OptimizationLevel::O0,
+            OptLevelArg::O1 => OptimizationLevel::O1,
+            OptLevelArg::O2 => OptimizationLevel::O2,
+            OptLevelArg::O3 => OptimizationLevel::O3,
+            OptLevelArg::Os => OptimizationLevel::Os,
+            OptLevelArg::Oz => OptimizationLevel::Oz,
+        }
+    }
+}
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum RuntimeCheckArg {
+    DivisionByZero,
+    NullPointerDereference,
+    Overflow,
+    IndexOutOfBounds,
+    SliceRange,
+}
+
+impl RuntimeCheckArg {
+    fn set(&self, runtime_checks: &mut RuntimeChecks, enabled: bool) {
+        match self {
+            RuntimeCheckArg::DivisionByZero => runtime_checks.division_by_zero = enabled,
+            RuntimeCheckArg::NullPointerDereference => runtime_checks.null_pointer_dereference = enabled,
+            RuntimeCheckArg::Overflow => runtime_checks.overflow = enabled,
+            RuntimeCheckArg::IndexOutOfBounds => runtime_checks.index_out_of_bounds = enabled,
+            RuntimeCheckArg::SliceRange => runtime_checks.slice_range = enabled,
+        }
+    }
 }
 
 fn cli() {
     let args = Args::parse();
 
+    if args.input == "repl" {
+        let build_options = BuildOptions {
+            source_file: repl::root_file(),
+            output_file: None,
+            target_platform: current_target_platform(),
+            auto_target_platform: None,
+            optimization_level: OptimizationLevel::O0,
+            runtime_checks: RuntimeChecks::all(),
+            emit_times: false,
+            emit_hir: false,
+            emit_bytecode: args.emit_bytecode,
+            profile_comptime: false,
+            profile_comptime_folded: None,
+            debug_comptime: args.debug_comptime,
+            diagnostic_options: DiagnosticOptions::Emit {
+                no_color: args.no_color,
+            },
+            codegen_options: CodegenOptions::Skip { emit_llvm_ir: false },
+            output_kind: OutputKind::Executable,
+            keep_intermediates: false,
+            include_paths: get_include_paths(&args.include_paths),
+            libraries: vec![],
+            library_search_paths: vec![],
+            sysroot: None,
+            defines: HashMap::new(),
+            comptime_cache: false,
+            check_mode: false,
+        };
+
+        repl::start_repl(build_options);
+
+        return;
+    }
+
     match get_file_path(&args.input) {
         Ok(source_file) => {
             let name = get_workspace_name(&source_file);
 
             if args.run {
+                let optimization_level = get_optimization_level(&args, OptimizationLevel::O2);
+
                 let build_options = BuildOptions {
                     source_file,
                     output_file: None,
                     target_platform: current_target_platform(),
-                    optimization_level: OptimizationLevel::Release,
+                    auto_target_platform: None,
+                    optimization_level,
+                    runtime_checks: get_runtime_checks(&args, optimization_level),
                     emit_times: args.emit_times,
                     emit_hir: args.emit_hir,
                     emit_bytecode: args.emit_bytecode,
+                    profile_comptime: args.profile_comptime || args.profile_comptime_folded.is_some(),
+                    profile_comptime_folded: args.profile_comptime_folded.clone(),
+                    debug_comptime: args.debug_comptime,
                     diagnostic_options: DiagnosticOptions::Emit {
                         no_color: args.no_color,
                     },
                     codegen_options: CodegenOptions::Codegen {
                         emit_llvm_ir: args.emit_llvm_ir,
                     },
+                    output_kind: OutputKind::Executable,
+                    keep_intermediates: args.keep_intermediates,
                     include_paths: get_include_paths(&args.include_paths),
+                    libraries: vec![],
+                    library_search_paths: vec![],
+                    sysroot: None,
+                    defines: HashMap::new(),
+                    comptime_cache: !args.no_comptime_cache,
                     check_mode: false,
                 };
 
                 let result = driver::start_workspace(name, build_options);
 
                 if let Some(output_file) = &result.output_file {
-                    let _ = Command::new(output_file).spawn();
+                    // Forward the program's exit code, so that `main`'s return value is observable
+                    if let Ok(status) = Command::new(output_file).args(&args.program_args).status() {
+                        std::process::exit(status.code().unwrap_or(1));
+                    }
                 }
             } else if args.check {
                 let build_options = BuildOptions {
                     source_file,
                     output_file: None,
                     target_platform: current_target_platform(),
-                    optimization_level: OptimizationLevel::Debug,
+                    auto_target_platform: None,
+                    optimization_level: OptimizationLevel::O0,
+                    runtime_checks: RuntimeChecks::all(),
                     emit_times: false,
                     emit_hir: false,
                     emit_bytecode: false,
+                    profile_comptime: false,
+                    profile_comptime_folded: None,
+                    debug_comptime: false,
                     diagnostic_options: DiagnosticOptions::DontEmit,
                     codegen_options: CodegenOptions::Skip { emit_llvm_ir: false },
+                    output_kind: OutputKind::Executable,
+                    keep_intermediates: false,
                     include_paths: get_include_paths(&args.include_paths),
+                    libraries: vec![],
+                    library_search_paths: vec![],
+                    sysroot: None,
+                    defines: HashMap::new(),
+                    comptime_cache: false,
                     check_mode: true,
                 };
 
@@ -157,22 +375,68 @@ fn cli() {
                 } else if let Some(offset) = args.goto_def {
                     ide::goto_definition(&result.workspace, result.tcx.as_ref(), offset);
                 }
+            } else if let Some(output_kind) = args.output_kind {
+                let optimization_level = get_optimization_level(&args, OptimizationLevel::O0);
+
+                let build_options = BuildOptions {
+                    source_file,
+                    output_file: args.output.clone(),
+                    target_platform: get_target_platform(&args),
+                    auto_target_platform: None,
+                    optimization_level,
+                    runtime_checks: get_runtime_checks(&args, optimization_level),
+                    emit_times: args.emit_times,
+                    emit_hir: args.emit_hir,
+                    emit_bytecode: args.emit_bytecode,
+                    profile_comptime: args.profile_comptime || args.profile_comptime_folded.is_some(),
+                    profile_comptime_folded: args.profile_comptime_folded.clone(),
+                    debug_comptime: args.debug_comptime,
+                    diagnostic_options: DiagnosticOptions::Emit {
+                        no_color: args.no_color,
+                    },
+                    codegen_options: CodegenOptions::Codegen {
+                        emit_llvm_ir: args.emit_llvm_ir,
+                    },
+                    output_kind: output_kind.into(),
+                    keep_intermediates: args.keep_intermediates,
+                    include_paths: get_include_paths(&args.include_paths),
+                    libraries: vec![],
+                    library_search_paths: vec![],
+                    sysroot: args.sysroot.clone(),
+                    defines: HashMap::new(),
+                    comptime_cache: !args.no_comptime_cache,
+                    check_mode: false,
+                };
+
+                driver::start_workspace(name, build_options);
             } else {
                 let build_options = BuildOptions {
                     source_file,
                     output_file: None,
                     target_platform: current_target_platform(),
-                    optimization_level: OptimizationLevel::Debug,
+                    auto_target_platform: args.target.as_ref().map(|_| get_target_platform(&args)),
+                    optimization_level: OptimizationLevel::O0,
+                    runtime_checks: RuntimeChecks::all(),
                     emit_times: args.emit_times,
                     emit_hir: args.emit_hir,
                     emit_bytecode: args.emit_bytecode,
+                    profile_comptime: args.profile_comptime || args.profile_comptime_folded.is_some(),
+                    profile_comptime_folded: args.profile_comptime_folded.clone(),
+                    debug_comptime: args.debug_comptime,
                     diagnostic_options: DiagnosticOptions::Emit {
                         no_color: args.no_color,
                     },
                     codegen_options: CodegenOptions::Skip {
                         emit_llvm_ir: args.emit_llvm_ir,
                     },
+                    output_kind: OutputKind::Executable,
+                    keep_intermediates: args.keep_intermediates,
                     include_paths: get_include_paths(&args.include_paths),
+                    libraries: vec![],
+                    library_search_paths: vec![],
+                    sysroot: args.sysroot.clone(),
+                    defines: HashMap::new(),
+                    comptime_cache: !args.no_comptime_cache,
                     check_mode: false,
                 };
 
@@ -215,10 +479,57 @@ fn current_target_platform() -> TargetPlatform {
     }
 }
 
+fn get_target_platform(args: &Args) -> TargetPlatform {
+    match &args.target {
+        Some(name) => match TargetPlatform::from_name(name) {
+            Some(target_platform) => target_platform,
+            None => {
+                let targets = TargetPlatform::all()
+                    .iter()
+                    .map(|platform| platform.name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                print_err(&format!("unknown target `{}`, expected one of: {}", name, targets));
+                std::process::exit(1);
+            }
+        },
+        None => current_target_platform(),
+    }
+}
+
 fn print_err(msg: &str) {
     println!("\n{} {}\n", "error:".red().bold(), msg.bold());
 }
 
+fn get_optimization_level(args: &Args, default: OptimizationLevel) -> OptimizationLevel {
+    if args.checked_release {
+        OptimizationLevel::O2
+    } else {
+        args.opt_level.map_or(default, OptimizationLevel::from)
+    }
+}
+
+fn get_runtime_checks(args: &Args, optimization_level: OptimizationLevel) -> RuntimeChecks {
+    let mut runtime_checks = if args.runtime_checks || args.checked_release {
+        RuntimeChecks::all()
+    } else if args.no_runtime_checks {
+        RuntimeChecks::none()
+    } else {
+        RuntimeChecks::default_for(optimization_level)
+    };
+
+    for check in args.enable_check.iter() {
+        check.set(&mut runtime_checks, true);
+    }
+
+    for check in args.disable_check.iter() {
+        check.set(&mut runtime_checks, false);
+    }
+
+    runtime_checks
+}
+
 fn get_include_paths(include_paths: &Option<String>) -> Vec<PathBuf> {
     include_paths
         .as_ref()
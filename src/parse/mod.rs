@@ -11,7 +11,7 @@ mod top_level;
 use crate::{
     ast::{self, Ast},
     error::{diagnostic::Diagnostic, DiagnosticResult, Diagnostics, SyntaxError},
-    span::Span,
+    span::{FileId, Span},
     token::{lexer::Lexer, Token, TokenKind::*},
     workspace::{library::Library, ModuleInfo, ModulePath},
 };
@@ -134,6 +134,62 @@ pub enum ParserResult {
     LexerFailed(ast::Module, Diagnostic),
 }
 
+// A single item entered in the repl
+pub enum ReplItem {
+    Binding(ast::Binding),
+    Expr(Ast),
+}
+
+/// Parses input entered in the repl, as if it was written in the module at `module_path`.
+/// Modules imported by the input are parsed in the background, and sent through `tx`.
+pub fn parse_repl_input(
+    thread_pool: ThreadPool,
+    tx: Sender<Box<ParserResult>>,
+    cache: Arc<Mutex<ParserCache>>,
+    module_path: ModulePath,
+    file_id: FileId,
+    source: &str,
+) -> DiagnosticResult<Vec<ReplItem>> {
+    let mut parser = Parser::new(thread_pool, tx, cache, module_path);
+
+    parser.module_info.file_id = file_id;
+    parser.tokens = Lexer::new(file_id, source).scan()?;
+
+    let mut items = vec![];
+
+    while !parser.eof() {
+        if !eat!(parser, Semicolon | Newline) {
+            items.push(parser.parse_repl_item()?);
+        }
+    }
+
+    Ok(items)
+}
+
+/// Parses source code injected by compile-time code, as if it was written in the module at `module_path`.
+/// Returns a module that holds the injected bindings and comptime blocks.
+pub fn parse_injected_bindings(
+    thread_pool: ThreadPool,
+    tx: Sender<Box<ParserResult>>,
+    cache: Arc<Mutex<ParserCache>>,
+    module_path: ModulePath,
+    file_id: FileId,
+    source: &str,
+) -> DiagnosticResult<ast::Module> {
+    let mut parser = Parser::new(thread_pool, tx, cache, module_path);
+
+    parser.module_info.file_id = file_id;
+    parser.tokens = Lexer::new(file_id, source).scan()?;
+
+    let mut module = ast::Module::new(file_id, parser.module_info);
+
+    while !parser.eof() {
+        parser.parse_top_level(&mut module)?;
+    }
+
+    Ok(module)
+}
+
 impl Parser {
     pub fn new(
         thread_pool: ThreadPool,
@@ -58,4 +58,28 @@ impl Parser {
             }
         }
     }
+
+    pub fn parse_repl_item(&mut self) -> DiagnosticResult<ReplItem> {
+        let attrs = self.parse_attrs()?;
+        let has_attrs = !attrs.is_empty();
+
+        let visibility = if eat!(self, Pub) {
+            ast::Visibility::Public
+        } else {
+            ast::Visibility::Private
+        };
+
+        match self.try_parse_any_binding(attrs, visibility, true)? {
+            Some(binding) => Ok(ReplItem::Binding(binding?)),
+            None => {
+                if has_attrs {
+                    Err(Diagnostic::error()
+                        .with_message(format!("expected a binding, got `{}`", self.peek().kind.lexeme()))
+                        .with_label(Label::primary(self.span(), "unexpected token")))
+                } else {
+                    Ok(ReplItem::Expr(self.parse_expression(true, false)?))
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,194 @@
+use crate::{
+    ast,
+    astgen::{generate_ast, generate_repl_ast},
+    check::CheckSess,
+    common::build_options::BuildOptions,
+    infer::{display::DisplayType, normalize::Normalize},
+    parse::ReplItem,
+    workspace::{library::Library, LibraryId, ModuleId, ModuleInfo, ModulePath, Workspace},
+};
+use colored::Colorize;
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+use ustr::ustr;
+
+// The repl's input is treated as if it was written in this file, which doesn't exist on disk.
+// It's placed in the current directory, so that project modules can be imported relative to it.
+const REPL_FILE_NAME: &str = "<repl>.chl";
+
+pub fn root_file() -> PathBuf {
+    env::current_dir().unwrap().join(REPL_FILE_NAME)
+}
+
+pub fn start_repl(build_options: BuildOptions) {
+    let main_library = Library {
+        id: LibraryId::unknown(),
+        name: ustr("repl"),
+        root_file: build_options.source_file.clone(),
+        root_module_id: ModuleId::unknown(),
+        is_main: true,
+    };
+
+    let mut workspace = Workspace::new("repl".to_string(), build_options, main_library);
+
+    // Parse std up front. The main library's root file doesn't exist, so it's skipped
+    let modules = match generate_ast(&mut workspace) {
+        Some((modules, _)) if !workspace.diagnostics.has_errors() => modules,
+        _ => {
+            workspace.emit_diagnostics();
+            return;
+        }
+    };
+
+    let module_path = workspace.main_library().as_module_path();
+
+    let file_id = workspace
+        .diagnostics
+        .add_file(module_path.path().display().to_string(), String::new());
+
+    // The repl's module is nameless, so that diagnostics refer to it as "this scope"
+    let module_info = ModuleInfo {
+        name: ustr(""),
+        file_id,
+        ..ModuleInfo::from(&module_path)
+    };
+
+    let module_id = workspace.module_infos.insert(module_info);
+    workspace.root_module_id = module_id;
+
+    let mut module = ast::Module::new(file_id, module_info);
+    module.id = module_id;
+
+    let mut sess = CheckSess::new(&mut workspace, modules.iter().chain([&module]).collect());
+
+    if let Err(diag) = sess.start() {
+        sess.workspace.diagnostics.push(diag);
+    }
+
+    if sess.workspace.diagnostics.has_errors() {
+        sess.workspace.emit_diagnostics();
+        return;
+    }
+
+    println!(
+        "{}",
+        format!(
+            "chili {} - enter an expression or a binding. press ctrl+d to exit.",
+            env!("CARGO_PKG_VERSION")
+        )
+        .bright_black()
+    );
+
+    let stdin = io::stdin();
+    let mut input_count = 0;
+
+    while let Some(input) = read_input(&stdin) {
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        input_count += 1;
+
+        eval_input(
+            &mut sess,
+            &module_path,
+            module_id,
+            format!("<repl:{}>", input_count),
+            &input,
+        );
+
+        sess.workspace.emit_diagnostics();
+        sess.workspace.diagnostics.clear();
+    }
+}
+
+fn eval_input(sess: &mut CheckSess, module_path: &ModulePath, module_id: ModuleId, name: String, input: &str) {
+    let (items, modules) = match generate_repl_ast(sess.workspace, module_path.clone(), name, input) {
+        Some(result) => result,
+        None => return,
+    };
+
+    if sess.workspace.diagnostics.has_errors() {
+        return;
+    }
+
+    // Newly imported modules need to live as long as the session
+    for module in modules {
+        sess.add_repl_module(Box::leak(Box::new(module)));
+    }
+
+    for item in items.iter() {
+        let result = match item {
+            ReplItem::Binding(binding) => sess.check_repl_binding(binding, module_id),
+            ReplItem::Expr(expr) => sess.eval_repl_expr(expr, module_id).map(|(value, ty)| {
+                let normalized = ty.normalize(&sess.tcx);
+
+                if !normalized.is_unit() && !normalized.is_never() {
+                    println!(
+                        "{} {}",
+                        value.display(&sess.tcx),
+                        format!(": {}", ty.display(&sess.tcx)).bright_black()
+                    );
+                }
+            }),
+        };
+
+        if let Err(diag) = result {
+            sess.workspace.diagnostics.push(diag);
+            break;
+        }
+    }
+}
+
+// Reads a single line of input. If the line has unclosed delimiters, lines are read until they're closed.
+// Returns `None` when stdin is closed.
+fn read_input(stdin: &io::Stdin) -> Option<String> {
+    let mut input = String::new();
+
+    loop {
+        print!("{} ", if input.is_empty() { ">" } else { "." });
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return if input.is_empty() { None } else { Some(input) },
+            Ok(_) => input.push_str(&line),
+        }
+
+        if delimiter_depth(&input) <= 0 {
+            return Some(input);
+        }
+    }
+}
+
+fn delimiter_depth(input: &str) -> isize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut chars = input.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '#' if !in_string => {
+                // skip comments
+                for ch in chars.by_ref() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            _ => (),
+        }
+    }
+
+    depth
+}
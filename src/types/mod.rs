@@ -9,6 +9,7 @@ use crate::{
     span::Span,
     workspace::{BindingId, ModuleId},
 };
+use serde::{Deserialize, Serialize};
 use ustr::{ustr, Ustr};
 
 define_id_type!(TypeId);
@@ -53,7 +54,7 @@ pub enum InferType {
     AnyFloat,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum IntType {
     I8,
     I16,
@@ -68,7 +69,7 @@ impl Default for IntType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum UintType {
     U8,
     U16,
@@ -83,7 +84,7 @@ impl Default for UintType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum FloatType {
     F16,
     F32,
@@ -210,7 +211,7 @@ impl StructType {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum StructTypeKind {
     Struct,
     PackedStruct,
@@ -89,9 +89,8 @@ impl SizeOf for StructType {
             StructTypeKind::Union => {
                 let max_size = self.fields.iter().map(|f| f.ty.size_of(word_size)).max().unwrap_or(0);
 
-                let align = self.align_of(word_size);
-
-                (max_size / align) * align
+                // the largest field is padded up to the union's alignment, like in C
+                calculate_align_from_offset(max_size, self.align_of(word_size))
             }
         }
     }
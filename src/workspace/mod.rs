@@ -8,6 +8,7 @@ use crate::{
     define_id_type,
     error::{emit_diagnostics, emitter::ColorMode, Diagnostics},
     hir::{self, const_value::ConstValue},
+    interp::vm::profiler::Profiler,
     span::{FileId, Span},
     types::TypeId,
 };
@@ -44,6 +45,9 @@ pub struct Workspace {
     // Bindings resolved during semantic analysis
     // BindingInfoId -> BindingInfo
     pub binding_infos: IdCache<BindingId, BindingInfo>,
+
+    // The compile-time profile, collected during semantic analysis if `profile_comptime` is enabled
+    pub comptime_profile: Option<Profiler>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -195,6 +199,7 @@ impl Workspace {
             module_infos: Default::default(),
             root_module_id: Default::default(),
             binding_infos: Default::default(),
+            comptime_profile: None,
         }
     }
 
@@ -206,6 +211,36 @@ impl Workspace {
         self.libraries.get(LIBRARY_ID_STD).unwrap()
     }
 
+    /// Rebuilds the module path of an already parsed module, so that it can be used to resolve relative imports
+    pub fn module_path(&self, module_id: ModuleId) -> ModulePath {
+        let module_info = self.module_infos.get(module_id).unwrap();
+        let file_path = Path::new(module_info.file_path.as_str());
+
+        let library_path = self.libraries.get(module_info.library_id).and_then(|library| {
+            let components = file_path
+                .strip_prefix(library.root_dir())
+                .ok()?
+                .with_extension("")
+                .iter()
+                .map(|component| ustr(component.to_str().unwrap()))
+                .collect();
+
+            Some(ModulePath::new(library.clone(), components))
+        });
+
+        // Modules outside of a library's root, such as the ones found in include paths, are their own library
+        library_path.unwrap_or_else(|| {
+            Library {
+                id: LibraryId::unknown(),
+                name: ustr("tmp"),
+                root_file: file_path.to_path_buf(),
+                root_module_id: ModuleId::unknown(),
+                is_main: false,
+            }
+            .as_module_path()
+        })
+    }
+
     pub fn library_map(&self) -> UstrMap<Library> {
         let mut map = UstrMap::default();
 